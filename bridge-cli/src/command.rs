@@ -0,0 +1,604 @@
+use borsh::BorshDeserialize;
+use serde_json::json;
+use sisu_bridge::state::{
+    ActivateSpenderData, AddSpenderData, AdminOperation, ChangeAdminData, DecommissionData, Role,
+    SendMessageData, SetCpiCallerData, SetRoleData, SetTimelockData, SpenderOverlap, TokenConfig,
+    TransferOutData, TransferOutNativeData, NATIVE_SOL_MINT,
+};
+use sisu_bridge_client::{
+    instruction::{self, InboundRelease, InboundTransfer},
+    BridgeBackend, BridgeClient, ClientError, OfflineTransaction,
+};
+use solana_client::{
+    nonblocking::rpc_client::RpcClient, rpc_config::RpcProgramAccountsConfig,
+    rpc_filter::RpcFilterType,
+};
+use solana_program::{instruction::Instruction, program_pack::Pack, pubkey::Pubkey};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+use spl_associated_token_account::{
+    get_associated_token_address, instruction::create_associated_token_account,
+};
+
+use crate::{output::Output, Asset, Command, SignerSignature, Transfer};
+
+type Client = BridgeClient<RpcClient>;
+
+pub async fn run(
+    client: &mut Client,
+    keypair: &Keypair,
+    command: Command,
+    output: &mut Output,
+) -> Result<(), ClientError> {
+    let program_id = *client.program_id();
+    let signer = keypair.pubkey();
+
+    match command {
+        Command::Init => {
+            let ix = instruction::initialize(&program_id, &signer);
+            submit(client, keypair, &[ix], output).await?;
+            output.field("bridge", client.bridge_pda().to_string());
+        }
+        Command::Migrate => {
+            let ix = instruction::migrate(&program_id, &signer);
+            submit(client, keypair, &[ix], output).await?;
+        }
+        Command::ShowState => show_state(client, output).await?,
+        Command::AddSpender { spender, queue } => {
+            let ix = match queue {
+                Some(id) => instruction::queue_operation(
+                    &program_id,
+                    &signer,
+                    id,
+                    AdminOperation::ActivateSpender(ActivateSpenderData {
+                        spender,
+                        overlap: SpenderOverlap::Slots(0),
+                    }),
+                ),
+                None => instruction::add_spender(&program_id, &signer, &spender),
+            };
+            submit(client, keypair, &[ix], output).await?;
+        }
+        Command::ActivateSpender {
+            spender,
+            overlap_slots,
+            overlap_seconds,
+            queue,
+        } => {
+            let overlap = match overlap_seconds {
+                Some(seconds) => SpenderOverlap::Seconds(seconds),
+                None => SpenderOverlap::Slots(overlap_slots.unwrap_or(0)),
+            };
+            let operation =
+                AdminOperation::ActivateSpender(ActivateSpenderData { spender, overlap });
+            let ix = admin_operation_ix(&program_id, &signer, operation, queue);
+            submit(client, keypair, &[ix], output).await?;
+        }
+        Command::RemoveSpender { spender, queue } => {
+            let operation = AdminOperation::RemoveSpender(AddSpenderData { spender });
+            let ix = admin_operation_ix(&program_id, &signer, operation, queue);
+            submit(client, keypair, &[ix], output).await?;
+        }
+        Command::ChangeAdmin { new_admin, queue } => {
+            let operation = AdminOperation::ChangeAdmin(ChangeAdminData { new_admin });
+            let ix = admin_operation_ix(&program_id, &signer, operation, queue);
+            submit(client, keypair, &[ix], output).await?;
+        }
+        Command::SetTimelock {
+            guardian,
+            delay,
+            queue,
+        } => {
+            let operation = AdminOperation::SetTimelock(SetTimelockData { guardian, delay });
+            let ix = admin_operation_ix(&program_id, &signer, operation, queue);
+            submit(client, keypair, &[ix], output).await?;
+        }
+        Command::SetCpiCaller {
+            program,
+            revoke,
+            queue,
+        } => {
+            let operation = AdminOperation::SetCpiCaller(SetCpiCallerData {
+                program,
+                allowed: !revoke,
+            });
+            let ix = admin_operation_ix(&program_id, &signer, operation, queue);
+            submit(client, keypair, &[ix], output).await?;
+        }
+        Command::SetRole {
+            holder,
+            role,
+            revoke,
+            queue,
+        } => {
+            let operation = AdminOperation::SetRole(SetRoleData {
+                holder,
+                role: role.into(),
+                granted: !revoke,
+            });
+            let ix = admin_operation_ix(&program_id, &signer, operation, queue);
+            submit(client, keypair, &[ix], output).await?;
+        }
+        Command::ExecuteOperation { id } => {
+            let ix = instruction::execute_operation(&program_id, &signer, id);
+            submit(client, keypair, &[ix], output).await?;
+        }
+        Command::CancelOperation { id } => {
+            let ix = instruction::cancel_operation(&program_id, &signer, id);
+            submit(client, keypair, &[ix], output).await?;
+        }
+        Command::ShowOperation { id } => {
+            let pending = client.get_pending_operation(id).await?;
+            output.fields(json!(pending));
+        }
+        Command::Pause => {
+            let ix = instruction::set_paused(&program_id, &signer, true);
+            submit(client, keypair, &[ix], output).await?;
+        }
+        Command::Unpause => {
+            let ix = instruction::set_paused(&program_id, &signer, false);
+            submit(client, keypair, &[ix], output).await?;
+        }
+        Command::RegisterToken { mint, disable } => {
+            let mut instructions = vec![];
+            let vault = match mint {
+                Asset::Sol => instruction::native_vault(&program_id).0,
+                Asset::Token(mint) => {
+                    let bridge_ata = instruction::bridge_ata(&program_id, &mint);
+                    if BridgeBackend::get_account(client.backend(), bridge_ata)
+                        .await?
+                        .is_none()
+                    {
+                        instructions.push(create_associated_token_account(
+                            &signer,
+                            &client.bridge_pda(),
+                            &mint,
+                            &spl_token::id(),
+                        ));
+                    }
+                    bridge_ata
+                }
+            };
+            instructions.push(instruction::register_token(
+                &program_id,
+                &signer,
+                &mint.mint(),
+                !disable,
+            ));
+            submit(client, keypair, &instructions, output).await?;
+            output.field("vault", vault.to_string());
+        }
+        Command::Decommission { treasury, queue } => {
+            let operation = AdminOperation::Decommission(DecommissionData { treasury });
+            let ix = admin_operation_ix(&program_id, &signer, operation, queue);
+            submit(client, keypair, &[ix], output).await?;
+        }
+        Command::SweepVault { mint, vault } => {
+            let treasury = client.get_state().await?.treasury;
+            let treasury_ata = get_associated_token_address(&treasury, &mint);
+            let mut instructions = vec![];
+            if BridgeBackend::get_account(client.backend(), treasury_ata)
+                .await?
+                .is_none()
+            {
+                instructions.push(create_associated_token_account(
+                    &signer,
+                    &treasury,
+                    &mint,
+                    &spl_token::id(),
+                ));
+            }
+            let vault = vault.unwrap_or_else(|| instruction::bridge_ata(&program_id, &mint));
+            instructions.push(instruction::sweep_token_account(
+                &program_id,
+                &signer,
+                &mint,
+                &vault,
+                &treasury,
+            ));
+            submit(client, keypair, &instructions, output).await?;
+            output.field("treasury", treasury_ata.to_string());
+        }
+        Command::CloseBridge => {
+            let treasury = client.get_state().await?.treasury;
+            let ix = instruction::close_bridge(&program_id, &signer, &treasury);
+            submit(client, keypair, &[ix], output).await?;
+        }
+        Command::Reconcile { mint } => {
+            let ix = instruction::reconcile(&program_id, &signer, &mint.mint());
+            submit(client, keypair, &[ix], output).await?;
+        }
+        Command::TransferOut {
+            mint,
+            amount,
+            chain_id,
+            recipient,
+            token_address,
+            payload,
+        } => {
+            let sequence = client.get_state().await?.out_sequence;
+            let instructions = match mint {
+                Asset::Sol => vec![instruction::transfer_out_native(
+                    &program_id,
+                    &signer,
+                    sequence,
+                    &TransferOutNativeData {
+                        amount,
+                        chain_id,
+                        recipient,
+                    },
+                )],
+                Asset::Token(mint) => {
+                    // The bridge pda moves the tokens as a delegate of the user account.
+                    let user_ata = get_associated_token_address(&signer, &mint);
+                    let approve = spl_token::instruction::approve(
+                        &spl_token::id(),
+                        &user_ata,
+                        &client.bridge_pda(),
+                        &signer,
+                        &[],
+                        amount,
+                    )
+                    .map_err(|e| ClientError::Backend(e.to_string()))?;
+                    let data = TransferOutData {
+                        amount,
+                        token_address: token_address.unwrap_or_else(|| mint.to_string()),
+                        chain_id,
+                        recipient,
+                    };
+                    let transfer_out = match payload {
+                        Some(payload) => instruction::transfer_out_with_payload(
+                            &program_id,
+                            &signer,
+                            &mint,
+                            sequence,
+                            &data,
+                            payload.0,
+                        ),
+                        None => {
+                            instruction::transfer_out(&program_id, &signer, &mint, sequence, &data)
+                        }
+                    };
+                    vec![approve, transfer_out]
+                }
+            };
+            submit(client, keypair, &instructions, output).await?;
+            output.field("sequence", sequence);
+        }
+        Command::SendMessage {
+            chain_id,
+            target,
+            payload,
+        } => {
+            let sequence = client.get_state().await?.message_sequence;
+            let data = SendMessageData {
+                chain_id,
+                target,
+                payload: payload.0,
+            };
+            let ix = instruction::send_message(&program_id, &signer, &data);
+            submit(client, keypair, &[ix], output).await?;
+            output.field("message_sequence", sequence);
+        }
+        Command::TransferIn {
+            nonce,
+            transfers,
+            nonce_account: None,
+            ..
+        } => {
+            let instructions = transfer_in_instructions(&program_id, &signer, nonce, &transfers);
+            submit(client, keypair, &instructions, output).await?;
+        }
+        Command::TransferIn {
+            nonce,
+            transfers,
+            nonce_account: Some(nonce_account),
+            spender,
+        } => {
+            let spender = spender.unwrap_or(signer);
+            let instructions = transfer_in_instructions(&program_id, &spender, nonce, &transfers);
+            let transaction = client
+                .offline_transaction(&instructions, &signer, &nonce_account)
+                .await?;
+            output.field("message", base64::encode(transaction.message_bytes()));
+            output.field(
+                "signers",
+                transaction
+                    .signers()
+                    .iter()
+                    .map(|key| key.to_string())
+                    .collect::<Vec<_>>(),
+            );
+        }
+        Command::SubmitSigned {
+            message,
+            signatures,
+        } => {
+            let message = base64::decode(&message)
+                .map_err(|e| ClientError::Signing(format!("invalid message: {}", e)))?;
+            let mut transaction = OfflineTransaction::from_message_bytes(&message)?;
+            for SignerSignature { signer, signature } in signatures {
+                transaction.add_signature(&signer, signature)?;
+            }
+            if transaction.missing_signers().contains(&signer) {
+                transaction.sign(keypair)?;
+            }
+            submit_offline(client, transaction, output).await?;
+        }
+        Command::VaultBalances => vault_balances(client, output).await?,
+    }
+
+    Ok(())
+}
+
+/// Applies an admin change right away, or queues it when `queue` has an id.
+fn admin_operation_ix(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    operation: AdminOperation,
+    queue: Option<u64>,
+) -> Instruction {
+    match queue {
+        Some(id) => instruction::queue_operation(program_id, admin, id, operation),
+        None => instruction::admin_operation(program_id, admin, operation),
+    }
+}
+
+/// Signs and sends `instructions`, or only simulates them with `--dry-run`.
+async fn submit(
+    client: &mut Client,
+    keypair: &Keypair,
+    instructions: &[Instruction],
+    output: &mut Output,
+) -> Result<(), ClientError> {
+    if !output.dry_run {
+        let signature = client.send(instructions, keypair).await?;
+        output.field("signature", signature.to_string());
+        return Ok(());
+    }
+
+    let recent_blockhash = client.backend().get_latest_blockhash().await?;
+    let transaction = Transaction::new_signed_with_payer(
+        &client.with_compute_budget(instructions),
+        Some(&keypair.pubkey()),
+        &[keypair],
+        recent_blockhash,
+    );
+    simulate(client, &transaction, output).await
+}
+
+async fn submit_offline(
+    client: &mut Client,
+    transaction: OfflineTransaction,
+    output: &mut Output,
+) -> Result<(), ClientError> {
+    if !output.dry_run {
+        let signature = client.submit_offline(transaction).await?;
+        output.field("signature", signature.to_string());
+        return Ok(());
+    }
+
+    simulate(client, &transaction.into_transaction()?, output).await
+}
+
+async fn simulate(
+    client: &mut Client,
+    transaction: &Transaction,
+    output: &mut Output,
+) -> Result<(), ClientError> {
+    let result = client
+        .backend()
+        .simulate_transaction(transaction)
+        .await?
+        .value;
+
+    output.field(
+        "simulation",
+        match result.err {
+            Some(err) => err.to_string(),
+            None => "ok".to_string(),
+        },
+    );
+    if let Some(units) = result.units_consumed {
+        output.field("units_consumed", units);
+    }
+    output.field("logs", result.logs.unwrap_or_default());
+    Ok(())
+}
+
+/// Token and native releases are separate instructions, both go in the same transaction.
+fn transfer_in_instructions(
+    program_id: &Pubkey,
+    spender: &Pubkey,
+    nonce: u64,
+    transfers: &[Transfer],
+) -> Vec<Instruction> {
+    let mut release = InboundRelease {
+        nonce,
+        transfers: vec![],
+        native: vec![],
+    };
+    for transfer in transfers {
+        match transfer.asset {
+            Asset::Sol => release.native.push((transfer.owner, transfer.amount)),
+            Asset::Token(mint) => release.transfers.push(InboundTransfer {
+                mint,
+                receiver: get_associated_token_address(&transfer.owner, &mint),
+                amount: transfer.amount,
+            }),
+        }
+    }
+
+    instruction::transfer_in_release(program_id, spender, &release)
+}
+
+async fn show_state(client: &mut Client, output: &mut Output) -> Result<(), ClientError> {
+    let state = client.get_state().await?;
+    let spenders = state.spender_keys();
+
+    output.field("bridge", client.bridge_pda().to_string());
+    output.field("version", state.version);
+    output.field("admin", state.admin.to_string());
+    output.field("spender_epoch", state.spender_epoch);
+    output.field("spender", spenders.current().to_string());
+    output.field("previous_spender", spenders.previous().to_string());
+    output.field("previous_valid_until_slot", state.previous_valid_until_slot);
+    output.field("previous_valid_until", state.previous_valid_until);
+    output.field("guardian", state.guardian.to_string());
+    output.field("timelock_delay", state.timelock_delay);
+    let cpi_callers: Vec<String> = state
+        .cpi_callers
+        .iter()
+        .filter(|caller| **caller != Pubkey::default())
+        .map(|caller| caller.to_string())
+        .collect();
+    output.field("cpi_callers", cpi_callers);
+    let roles: Vec<_> = state
+        .role_holders
+        .iter()
+        .zip(state.role_masks)
+        .filter(|(holder, _)| **holder != Pubkey::default())
+        .map(|(holder, mask)| {
+            let roles: Vec<&Role> = Role::ALL
+                .iter()
+                .filter(|role| mask & role.bit() != 0)
+                .collect();
+            json!({"holder": holder.to_string(), "roles": roles})
+        })
+        .collect();
+    output.field("roles", roles);
+    output.field("decommissioned", state.is_decommissioned());
+    if state.is_decommissioned() {
+        output.field("treasury", state.treasury.to_string());
+    }
+    output.field("token_count", state.token_count);
+    output.field("out_sequence", state.out_sequence);
+    output.field("message_sequence", state.message_sequence);
+    output.field("paused", state.paused);
+    Ok(())
+}
+
+async fn vault_balances(client: &mut Client, output: &mut Output) -> Result<(), ClientError> {
+    let program_id = *client.program_id();
+    let rpc = client.backend();
+
+    let config = RpcProgramAccountsConfig {
+        filters: Some(vec![RpcFilterType::DataSize(TokenConfig::LEN as u64)]),
+        ..RpcProgramAccountsConfig::default()
+    };
+    let accounts = rpc
+        .get_program_accounts_with_config(&program_id, config)
+        .await?;
+
+    // Pending operations can have the size of a token config too, only the accounts at the
+    // config address of their mint are configs.
+    let (native_vault, _) = instruction::native_vault(&program_id);
+    let mut tokens = vec![];
+    for (address, account) in accounts {
+        let token_config = match TokenConfig::try_from_slice(&account.data) {
+            Ok(token_config) => token_config,
+            Err(_) => continue,
+        };
+        if address != instruction::token_config(&program_id, &token_config.mint).0 {
+            continue;
+        }
+
+        let (vault, balance) = if token_config.mint == NATIVE_SOL_MINT {
+            // The rent reserve of the native vault is not bridged funds.
+            let reserve = rpc.get_minimum_balance_for_rent_exemption(0).await?;
+            let lamports = rpc.get_balance(&native_vault).await?;
+            (native_vault, lamports.saturating_sub(reserve).to_string())
+        } else {
+            let vault = instruction::bridge_ata(&program_id, &token_config.mint);
+            let vault_account = rpc
+                .get_account_with_commitment(&vault, rpc.commitment())
+                .await?
+                .value;
+            let balance = match vault_account {
+                Some(account) => spl_token::state::Account::unpack(&account.data)
+                    .map(|token| token.amount.to_string())
+                    .unwrap_or_else(|_| "invalid".to_string()),
+                None => "missing".to_string(),
+            };
+            (vault, balance)
+        };
+        // Amounts are decimal strings, as in the canonical json of the config.
+        let mut token = json!(token_config);
+        token["outstanding"] = json!(token_config.outstanding().to_string());
+        token["vault"] = json!(vault.to_string());
+        token["balance"] = json!(balance);
+        tokens.push(token);
+    }
+    output.field("tokens", tokens);
+
+    output.field("native_vault", native_vault.to_string());
+    let native_balance = rpc.get_balance(&native_vault).await?;
+    output.field("native_balance", native_balance.to_string());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::transfer_in_instructions;
+    use crate::{Asset, Transfer};
+    use sisu_bridge::state::{BridgeInstruction, TransferInData};
+    use solana_program::pubkey::Pubkey;
+    use spl_associated_token_account::get_associated_token_address;
+
+    #[test]
+    fn test_transfer_in_instructions() {
+        let program_id = Pubkey::new_unique();
+        let spender = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let (owner, wallet) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let transfers = [
+            Transfer {
+                asset: Asset::Token(mint),
+                owner,
+                amount: 100,
+            },
+            Transfer {
+                asset: Asset::Sol,
+                owner: wallet,
+                amount: 200,
+            },
+        ];
+
+        // One token release and one native release, under the same nonce.
+        let instructions = transfer_in_instructions(&program_id, &spender, 7, &transfers);
+        assert_eq!(instructions.len(), 2);
+        let expected = TransferInData {
+            nonce: 7,
+            amounts: vec![100],
+        };
+        assert_eq!(
+            BridgeInstruction::unpack(&instructions[0].data).unwrap(),
+            BridgeInstruction::TransferIn(expected)
+        );
+        let expected = TransferInData {
+            nonce: 7,
+            amounts: vec![200],
+        };
+        assert_eq!(
+            BridgeInstruction::unpack(&instructions[1].data).unwrap(),
+            BridgeInstruction::TransferInNative(expected)
+        );
+
+        // Tokens go to the owner's associated account, lamports to the wallet itself.
+        let receiver = get_associated_token_address(&owner, &mint);
+        assert!(instructions[0]
+            .accounts
+            .iter()
+            .any(|meta| meta.pubkey == receiver));
+        assert!(instructions[1]
+            .accounts
+            .iter()
+            .any(|meta| meta.pubkey == wallet));
+        for ix in &instructions {
+            assert_eq!(ix.program_id, program_id);
+            assert!(ix.accounts[0].is_signer && ix.accounts[0].pubkey == spender);
+        }
+    }
+}
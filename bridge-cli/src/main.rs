@@ -0,0 +1,484 @@
+mod command;
+mod output;
+
+use std::{process, str::FromStr};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use sisu_bridge::state::{Role, NATIVE_SOL_MINT};
+use sisu_bridge_client::{BridgeClient, ComputeBudgetPolicy};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_program::pubkey::Pubkey;
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    signature::{read_keypair_file, Signature},
+};
+
+use crate::output::Output;
+
+/// Operates a deployment of the Sisu bridge program.
+#[derive(Parser)]
+#[clap(name = "sisu-bridge", version)]
+struct Cli {
+    /// Solana CLI config file, used for any of --url and --keypair not given
+    #[clap(long, global = true)]
+    config: Option<String>,
+
+    /// RPC endpoint of the cluster
+    #[clap(long, short = 'u', global = true)]
+    url: Option<String>,
+
+    /// Keypair that signs and pays, the admin or a spender depending on the command
+    #[clap(long, short = 'k', global = true)]
+    keypair: Option<String>,
+
+    /// Address of the bridge program
+    #[clap(long, global = true, env = "SISU_BRIDGE_PROGRAM_ID")]
+    program_id: Option<Pubkey>,
+
+    /// Simulate transactions and print their logs instead of sending them
+    #[clap(long, global = true)]
+    dry_run: bool,
+
+    /// Priority fee in micro-lamports per compute unit. Transactions also request the units
+    /// they are estimated to use.
+    #[clap(long, global = true)]
+    priority_fee: Option<u64>,
+
+    #[clap(long, value_enum, default_value = "display", global = true)]
+    output: OutputFormat,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum OutputFormat {
+    Display,
+    Json,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Creates the bridge with the keypair as admin, with no spender or guardian yet
+    Init,
+    /// Grows a bridge created by an older version of the program to the current layout
+    Migrate,
+    /// Prints the bridge state
+    ShowState,
+    /// Makes a spender current, the previous one expires after this slot
+    AddSpender {
+        spender: Pubkey,
+        /// Queue the change under this id instead, required once the bridge has a timelock
+        #[clap(long)]
+        queue: Option<u64>,
+    },
+    /// Starts a new spender epoch. The current spender keeps signing until the overlap ends,
+    /// right away by default.
+    ActivateSpender {
+        spender: Pubkey,
+        #[clap(long, conflicts_with = "overlap_seconds")]
+        overlap_slots: Option<u64>,
+        #[clap(long)]
+        overlap_seconds: Option<u64>,
+        /// Queue the change under this id instead, required once the bridge has a timelock
+        #[clap(long)]
+        queue: Option<u64>,
+    },
+    RemoveSpender {
+        spender: Pubkey,
+        /// Queue the change under this id instead, required once the bridge has a timelock
+        #[clap(long)]
+        queue: Option<u64>,
+    },
+    ChangeAdmin {
+        new_admin: Pubkey,
+        /// Queue the change under this id instead, required once the bridge has a timelock
+        #[clap(long)]
+        queue: Option<u64>,
+    },
+    /// Sets who can cancel queued changes and how long they wait before they can be executed
+    SetTimelock {
+        #[clap(long)]
+        guardian: Pubkey,
+        /// Seconds, 0 lets the admin apply changes right away
+        #[clap(long)]
+        delay: u64,
+        /// Queue the change under this id instead, required once the bridge has a timelock
+        #[clap(long)]
+        queue: Option<u64>,
+    },
+    /// Lets a program call the spender and admin instructions through CPI, or revokes it
+    SetCpiCaller {
+        #[clap(long)]
+        program: Pubkey,
+        #[clap(long)]
+        revoke: bool,
+        /// Queue the change under this id instead, required once the bridge has a timelock
+        #[clap(long)]
+        queue: Option<u64>,
+    },
+    /// Grants a role to a key, or revokes it. Relayer and guardian keys cannot hold the admin or
+    /// token registrar roles.
+    SetRole {
+        holder: Pubkey,
+        #[clap(long, value_enum)]
+        role: RoleName,
+        #[clap(long)]
+        revoke: bool,
+        /// Queue the change under this id instead, required once the bridge has a timelock
+        #[clap(long)]
+        queue: Option<u64>,
+    },
+    /// Applies a queued change whose delay has passed
+    ExecuteOperation {
+        id: u64,
+    },
+    /// Drops a queued change, as a guardian
+    CancelOperation {
+        id: u64,
+    },
+    /// Prints a queued change and when it can be executed
+    ShowOperation {
+        id: u64,
+    },
+    /// Stops all transfers in and out
+    Pause,
+    Unpause,
+    /// Creates the bridge vault of a mint if needed and registers the mint, or SOL, so that it
+    /// can be bridged out
+    RegisterToken {
+        mint: Asset,
+        /// Forbid outbound transfers of the mint instead
+        #[clap(long)]
+        disable: bool,
+    },
+    /// Pauses the bridge for good, before sweeping its vaults to the treasury
+    Decommission {
+        /// Wallet that receives the tokens, SOL and rent
+        #[clap(long)]
+        treasury: Pubkey,
+        /// Queue the change under this id instead, required once the bridge has a timelock
+        #[clap(long)]
+        queue: Option<u64>,
+    },
+    /// Moves a vault's balance to the treasury and closes the vault
+    SweepVault {
+        #[clap(long)]
+        mint: Pubkey,
+        /// Token account of the bridge to sweep, if not the vault of the mint
+        #[clap(long)]
+        vault: Option<Pubkey>,
+    },
+    /// Sweeps the SOL vault and closes the bridge, once every vault is swept
+    CloseBridge,
+    /// Compares a vault with the amounts locked and released for its mint
+    Reconcile {
+        /// Mint of the token, or SOL
+        #[clap(long)]
+        mint: Asset,
+    },
+    /// Locks tokens, or SOL, from the keypair's wallet for the destination chain
+    TransferOut {
+        /// Mint of the token, or SOL
+        #[clap(long)]
+        mint: Asset,
+        /// Amount in base units
+        #[clap(long)]
+        amount: u64,
+        #[clap(long)]
+        chain_id: u64,
+        #[clap(long)]
+        recipient: String,
+        /// Token address on the destination chain, the mint by default
+        #[clap(long)]
+        token_address: Option<String>,
+        /// Hex payload for the recipient contract, token transfers only
+        #[clap(long)]
+        payload: Option<Payload>,
+    },
+    /// Sends a message to a contract on the destination chain
+    SendMessage {
+        #[clap(long)]
+        chain_id: u64,
+        /// Contract address on the destination chain
+        #[clap(long)]
+        target: String,
+        /// Hex encoded
+        #[clap(long)]
+        payload: Payload,
+    },
+    /// Releases tokens as a spender
+    TransferIn {
+        #[clap(long)]
+        nonce: u64,
+        /// MINT:OWNER:AMOUNT, with SOL as mint for native transfers. Tokens go to the
+        /// associated token account of OWNER.
+        #[clap(long = "transfer", required = true)]
+        transfers: Vec<Transfer>,
+        /// Build the transaction against this durable nonce account and print its message
+        /// for offline signing instead of sending it. The keypair pays the fee.
+        #[clap(long)]
+        nonce_account: Option<Pubkey>,
+        /// Spender that signs offline, the keypair by default
+        #[clap(long, requires = "nonce_account")]
+        spender: Option<Pubkey>,
+    },
+    /// Sends a transaction built with --nonce-account once it is signed. The keypair adds its
+    /// own signature if it is one of the signers.
+    SubmitSigned {
+        /// Base64 message printed when the transaction was built
+        #[clap(long)]
+        message: String,
+        /// PUBKEY=SIGNATURE, both base58
+        #[clap(long = "signature")]
+        signatures: Vec<SignerSignature>,
+    },
+    /// Lists the registered tokens with their vault balances
+    VaultBalances,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum RoleName {
+    Admin,
+    Guardian,
+    TokenRegistrar,
+    Relayer,
+}
+
+impl From<RoleName> for Role {
+    fn from(name: RoleName) -> Self {
+        match name {
+            RoleName::Admin => Role::Admin,
+            RoleName::Guardian => Role::Guardian,
+            RoleName::TokenRegistrar => Role::TokenRegistrar,
+            RoleName::Relayer => Role::Relayer,
+        }
+    }
+}
+
+/// A mint, or native SOL.
+#[derive(Clone, Copy, Debug)]
+pub enum Asset {
+    Sol,
+    Token(Pubkey),
+}
+
+impl Asset {
+    /// The mint the bridge records the asset under.
+    pub fn mint(&self) -> Pubkey {
+        match self {
+            Asset::Sol => NATIVE_SOL_MINT,
+            Asset::Token(mint) => *mint,
+        }
+    }
+}
+
+impl FromStr for Asset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("sol") {
+            return Ok(Asset::Sol);
+        }
+        s.parse()
+            .map(Asset::Token)
+            .map_err(|_| format!("invalid mint {}", s))
+    }
+}
+
+/// Hex encoded bytes, with or without a 0x prefix.
+#[derive(Clone, Debug)]
+pub struct Payload(pub Vec<u8>);
+
+impl FromStr for Payload {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.strip_prefix("0x").unwrap_or(s);
+        hex::decode(s)
+            .map(Payload)
+            .map_err(|e| format!("invalid hex payload: {}", e))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Transfer {
+    pub asset: Asset,
+    pub owner: Pubkey,
+    pub amount: u64,
+}
+
+impl FromStr for Transfer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+        if parts.len() != 3 {
+            return Err(format!("expected MINT:OWNER:AMOUNT, got {}", s));
+        }
+
+        Ok(Transfer {
+            asset: parts[0].parse()?,
+            owner: parts[1]
+                .parse()
+                .map_err(|_| format!("invalid owner {}", parts[1]))?,
+            amount: parts[2]
+                .parse()
+                .map_err(|_| format!("invalid amount {}", parts[2]))?,
+        })
+    }
+}
+
+/// A signature made offline and the key that made it.
+#[derive(Clone, Debug)]
+pub struct SignerSignature {
+    pub signer: Pubkey,
+    pub signature: Signature,
+}
+
+impl FromStr for SignerSignature {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (signer, signature) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected PUBKEY=SIGNATURE, got {}", s))?;
+
+        Ok(SignerSignature {
+            signer: signer
+                .parse()
+                .map_err(|_| format!("invalid pubkey {}", signer))?,
+            signature: signature
+                .parse()
+                .map_err(|_| format!("invalid signature {}", signature))?,
+        })
+    }
+}
+
+fn load_config(cli: &Cli) -> solana_cli_config::Config {
+    let path = match (&cli.config, solana_cli_config::CONFIG_FILE.as_ref()) {
+        (Some(path), _) => path,
+        (None, Some(path)) => path,
+        (None, None) => return solana_cli_config::Config::default(),
+    };
+    match solana_cli_config::Config::load(path) {
+        Ok(config) => config,
+        Err(_) if cli.config.is_none() => solana_cli_config::Config::default(),
+        Err(e) => exit(format!("cannot load config {}: {}", path, e)),
+    }
+}
+
+fn exit(message: String) -> ! {
+    eprintln!("error: {}", message);
+    process::exit(1);
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    let config = load_config(&cli);
+
+    let url = cli.url.clone().unwrap_or(config.json_rpc_url);
+    let keypair_path = cli.keypair.clone().unwrap_or(config.keypair_path);
+    let keypair = read_keypair_file(&keypair_path)
+        .unwrap_or_else(|e| exit(format!("cannot read keypair {}: {}", keypair_path, e)));
+    let program_id = cli
+        .program_id
+        .unwrap_or_else(|| exit("--program-id is required".to_string()));
+    if let Command::TransferOut {
+        mint: Asset::Sol,
+        payload: Some(_),
+        ..
+    } = cli.command
+    {
+        exit("--payload is not supported for SOL transfers".to_string());
+    }
+
+    let rpc = RpcClient::new_with_commitment(url, CommitmentConfig::confirmed());
+    let mut client = BridgeClient::new(rpc, program_id);
+    if let Some(unit_price) = cli.priority_fee {
+        client.set_compute_budget(Some(ComputeBudgetPolicy {
+            unit_price,
+            ..ComputeBudgetPolicy::default()
+        }));
+    }
+    let mut output = Output::new(cli.output, cli.dry_run);
+
+    if let Err(e) = command::run(&mut client, &keypair, cli.command, &mut output).await {
+        exit(e.to_string());
+    }
+    output.flush();
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Asset, Payload, SignerSignature, Transfer};
+    use sisu_bridge::state::NATIVE_SOL_MINT;
+    use solana_program::pubkey::Pubkey;
+    use solana_sdk::signature::Signature;
+
+    #[test]
+    fn test_parse_asset() {
+        assert!(matches!("sol".parse(), Ok(Asset::Sol)));
+        assert!(matches!("SOL".parse(), Ok(Asset::Sol)));
+        assert_eq!("SOL".parse::<Asset>().unwrap().mint(), NATIVE_SOL_MINT);
+
+        let mint = Pubkey::new_unique();
+        let asset: Asset = mint.to_string().parse().unwrap();
+        assert!(matches!(asset, Asset::Token(parsed) if parsed == mint));
+        assert_eq!(asset.mint(), mint);
+
+        assert!("not-a-mint".parse::<Asset>().is_err());
+    }
+
+    #[test]
+    fn test_parse_payload() {
+        assert_eq!("0x0aff".parse::<Payload>().unwrap().0, vec![0x0a, 0xff]);
+        assert_eq!("0aff".parse::<Payload>().unwrap().0, vec![0x0a, 0xff]);
+        assert!("0x".parse::<Payload>().unwrap().0.is_empty());
+
+        assert!("0xabc".parse::<Payload>().is_err());
+        assert!("0xzz".parse::<Payload>().is_err());
+    }
+
+    #[test]
+    fn test_parse_transfer() {
+        let mint = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+
+        let transfer: Transfer = format!("{}:{}:1000", mint, owner).parse().unwrap();
+        assert!(matches!(transfer.asset, Asset::Token(parsed) if parsed == mint));
+        assert_eq!((transfer.owner, transfer.amount), (owner, 1000));
+
+        let transfer: Transfer = format!("sol:{}:{}", owner, u64::MAX).parse().unwrap();
+        assert!(matches!(transfer.asset, Asset::Sol));
+        assert_eq!(transfer.amount, u64::MAX);
+
+        for invalid in [
+            format!("{}:{}", mint, owner),
+            format!("{}:{}:1:2", mint, owner),
+            format!("{}:not-an-owner:1", mint),
+            format!("{}:{}:-1", mint, owner),
+            format!("not-a-mint:{}:1", owner),
+        ] {
+            assert!(invalid.parse::<Transfer>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_parse_signer_signature() {
+        let signer = Pubkey::new_unique();
+        let signature = Signature::new(&[7; 64]);
+
+        let parsed: SignerSignature = format!("{}={}", signer, signature).parse().unwrap();
+        assert_eq!((parsed.signer, parsed.signature), (signer, signature));
+
+        assert!(signer.to_string().parse::<SignerSignature>().is_err());
+        assert!(format!("{}=not-a-signature", signer)
+            .parse::<SignerSignature>()
+            .is_err());
+        assert!(format!("not-a-pubkey={}", signature)
+            .parse::<SignerSignature>()
+            .is_err());
+    }
+}
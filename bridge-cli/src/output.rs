@@ -0,0 +1,68 @@
+use serde_json::{Map, Value};
+
+use crate::OutputFormat;
+
+/// Collects the result of a command. Display output is printed as it comes, json output is
+/// printed as a single object by `flush`.
+pub struct Output {
+    format: OutputFormat,
+    pub dry_run: bool,
+    fields: Map<String, Value>,
+}
+
+impl Output {
+    pub fn new(format: OutputFormat, dry_run: bool) -> Self {
+        Output {
+            format,
+            dry_run,
+            fields: Map::new(),
+        }
+    }
+
+    pub fn field<V: Into<Value>>(&mut self, key: &str, value: V) {
+        let value = value.into();
+        match self.format {
+            OutputFormat::Display => match &value {
+                Value::String(s) => println!("{}: {}", key, s),
+                Value::Array(items) => {
+                    println!("{}:", key);
+                    for item in items {
+                        println!("  {}", display_value(item));
+                    }
+                }
+                _ => println!("{}: {}", key, value),
+            },
+            OutputFormat::Json => {
+                self.fields.insert(key.to_string(), value);
+            }
+        }
+    }
+
+    /// Each field of an object, such as a bridge type in its canonical json form.
+    pub fn fields(&mut self, value: Value) {
+        if let Value::Object(fields) = value {
+            for (key, value) in fields {
+                self.field(&key, value);
+            }
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if let OutputFormat::Json = self.format {
+            let fields = std::mem::take(&mut self.fields);
+            println!("{}", Value::Object(fields));
+        }
+    }
+}
+
+fn display_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Object(fields) => fields
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, display_value(value)))
+            .collect::<Vec<_>>()
+            .join(" "),
+        _ => value.to_string(),
+    }
+}
@@ -0,0 +1,158 @@
+use async_trait::async_trait;
+use solana_banks_client::BanksClient;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    account::Account,
+    hash::Hash,
+    pubkey::Pubkey,
+    signature::Signature,
+    system_instruction::SystemInstruction,
+    system_program,
+    transaction::{Transaction, TransactionError},
+};
+
+use crate::error::ClientError;
+
+/// The few cluster operations the bridge client needs. `BanksClient` implements it so that
+/// everything can be exercised offline under `solana-program-test`, and `RpcClient` for real
+/// clusters.
+#[async_trait]
+pub trait BridgeBackend {
+    async fn get_account(&mut self, address: Pubkey) -> Result<Option<Account>, ClientError>;
+
+    async fn get_latest_blockhash(&mut self) -> Result<Hash, ClientError>;
+
+    async fn get_minimum_balance_for_rent_exemption(
+        &mut self,
+        data_len: usize,
+    ) -> Result<u64, ClientError>;
+
+    /// Sends a signed transaction and waits until it is processed.
+    async fn send_transaction(
+        &mut self,
+        transaction: Transaction,
+    ) -> Result<Signature, ClientError>;
+
+    /// Simulates a signed transaction and returns the program and the data it set with
+    /// `set_return_data`, if any.
+    async fn simulate_return_data(
+        &mut self,
+        transaction: Transaction,
+    ) -> Result<Option<(Pubkey, Vec<u8>)>, ClientError>;
+}
+
+#[async_trait]
+impl BridgeBackend for BanksClient {
+    async fn get_account(&mut self, address: Pubkey) -> Result<Option<Account>, ClientError> {
+        Ok(BanksClient::get_account(self, address).await?)
+    }
+
+    async fn get_latest_blockhash(&mut self) -> Result<Hash, ClientError> {
+        Ok(BanksClient::get_latest_blockhash(self).await?)
+    }
+
+    async fn get_minimum_balance_for_rent_exemption(
+        &mut self,
+        data_len: usize,
+    ) -> Result<u64, ClientError> {
+        Ok(self.get_rent().await?.minimum_balance(data_len))
+    }
+
+    async fn send_transaction(
+        &mut self,
+        transaction: Transaction,
+    ) -> Result<Signature, ClientError> {
+        let signature = transaction.signatures[0];
+        // The banks server waits on the blockhash of a transaction among the recent ones, which
+        // a durable nonce is not. Those are processed without waiting instead.
+        if uses_durable_nonce(&transaction) {
+            self.process_transaction_with_metadata(transaction)
+                .await?
+                .result
+                .map_err(|e: TransactionError| ClientError::Backend(e.to_string()))?;
+            return Ok(signature);
+        }
+        self.process_transaction(transaction).await?;
+
+        Ok(signature)
+    }
+
+    async fn simulate_return_data(
+        &mut self,
+        transaction: Transaction,
+    ) -> Result<Option<(Pubkey, Vec<u8>)>, ClientError> {
+        let simulation = self.simulate_transaction(transaction).await?;
+        if let Some(Err(err)) = simulation.result {
+            return Err(ClientError::Simulation(err.to_string()));
+        }
+
+        Ok(simulation
+            .simulation_details
+            .and_then(|details| details.return_data)
+            .map(|return_data| (return_data.program_id, return_data.data)))
+    }
+}
+
+#[async_trait]
+impl BridgeBackend for RpcClient {
+    async fn get_account(&mut self, address: Pubkey) -> Result<Option<Account>, ClientError> {
+        let response = self
+            .get_account_with_commitment(&address, self.commitment())
+            .await?;
+
+        Ok(response.value)
+    }
+
+    async fn get_latest_blockhash(&mut self) -> Result<Hash, ClientError> {
+        Ok(RpcClient::get_latest_blockhash(self).await?)
+    }
+
+    async fn get_minimum_balance_for_rent_exemption(
+        &mut self,
+        data_len: usize,
+    ) -> Result<u64, ClientError> {
+        Ok(RpcClient::get_minimum_balance_for_rent_exemption(self, data_len).await?)
+    }
+
+    async fn send_transaction(
+        &mut self,
+        transaction: Transaction,
+    ) -> Result<Signature, ClientError> {
+        Ok(self.send_and_confirm_transaction(&transaction).await?)
+    }
+
+    async fn simulate_return_data(
+        &mut self,
+        transaction: Transaction,
+    ) -> Result<Option<(Pubkey, Vec<u8>)>, ClientError> {
+        let simulation = self.simulate_transaction(&transaction).await?.value;
+        if let Some(err) = simulation.err {
+            return Err(ClientError::Simulation(err.to_string()));
+        }
+
+        let return_data = match simulation.return_data {
+            Some(return_data) => return_data,
+            None => return Ok(None),
+        };
+        let program_id = return_data
+            .program_id
+            .parse()
+            .map_err(|_| ClientError::ReturnData("program id is not a pubkey".to_string()))?;
+        let data = base64::decode(&return_data.data.0)
+            .map_err(|e| ClientError::ReturnData(e.to_string()))?;
+
+        Ok(Some((program_id, data)))
+    }
+}
+
+/// Whether the transaction starts by advancing a nonce account, see `OfflineTransaction`.
+fn uses_durable_nonce(transaction: &Transaction) -> bool {
+    let message = &transaction.message;
+    message.instructions.first().map_or(false, |ix| {
+        message.account_keys.get(ix.program_id_index as usize) == Some(&system_program::id())
+            && matches!(
+                bincode::deserialize(&ix.data),
+                Ok(SystemInstruction::AdvanceNonceAccount)
+            )
+    })
+}
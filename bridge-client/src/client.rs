@@ -0,0 +1,597 @@
+use borsh::BorshDeserialize;
+use sisu_bridge::state::{
+    AdminOperation, BridgeQuery, BridgeReturnData, BridgeStateV0, DeliverMessageData,
+    DeliveryRecord, PendingOperation, Role, SendMessageData, SpenderOverlap, TokenConfig,
+    TransferOutData, TransferOutNativeData, TransferReceipt,
+};
+use solana_client::nonce_utils;
+use solana_program::{
+    hash::Hash,
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+use solana_sdk::{
+    nonce,
+    signature::{Keypair, Signature, Signer},
+    system_instruction,
+    transaction::Transaction,
+};
+
+use crate::backend::BridgeBackend;
+use crate::compute_budget::{self, ComputeBudgetPolicy};
+use crate::error::ClientError;
+use crate::instruction::{self, InboundRelease, InboundTransfer};
+use crate::offline::OfflineTransaction;
+
+/// The spender key set in force, see `BridgeClient::get_spender_epoch`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpenderEpoch {
+    pub epoch: u64,
+    pub current: Pubkey,
+    pub previous: Pubkey,
+    /// The previous spender may sign up to this slot and this unix timestamp.
+    pub previous_valid_until_slot: u64,
+    pub previous_valid_until: i64,
+}
+
+/// Typed access to one deployment of the bridge program.
+pub struct BridgeClient<B: BridgeBackend> {
+    backend: B,
+    program_id: Pubkey,
+    compute_budget: Option<ComputeBudgetPolicy>,
+}
+
+impl<B: BridgeBackend> BridgeClient<B> {
+    pub fn new(backend: B, program_id: Pubkey) -> Self {
+        BridgeClient {
+            backend,
+            program_id,
+            compute_budget: None,
+        }
+    }
+
+    /// Sets the compute unit limit and priority fee of every transaction the client sends or
+    /// builds. Without a policy they get the runtime defaults.
+    pub fn set_compute_budget(&mut self, policy: Option<ComputeBudgetPolicy>) {
+        self.compute_budget = policy;
+    }
+
+    /// `instructions` with the compute budget instructions of the policy, if any.
+    pub fn with_compute_budget(&self, instructions: &[Instruction]) -> Vec<Instruction> {
+        match &self.compute_budget {
+            Some(policy) => policy.apply(&self.program_id, instructions),
+            None => instructions.to_vec(),
+        }
+    }
+
+    pub fn program_id(&self) -> &Pubkey {
+        &self.program_id
+    }
+
+    pub fn bridge_pda(&self) -> Pubkey {
+        instruction::bridge_pda(&self.program_id).0
+    }
+
+    pub fn backend(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
+    pub async fn get_state(&mut self) -> Result<BridgeStateV0, ClientError> {
+        self.get_decoded(self.bridge_pda()).await
+    }
+
+    pub async fn get_spender_epoch(&mut self) -> Result<SpenderEpoch, ClientError> {
+        let state = self.get_state().await?;
+        let keys = state.spender_keys();
+
+        Ok(SpenderEpoch {
+            epoch: state.spender_epoch,
+            current: *keys.current(),
+            previous: *keys.previous(),
+            previous_valid_until_slot: state.previous_valid_until_slot,
+            previous_valid_until: state.previous_valid_until,
+        })
+    }
+
+    pub async fn get_receipt(&mut self, sequence: u64) -> Result<TransferReceipt, ClientError> {
+        let (receipt, _) = instruction::receipt_address(&self.program_id, sequence);
+        self.get_decoded(receipt).await
+    }
+
+    /// The delivery record of an inbound message, `AccountNotFound` until it is executed.
+    pub async fn get_delivery_record(
+        &mut self,
+        source_chain_id: u64,
+        nonce: u64,
+    ) -> Result<DeliveryRecord, ClientError> {
+        let (record, _) = instruction::delivery_record(&self.program_id, source_chain_id, nonce);
+        self.get_decoded(record).await
+    }
+
+    async fn get_decoded<T: BorshDeserialize>(
+        &mut self,
+        address: Pubkey,
+    ) -> Result<T, ClientError> {
+        let account = self
+            .backend
+            .get_account(address)
+            .await?
+            .ok_or(ClientError::AccountNotFound(address))?;
+
+        Ok(T::try_from_slice(&account.data)?)
+    }
+
+    /// Simulates a query and decodes the answer, so that nothing depends on the account
+    /// layouts. `payer` signs the simulated transaction and is not charged.
+    pub async fn query(
+        &mut self,
+        payer: &Keypair,
+        query: BridgeQuery,
+        mint: Option<&Pubkey>,
+    ) -> Result<BridgeReturnData, ClientError> {
+        let ix = instruction::query(&self.program_id, query, mint);
+        self.simulate_return_data(&[ix], payer).await
+    }
+
+    /// Simulates `instructions` and decodes what the bridge returned.
+    pub async fn simulate_return_data(
+        &mut self,
+        instructions: &[Instruction],
+        signer: &Keypair,
+    ) -> Result<BridgeReturnData, ClientError> {
+        let recent_blockhash = self.backend.get_latest_blockhash().await?;
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&signer.pubkey()),
+            &[signer],
+            recent_blockhash,
+        );
+
+        match self.backend.simulate_return_data(transaction).await? {
+            Some((program_id, data)) if program_id == self.program_id => {
+                BridgeReturnData::unpack(&data).map_err(|e| ClientError::ReturnData(e.to_string()))
+            }
+            Some((program_id, _)) => Err(ClientError::ReturnData(format!(
+                "returned by {}",
+                program_id
+            ))),
+            None => Err(ClientError::ReturnData("nothing returned".to_string())),
+        }
+    }
+
+    /// Signs `instructions` with `signer`, who also pays the fee, and sends them.
+    pub async fn send(
+        &mut self,
+        instructions: &[Instruction],
+        signer: &Keypair,
+    ) -> Result<Signature, ClientError> {
+        let recent_blockhash = self.backend.get_latest_blockhash().await?;
+        let transaction = Transaction::new_signed_with_payer(
+            &self.with_compute_budget(instructions),
+            Some(&signer.pubkey()),
+            &[signer],
+            recent_blockhash,
+        );
+
+        self.backend.send_transaction(transaction).await
+    }
+
+    /// The authority and current value of a durable nonce account.
+    pub async fn get_nonce(
+        &mut self,
+        nonce_account: &Pubkey,
+    ) -> Result<(Pubkey, Hash), ClientError> {
+        let account = self
+            .backend
+            .get_account(*nonce_account)
+            .await?
+            .ok_or(ClientError::AccountNotFound(*nonce_account))?;
+        let data = nonce_utils::data_from_account(&account)
+            .map_err(|e| ClientError::Nonce(e.to_string()))?;
+
+        Ok((data.authority, data.blockhash()))
+    }
+
+    /// Creates a durable nonce account for offline transactions, paid by `payer`.
+    pub async fn create_nonce_account(
+        &mut self,
+        payer: &Keypair,
+        nonce_account: &Keypair,
+        authority: &Pubkey,
+    ) -> Result<Signature, ClientError> {
+        let lamports = self
+            .backend
+            .get_minimum_balance_for_rent_exemption(nonce::State::size())
+            .await?;
+        let instructions = system_instruction::create_nonce_account(
+            &payer.pubkey(),
+            &nonce_account.pubkey(),
+            authority,
+            lamports,
+        );
+        let recent_blockhash = self.backend.get_latest_blockhash().await?;
+        let transaction = Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&payer.pubkey()),
+            &[payer, nonce_account],
+            recent_blockhash,
+        );
+
+        self.backend.send_transaction(transaction).await
+    }
+
+    /// Builds `instructions` against the current value of `nonce_account`, for signers that
+    /// sign away from the client. The nonce authority is one of the signers.
+    pub async fn offline_transaction(
+        &mut self,
+        instructions: &[Instruction],
+        payer: &Pubkey,
+        nonce_account: &Pubkey,
+    ) -> Result<OfflineTransaction, ClientError> {
+        let (authority, nonce) = self.get_nonce(nonce_account).await?;
+
+        Ok(OfflineTransaction::new(
+            &self.with_compute_budget(instructions),
+            payer,
+            nonce_account,
+            &authority,
+            nonce,
+        ))
+    }
+
+    /// A release for a spender that signs offline. `nonce` is the bridge nonce of the release,
+    /// unrelated to the durable nonce of `nonce_account`.
+    pub async fn offline_transfer_in(
+        &mut self,
+        spender: &Pubkey,
+        payer: &Pubkey,
+        nonce_account: &Pubkey,
+        nonce: u64,
+        transfers: &[InboundTransfer],
+    ) -> Result<OfflineTransaction, ClientError> {
+        let ix = instruction::transfer_in(&self.program_id, spender, nonce, transfers);
+        self.offline_transaction(&[ix], payer, nonce_account).await
+    }
+
+    /// Sends an offline transaction once every signature has been added.
+    pub async fn submit_offline(
+        &mut self,
+        transaction: OfflineTransaction,
+    ) -> Result<Signature, ClientError> {
+        let transaction = transaction.into_transaction()?;
+        self.backend.send_transaction(transaction).await
+    }
+
+    /// Creates the bridge pda with `admin` as admin, and no spender or guardian.
+    pub async fn initialize(&mut self, admin: &Keypair) -> Result<Signature, ClientError> {
+        let ix = instruction::initialize(&self.program_id, &admin.pubkey());
+        self.send(&[ix], admin).await
+    }
+
+    pub async fn migrate(&mut self, admin: &Keypair) -> Result<Signature, ClientError> {
+        let ix = instruction::migrate(&self.program_id, &admin.pubkey());
+        self.send(&[ix], admin).await
+    }
+
+    pub async fn add_spender(
+        &mut self,
+        admin: &Keypair,
+        spender: &Pubkey,
+    ) -> Result<Signature, ClientError> {
+        let ix = instruction::add_spender(&self.program_id, &admin.pubkey(), spender);
+        self.send(&[ix], admin).await
+    }
+
+    pub async fn activate_spender(
+        &mut self,
+        admin: &Keypair,
+        spender: &Pubkey,
+        overlap: SpenderOverlap,
+    ) -> Result<Signature, ClientError> {
+        let ix = instruction::activate_spender(&self.program_id, &admin.pubkey(), spender, overlap);
+        self.send(&[ix], admin).await
+    }
+
+    pub async fn remove_spender(
+        &mut self,
+        admin: &Keypair,
+        spender: &Pubkey,
+    ) -> Result<Signature, ClientError> {
+        let ix = instruction::remove_spender(&self.program_id, &admin.pubkey(), spender);
+        self.send(&[ix], admin).await
+    }
+
+    pub async fn change_admin(
+        &mut self,
+        admin: &Keypair,
+        new_admin: &Pubkey,
+    ) -> Result<Signature, ClientError> {
+        let ix = instruction::change_admin(&self.program_id, &admin.pubkey(), new_admin);
+        self.send(&[ix], admin).await
+    }
+
+    pub async fn set_paused(
+        &mut self,
+        admin: &Keypair,
+        paused: bool,
+    ) -> Result<Signature, ClientError> {
+        let ix = instruction::set_paused(&self.program_id, &admin.pubkey(), paused);
+        self.send(&[ix], admin).await
+    }
+
+    pub async fn set_timelock(
+        &mut self,
+        admin: &Keypair,
+        guardian: &Pubkey,
+        delay: u64,
+    ) -> Result<Signature, ClientError> {
+        let ix = instruction::set_timelock(&self.program_id, &admin.pubkey(), guardian, delay);
+        self.send(&[ix], admin).await
+    }
+
+    pub async fn set_cpi_caller(
+        &mut self,
+        admin: &Keypair,
+        program: &Pubkey,
+        allowed: bool,
+    ) -> Result<Signature, ClientError> {
+        let ix = instruction::set_cpi_caller(&self.program_id, &admin.pubkey(), program, allowed);
+        self.send(&[ix], admin).await
+    }
+
+    pub async fn set_role(
+        &mut self,
+        admin: &Keypair,
+        holder: &Pubkey,
+        role: Role,
+        granted: bool,
+    ) -> Result<Signature, ClientError> {
+        let ix = instruction::set_role(&self.program_id, &admin.pubkey(), holder, role, granted);
+        self.send(&[ix], admin).await
+    }
+
+    pub async fn decommission(
+        &mut self,
+        admin: &Keypair,
+        treasury: &Pubkey,
+    ) -> Result<Signature, ClientError> {
+        let ix = instruction::decommission(&self.program_id, &admin.pubkey(), treasury);
+        self.send(&[ix], admin).await
+    }
+
+    /// Sweeps the vault of `mint` to the treasury of a decommissioned bridge. The treasury's
+    /// associated token account must exist if the vault holds tokens.
+    pub async fn sweep_vault(
+        &mut self,
+        admin: &Keypair,
+        mint: &Pubkey,
+    ) -> Result<Signature, ClientError> {
+        let treasury = self.get_state().await?.treasury;
+        let ix = instruction::sweep_vault(&self.program_id, &admin.pubkey(), mint, &treasury);
+        self.send(&[ix], admin).await
+    }
+
+    pub async fn close_bridge(&mut self, admin: &Keypair) -> Result<Signature, ClientError> {
+        let treasury = self.get_state().await?.treasury;
+        let ix = instruction::close_bridge(&self.program_id, &admin.pubkey(), &treasury);
+        self.send(&[ix], admin).await
+    }
+
+    /// Logs a `Reconciled` event comparing the vault of `mint` with its counters.
+    pub async fn reconcile(
+        &mut self,
+        admin: &Keypair,
+        mint: &Pubkey,
+    ) -> Result<Signature, ClientError> {
+        let ix = instruction::reconcile(&self.program_id, &admin.pubkey(), mint);
+        self.send(&[ix], admin).await
+    }
+
+    /// A queued admin operation, `AccountNotFound` once it is executed or cancelled.
+    pub async fn get_pending_operation(
+        &mut self,
+        id: u64,
+    ) -> Result<PendingOperation, ClientError> {
+        let (operation, _) = instruction::operation_address(&self.program_id, id);
+        self.get_decoded(operation).await
+    }
+
+    pub async fn queue_operation(
+        &mut self,
+        admin: &Keypair,
+        id: u64,
+        operation: AdminOperation,
+    ) -> Result<Signature, ClientError> {
+        let ix = instruction::queue_operation(&self.program_id, &admin.pubkey(), id, operation);
+        self.send(&[ix], admin).await
+    }
+
+    pub async fn execute_operation(
+        &mut self,
+        admin: &Keypair,
+        id: u64,
+    ) -> Result<Signature, ClientError> {
+        let ix = instruction::execute_operation(&self.program_id, &admin.pubkey(), id);
+        self.send(&[ix], admin).await
+    }
+
+    pub async fn cancel_operation(
+        &mut self,
+        guardian: &Keypair,
+        id: u64,
+    ) -> Result<Signature, ClientError> {
+        let ix = instruction::cancel_operation(&self.program_id, &guardian.pubkey(), id);
+        self.send(&[ix], guardian).await
+    }
+
+    pub async fn get_token_config(&mut self, mint: &Pubkey) -> Result<TokenConfig, ClientError> {
+        let (token_config, _) = instruction::token_config(&self.program_id, mint);
+        self.get_decoded(token_config).await
+    }
+
+    /// Allows or forbids outbound transfers of `mint`. The bridge vault itself is not created
+    /// here.
+    pub async fn register_token(
+        &mut self,
+        admin: &Keypair,
+        mint: &Pubkey,
+        enabled: bool,
+    ) -> Result<Signature, ClientError> {
+        let ix = instruction::register_token(&self.program_id, &admin.pubkey(), mint, enabled);
+        self.send(&[ix], admin).await
+    }
+
+    /// Locks tokens from the user's associated token account, which must have approved the
+    /// bridge pda as delegate. Returns the sequence assigned to the transfer.
+    pub async fn transfer_out(
+        &mut self,
+        user: &Keypair,
+        mint: &Pubkey,
+        data: &TransferOutData,
+    ) -> Result<(Signature, u64), ClientError> {
+        let sequence = self.get_state().await?.out_sequence;
+        let ix = instruction::transfer_out(&self.program_id, &user.pubkey(), mint, sequence, data);
+
+        Ok((self.send(&[ix], user).await?, sequence))
+    }
+
+    pub async fn transfer_out_with_payload(
+        &mut self,
+        user: &Keypair,
+        mint: &Pubkey,
+        data: &TransferOutData,
+        payload: Vec<u8>,
+    ) -> Result<(Signature, u64), ClientError> {
+        let sequence = self.get_state().await?.out_sequence;
+        let ix = instruction::transfer_out_with_payload(
+            &self.program_id,
+            &user.pubkey(),
+            mint,
+            sequence,
+            data,
+            payload,
+        );
+
+        Ok((self.send(&[ix], user).await?, sequence))
+    }
+
+    /// Sends a message and returns the message sequence it was given.
+    pub async fn send_message(
+        &mut self,
+        sender: &Keypair,
+        data: &SendMessageData,
+    ) -> Result<(Signature, u64), ClientError> {
+        let sequence = self.get_state().await?.message_sequence;
+        let ix = instruction::send_message(&self.program_id, &sender.pubkey(), data);
+
+        Ok((self.send(&[ix], sender).await?, sequence))
+    }
+
+    pub async fn transfer_out_native(
+        &mut self,
+        user: &Keypair,
+        data: &TransferOutNativeData,
+    ) -> Result<(Signature, u64), ClientError> {
+        let sequence = self.get_state().await?.out_sequence;
+        let ix = instruction::transfer_out_native(&self.program_id, &user.pubkey(), sequence, data);
+
+        Ok((self.send(&[ix], user).await?, sequence))
+    }
+
+    pub async fn transfer_in(
+        &mut self,
+        spender: &Keypair,
+        nonce: u64,
+        transfers: &[InboundTransfer],
+    ) -> Result<Signature, ClientError> {
+        let ix = instruction::transfer_in(&self.program_id, &spender.pubkey(), nonce, transfers);
+        self.send(&[ix], spender).await
+    }
+
+    pub async fn transfer_in_native(
+        &mut self,
+        spender: &Keypair,
+        nonce: u64,
+        transfers: &[(Pubkey, u64)],
+    ) -> Result<Signature, ClientError> {
+        let ix =
+            instruction::transfer_in_native(&self.program_id, &spender.pubkey(), nonce, transfers);
+        self.send(&[ix], spender).await
+    }
+
+    /// Sends `releases` in as many transactions as they need, in order, and returns their
+    /// signatures. The releases of one nonce always go in the same transaction. Uses the
+    /// default policy when the client has none.
+    pub async fn transfer_in_releases(
+        &mut self,
+        spender: &Keypair,
+        releases: &[InboundRelease],
+    ) -> Result<Vec<Signature>, ClientError> {
+        let policy = self.compute_budget.unwrap_or_default();
+        let transactions = compute_budget::split_releases(
+            &self.program_id,
+            &spender.pubkey(),
+            &spender.pubkey(),
+            releases,
+            &policy,
+        )?;
+
+        let mut signatures = vec![];
+        for instructions in transactions {
+            let recent_blockhash = self.backend.get_latest_blockhash().await?;
+            let transaction = Transaction::new_signed_with_payer(
+                &instructions,
+                Some(&spender.pubkey()),
+                &[spender],
+                recent_blockhash,
+            );
+            signatures.push(self.backend.send_transaction(transaction).await?);
+        }
+        Ok(signatures)
+    }
+
+    /// Refunds a pending outbound transfer back to the account it came from.
+    pub async fn refund(
+        &mut self,
+        spender: &Keypair,
+        sequence: u64,
+    ) -> Result<Signature, ClientError> {
+        let receipt = self.get_receipt(sequence).await?;
+        let ix = instruction::refund(
+            &self.program_id,
+            &spender.pubkey(),
+            sequence,
+            &receipt.mint,
+            &receipt.source,
+        );
+        self.send(&[ix], spender).await
+    }
+
+    pub async fn mark_delivered(
+        &mut self,
+        spender: &Keypair,
+        sequence: u64,
+    ) -> Result<Signature, ClientError> {
+        let ix = instruction::mark_delivered(&self.program_id, &spender.pubkey(), sequence);
+        self.send(&[ix], spender).await
+    }
+
+    pub async fn deliver_message(
+        &mut self,
+        spender: &Keypair,
+        receiver_program: &Pubkey,
+        data: &DeliverMessageData,
+        token: Option<(Pubkey, Pubkey)>,
+        extra_accounts: &[AccountMeta],
+    ) -> Result<Signature, ClientError> {
+        let ix = instruction::deliver_message(
+            &self.program_id,
+            &spender.pubkey(),
+            receiver_program,
+            data,
+            token,
+            extra_accounts,
+        );
+        self.send(&[ix], spender).await
+    }
+}
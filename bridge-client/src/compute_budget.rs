@@ -0,0 +1,124 @@
+//! Compute unit limits and priority fees of bridge transactions, and the splitting of large
+//! inbound batches into transactions that fit.
+
+use sisu_bridge::state::BridgeInstruction;
+use solana_program::{instruction::Instruction, pubkey::Pubkey};
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction, packet::PACKET_DATA_SIZE, transaction::Transaction,
+};
+
+use crate::error::ClientError;
+use crate::instruction::{self, InboundRelease};
+
+/// The most compute units a transaction may request.
+pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+/// Estimate of any instruction that is not the bridge's, what the runtime grants it by default.
+pub const DEFAULT_INSTRUCTION_UNITS: u32 = 200_000;
+
+/// How transactions request compute units and pay for priority.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ComputeBudgetPolicy {
+    /// Percent added to the estimated units before they are requested.
+    pub margin_percent: u32,
+    /// Priority fee in micro-lamports per requested unit, no fee instruction when 0.
+    pub unit_price: u64,
+    /// Units one transaction may request. Batches are split to stay under it.
+    pub max_units: u32,
+}
+
+impl Default for ComputeBudgetPolicy {
+    fn default() -> Self {
+        ComputeBudgetPolicy {
+            margin_percent: 20,
+            unit_price: 0,
+            max_units: MAX_COMPUTE_UNIT_LIMIT,
+        }
+    }
+}
+
+impl ComputeBudgetPolicy {
+    /// The estimate of `instructions` with the margin, which may be above `max_units`.
+    pub fn estimate(&self, program_id: &Pubkey, instructions: &[Instruction]) -> u64 {
+        let units: u64 = instructions
+            .iter()
+            .map(|ix| estimate_units(program_id, ix) as u64)
+            .sum();
+        units * (100 + self.margin_percent as u64) / 100
+    }
+
+    /// `instructions` preceded by the compute budget instructions. The limit never goes
+    /// above `max_units`.
+    pub fn apply(&self, program_id: &Pubkey, instructions: &[Instruction]) -> Vec<Instruction> {
+        let units = self
+            .estimate(program_id, instructions)
+            .min(self.unit_limit() as u64) as u32;
+
+        let mut all_instructions = vec![ComputeBudgetInstruction::set_compute_unit_limit(units)];
+        if self.unit_price > 0 {
+            all_instructions.push(ComputeBudgetInstruction::set_compute_unit_price(
+                self.unit_price,
+            ));
+        }
+        all_instructions.extend_from_slice(instructions);
+        all_instructions
+    }
+
+    // `max_units`, but never more than a transaction may request.
+    fn unit_limit(&self) -> u32 {
+        self.max_units.min(MAX_COMPUTE_UNIT_LIMIT)
+    }
+
+    /// Whether `instructions` fit in one transaction paid by `payer`, in units and in size.
+    fn fits(&self, program_id: &Pubkey, payer: &Pubkey, instructions: &[Instruction]) -> bool {
+        if self.estimate(program_id, instructions) > self.unit_limit() as u64 {
+            return false;
+        }
+        let transaction =
+            Transaction::new_with_payer(&self.apply(program_id, instructions), Some(payer));
+        bincode::serialized_size(&transaction).map_or(false, |size| size <= PACKET_DATA_SIZE as u64)
+    }
+}
+
+/// Compute units of `ix`, from its batch size if it is a bridge instruction.
+pub fn estimate_units(program_id: &Pubkey, ix: &Instruction) -> u32 {
+    if ix.program_id != *program_id {
+        return DEFAULT_INSTRUCTION_UNITS;
+    }
+    BridgeInstruction::unpack(&ix.data)
+        .map(|instruction| instruction.compute_units())
+        .unwrap_or(DEFAULT_INSTRUCTION_UNITS)
+}
+
+/// Packs `releases` in order into as few transactions as the policy and the packet size
+/// allow, each with its compute budget instructions. A release is never split, one too large
+/// for a transaction on its own is an error.
+pub fn split_releases(
+    program_id: &Pubkey,
+    spender: &Pubkey,
+    payer: &Pubkey,
+    releases: &[InboundRelease],
+    policy: &ComputeBudgetPolicy,
+) -> Result<Vec<Vec<Instruction>>, ClientError> {
+    let mut transactions = vec![];
+    let mut current: Vec<Instruction> = vec![];
+    for release in releases {
+        let instructions = instruction::transfer_in_release(program_id, spender, release);
+        if !policy.fits(program_id, payer, &instructions) {
+            return Err(ClientError::BatchTooLarge(release.nonce));
+        }
+
+        let mut candidate = current.clone();
+        candidate.extend_from_slice(&instructions);
+        if policy.fits(program_id, payer, &candidate) {
+            current = candidate;
+        } else {
+            transactions.push(policy.apply(program_id, &current));
+            current = instructions;
+        }
+    }
+    if !current.is_empty() {
+        transactions.push(policy.apply(program_id, &current));
+    }
+
+    Ok(transactions)
+}
@@ -0,0 +1,43 @@
+use solana_banks_client::BanksClientError;
+use solana_client::client_error::ClientError as RpcClientError;
+use solana_program::pubkey::Pubkey;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ClientError {
+    #[error("Backend error: {0}")]
+    Backend(String),
+
+    #[error("Account {0} does not exist.")]
+    AccountNotFound(Pubkey),
+
+    #[error("Cannot decode account data: {0}")]
+    Decode(#[from] std::io::Error),
+
+    #[error("Simulation failed: {0}")]
+    Simulation(String),
+
+    #[error("Unexpected return data: {0}")]
+    ReturnData(String),
+
+    #[error("Offline signing failed: {0}")]
+    Signing(String),
+
+    #[error("Invalid nonce account: {0}")]
+    Nonce(String),
+
+    #[error("The release of nonce {0} does not fit in one transaction.")]
+    BatchTooLarge(u64),
+}
+
+impl From<BanksClientError> for ClientError {
+    fn from(e: BanksClientError) -> Self {
+        ClientError::Backend(e.to_string())
+    }
+}
+
+impl From<RpcClientError> for ClientError {
+    fn from(e: RpcClientError) -> Self {
+        ClientError::Backend(e.to_string())
+    }
+}
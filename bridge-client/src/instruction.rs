@@ -0,0 +1,709 @@
+//! Instruction builders for the bridge program. Account order matches `Processor` in
+//! `sisu-bridge`.
+
+use sisu_bridge::state::{
+    ActivateSpenderData, AddSpenderData, AdminOperation, BridgeInstruction, BridgeQuery,
+    ChangeAdminData, DecommissionData, DeliverMessageData, OperationData, QueueOperationData,
+    ReceiptData, RegisterTokenData, Role, SendMessageData, SetCpiCallerData, SetPausedData,
+    SetRoleData, SetTimelockData, SpenderOverlap, TransferInData, TransferOutData,
+    TransferOutNativeData, TransferOutWithPayloadData, BRIDGE_SEED, DELIVERY_SEED,
+    NATIVE_SOL_MINT, NATIVE_VAULT_SEED, OPERATION_SEED, RECEIPT_SEED, TOKEN_SEED,
+};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program, sysvar,
+};
+use spl_associated_token_account::get_associated_token_address;
+
+pub use sisu_bridge::receiver::message_authority;
+
+pub fn bridge_pda(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[BRIDGE_SEED], program_id)
+}
+
+pub fn native_vault(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[NATIVE_VAULT_SEED], program_id)
+}
+
+pub fn receipt_address(program_id: &Pubkey, sequence: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[RECEIPT_SEED, &sequence.to_le_bytes()], program_id)
+}
+
+pub fn token_config(program_id: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[TOKEN_SEED, mint.as_ref()], program_id)
+}
+
+pub fn delivery_record(program_id: &Pubkey, source_chain_id: u64, nonce: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            DELIVERY_SEED,
+            &source_chain_id.to_le_bytes(),
+            &nonce.to_le_bytes(),
+        ],
+        program_id,
+    )
+}
+
+pub fn operation_address(program_id: &Pubkey, id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[OPERATION_SEED, &id.to_le_bytes()], program_id)
+}
+
+/// The bridge vault for `mint`, i.e. the associated token account of the bridge pda.
+pub fn bridge_ata(program_id: &Pubkey, mint: &Pubkey) -> Pubkey {
+    get_associated_token_address(&bridge_pda(program_id).0, mint)
+}
+
+/// `bridge_ata`, or the native vault for `NATIVE_SOL_MINT`.
+fn bridge_vault(program_id: &Pubkey, mint: &Pubkey) -> Pubkey {
+    if *mint == NATIVE_SOL_MINT {
+        native_vault(program_id).0
+    } else {
+        bridge_ata(program_id, mint)
+    }
+}
+
+/// Last account of the instructions that other programs may only call if the admin allowed
+/// them, see `set_cpi_caller`.
+fn instructions_sysvar() -> AccountMeta {
+    AccountMeta::new_readonly(sysvar::instructions::id(), false)
+}
+
+pub fn initialize(program_id: &Pubkey, admin: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*admin, true),
+            AccountMeta::new(bridge_pda(program_id).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: BridgeInstruction::Initialize.pack(),
+    }
+}
+
+/// Grows a bridge account of an older layout to the current one, the admin pays the rent.
+pub fn migrate(program_id: &Pubkey, admin: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*admin, true),
+            AccountMeta::new(bridge_pda(program_id).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            instructions_sysvar(),
+        ],
+        data: BridgeInstruction::Migrate.pack(),
+    }
+}
+
+/// Makes `spender` current at once, the previous spender expires after this slot. See
+/// `activate_spender` for an overlap.
+pub fn add_spender(program_id: &Pubkey, admin: &Pubkey, spender: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new(bridge_pda(program_id).0, false),
+            instructions_sysvar(),
+        ],
+        data: BridgeInstruction::AddSpender(AddSpenderData { spender: *spender }).pack(),
+    }
+}
+
+fn admin_ix(program_id: &Pubkey, admin: &Pubkey, instruction: BridgeInstruction) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new(bridge_pda(program_id).0, false),
+            instructions_sysvar(),
+        ],
+        data: instruction.pack(),
+    }
+}
+
+/// Makes `spender` the current spender. The current one keeps signing for `overlap`.
+pub fn activate_spender(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    spender: &Pubkey,
+    overlap: SpenderOverlap,
+) -> Instruction {
+    admin_ix(
+        program_id,
+        admin,
+        BridgeInstruction::ActivateSpender(ActivateSpenderData {
+            spender: *spender,
+            overlap,
+        }),
+    )
+}
+
+pub fn remove_spender(program_id: &Pubkey, admin: &Pubkey, spender: &Pubkey) -> Instruction {
+    admin_ix(
+        program_id,
+        admin,
+        BridgeInstruction::RemoveSpender(AddSpenderData { spender: *spender }),
+    )
+}
+
+pub fn change_admin(program_id: &Pubkey, admin: &Pubkey, new_admin: &Pubkey) -> Instruction {
+    admin_ix(
+        program_id,
+        admin,
+        BridgeInstruction::ChangeAdmin(ChangeAdminData {
+            new_admin: *new_admin,
+        }),
+    )
+}
+
+pub fn set_timelock(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    guardian: &Pubkey,
+    delay: u64,
+) -> Instruction {
+    admin_ix(
+        program_id,
+        admin,
+        BridgeInstruction::SetTimelock(SetTimelockData {
+            guardian: *guardian,
+            delay,
+        }),
+    )
+}
+
+/// Allows `program` to call the guarded instructions through CPI, or revokes it. The check
+/// applies to the program of the top-level instruction.
+pub fn set_cpi_caller(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    program: &Pubkey,
+    allowed: bool,
+) -> Instruction {
+    admin_ix(
+        program_id,
+        admin,
+        BridgeInstruction::SetCpiCaller(SetCpiCallerData {
+            program: *program,
+            allowed,
+        }),
+    )
+}
+
+/// Grants `role` to `holder`, or revokes it. Keys that relay or guard cannot be granted the
+/// config roles, and the other way around.
+pub fn set_role(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    holder: &Pubkey,
+    role: Role,
+    granted: bool,
+) -> Instruction {
+    admin_ix(
+        program_id,
+        admin,
+        BridgeInstruction::SetRole(SetRoleData {
+            holder: *holder,
+            role,
+            granted,
+        }),
+    )
+}
+
+/// Applies `operation` right away, which the bridge only accepts without a timelock delay.
+pub fn admin_operation(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    operation: AdminOperation,
+) -> Instruction {
+    let instruction = match operation {
+        AdminOperation::ActivateSpender(data) => BridgeInstruction::ActivateSpender(data),
+        AdminOperation::RemoveSpender(data) => BridgeInstruction::RemoveSpender(data),
+        AdminOperation::ChangeAdmin(data) => BridgeInstruction::ChangeAdmin(data),
+        AdminOperation::SetTimelock(data) => BridgeInstruction::SetTimelock(data),
+        AdminOperation::Decommission(data) => BridgeInstruction::Decommission(data),
+        AdminOperation::SetCpiCaller(data) => BridgeInstruction::SetCpiCaller(data),
+        AdminOperation::SetRole(data) => BridgeInstruction::SetRole(data),
+    };
+    admin_ix(program_id, admin, instruction)
+}
+
+/// Queues `operation` under `id`, it can be executed once the timelock delay has passed.
+pub fn queue_operation(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    id: u64,
+    operation: AdminOperation,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*admin, true),
+            AccountMeta::new_readonly(bridge_pda(program_id).0, false),
+            AccountMeta::new(operation_address(program_id, id).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            instructions_sysvar(),
+        ],
+        data: BridgeInstruction::QueueOperation(QueueOperationData { id, operation }).pack(),
+    }
+}
+
+fn operation_ix(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    instruction: BridgeInstruction,
+    id: u64,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*signer, true),
+            AccountMeta::new(bridge_pda(program_id).0, false),
+            AccountMeta::new(operation_address(program_id, id).0, false),
+            instructions_sysvar(),
+        ],
+        data: instruction.pack(),
+    }
+}
+
+/// Applies a queued operation. The admin gets the rent of the operation account back.
+pub fn execute_operation(program_id: &Pubkey, admin: &Pubkey, id: u64) -> Instruction {
+    operation_ix(
+        program_id,
+        admin,
+        BridgeInstruction::ExecuteOperation(OperationData { id }),
+        id,
+    )
+}
+
+pub fn cancel_operation(program_id: &Pubkey, guardian: &Pubkey, id: u64) -> Instruction {
+    operation_ix(
+        program_id,
+        guardian,
+        BridgeInstruction::CancelOperation(OperationData { id }),
+        id,
+    )
+}
+
+/// Pauses the bridge for good and sets where `sweep_vault` and `close_bridge` send the funds.
+pub fn decommission(program_id: &Pubkey, admin: &Pubkey, treasury: &Pubkey) -> Instruction {
+    admin_ix(
+        program_id,
+        admin,
+        BridgeInstruction::Decommission(DecommissionData {
+            treasury: *treasury,
+        }),
+    )
+}
+
+/// Moves the vault balance of `mint` to the treasury's associated token account, then closes
+/// the vault and the token config. Their rent goes to the treasury.
+pub fn sweep_vault(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    mint: &Pubkey,
+    treasury: &Pubkey,
+) -> Instruction {
+    sweep_token_account(
+        program_id,
+        admin,
+        mint,
+        &bridge_ata(program_id, mint),
+        treasury,
+    )
+}
+
+/// `sweep_vault` for any token account of the bridge pda holding `mint`, not only its vault.
+/// Only the sweep of the vault closes the token config.
+pub fn sweep_token_account(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    mint: &Pubkey,
+    vault: &Pubkey,
+    treasury: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new(bridge_pda(program_id).0, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new(token_config(program_id, mint).0, false),
+            AccountMeta::new(*vault, false),
+            AccountMeta::new(get_associated_token_address(treasury, mint), false),
+            AccountMeta::new(*treasury, false),
+            instructions_sysvar(),
+        ],
+        data: BridgeInstruction::SweepVault.pack(),
+    }
+}
+
+/// Sweeps the native vault and closes the bridge pda, once every token has been swept. An empty
+/// account is left in its place, so that the bridge cannot be initialized again.
+pub fn close_bridge(program_id: &Pubkey, admin: &Pubkey, treasury: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new(bridge_pda(program_id).0, false),
+            AccountMeta::new(native_vault(program_id).0, false),
+            AccountMeta::new(*treasury, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(token_config(program_id, &NATIVE_SOL_MINT).0, false),
+            instructions_sysvar(),
+        ],
+        data: BridgeInstruction::CloseBridge.pack(),
+    }
+}
+
+/// A read-only query, answered with return data. `Token` queries need the `mint`.
+pub fn query(program_id: &Pubkey, query: BridgeQuery, mint: Option<&Pubkey>) -> Instruction {
+    let mut accounts = vec![AccountMeta::new_readonly(bridge_pda(program_id).0, false)];
+    if let Some(mint) = mint {
+        accounts.push(AccountMeta::new_readonly(
+            token_config(program_id, mint).0,
+            false,
+        ));
+    }
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: BridgeInstruction::Query(query).pack(),
+    }
+}
+
+/// Compares the vault of `mint` with its locked and released counters. The result is logged as
+/// a `Reconciled` event.
+pub fn reconcile(program_id: &Pubkey, admin: &Pubkey, mint: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new_readonly(bridge_pda(program_id).0, false),
+            AccountMeta::new_readonly(token_config(program_id, mint).0, false),
+            AccountMeta::new_readonly(bridge_vault(program_id, mint), false),
+            instructions_sysvar(),
+        ],
+        data: BridgeInstruction::Reconcile.pack(),
+    }
+}
+
+pub fn set_paused(program_id: &Pubkey, admin: &Pubkey, paused: bool) -> Instruction {
+    admin_ix(
+        program_id,
+        admin,
+        BridgeInstruction::SetPaused(SetPausedData { paused }),
+    )
+}
+
+/// Creates or updates the token config of `mint`. The admin pays for a new config, which counts
+/// from zero. Native SOL is registered as `NATIVE_SOL_MINT`, the admin also pays the rent
+/// reserve of the native vault.
+pub fn register_token(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    mint: &Pubkey,
+    enabled: bool,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*admin, true),
+        AccountMeta::new(bridge_pda(program_id).0, false),
+        AccountMeta::new(token_config(program_id, mint).0, false),
+        AccountMeta::new_readonly(*mint, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    if *mint == NATIVE_SOL_MINT {
+        accounts.push(AccountMeta::new(native_vault(program_id).0, false));
+    }
+    accounts.push(instructions_sysvar());
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: BridgeInstruction::RegisterToken(RegisterTokenData { enabled }).pack(),
+    }
+}
+
+/// Locks `data.amount` of `mint` from the user's associated token account. `sequence` must be
+/// the bridge's current `out_sequence`, it decides the receipt address.
+pub fn transfer_out(
+    program_id: &Pubkey,
+    user: &Pubkey,
+    mint: &Pubkey,
+    sequence: u64,
+    data: &TransferOutData,
+) -> Instruction {
+    transfer_out_ix(
+        program_id,
+        user,
+        mint,
+        sequence,
+        BridgeInstruction::TransferOut(data.clone()),
+    )
+}
+
+/// Same as `transfer_out`, with `payload` delivered to the recipient contract.
+pub fn transfer_out_with_payload(
+    program_id: &Pubkey,
+    user: &Pubkey,
+    mint: &Pubkey,
+    sequence: u64,
+    data: &TransferOutData,
+    payload: Vec<u8>,
+) -> Instruction {
+    transfer_out_ix(
+        program_id,
+        user,
+        mint,
+        sequence,
+        BridgeInstruction::TransferOutWithPayload(TransferOutWithPayloadData {
+            transfer: data.clone(),
+            payload,
+        }),
+    )
+}
+
+fn transfer_out_ix(
+    program_id: &Pubkey,
+    user: &Pubkey,
+    mint: &Pubkey,
+    sequence: u64,
+    instruction: BridgeInstruction,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*user, true),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new(get_associated_token_address(user, mint), false),
+            AccountMeta::new(bridge_ata(program_id, mint), false),
+            AccountMeta::new(bridge_pda(program_id).0, false),
+            AccountMeta::new(receipt_address(program_id, sequence).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(token_config(program_id, mint).0, false),
+        ],
+        data: instruction.pack(),
+    }
+}
+
+pub fn send_message(program_id: &Pubkey, sender: &Pubkey, data: &SendMessageData) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*sender, true),
+            AccountMeta::new(bridge_pda(program_id).0, false),
+        ],
+        data: BridgeInstruction::SendMessage(data.clone()).pack(),
+    }
+}
+
+pub fn transfer_out_native(
+    program_id: &Pubkey,
+    user: &Pubkey,
+    sequence: u64,
+    data: &TransferOutNativeData,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*user, true),
+            AccountMeta::new(bridge_pda(program_id).0, false),
+            AccountMeta::new(native_vault(program_id).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(receipt_address(program_id, sequence).0, false),
+            AccountMeta::new(token_config(program_id, &NATIVE_SOL_MINT).0, false),
+        ],
+        data: BridgeInstruction::TransferOutNative(data.clone()).pack(),
+    }
+}
+
+/// One release of a `TransferIn` batch. `receiver` is the receiving token account.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InboundTransfer {
+    pub mint: Pubkey,
+    pub receiver: Pubkey,
+    pub amount: u64,
+}
+
+pub fn transfer_in(
+    program_id: &Pubkey,
+    spender: &Pubkey,
+    nonce: u64,
+    transfers: &[InboundTransfer],
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*spender, true),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(bridge_pda(program_id).0, false),
+    ];
+    for transfer in transfers {
+        accounts.push(AccountMeta::new(
+            bridge_ata(program_id, &transfer.mint),
+            false,
+        ));
+        accounts.push(AccountMeta::new(transfer.receiver, false));
+        accounts.push(AccountMeta::new(
+            token_config(program_id, &transfer.mint).0,
+            false,
+        ));
+    }
+    accounts.push(instructions_sysvar());
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: BridgeInstruction::TransferIn(TransferInData {
+            nonce,
+            amounts: transfers.iter().map(|transfer| transfer.amount).collect(),
+        })
+        .pack(),
+    }
+}
+
+/// Releases native SOL, `transfers` pairs a receiving wallet with a lamport amount.
+pub fn transfer_in_native(
+    program_id: &Pubkey,
+    spender: &Pubkey,
+    nonce: u64,
+    transfers: &[(Pubkey, u64)],
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*spender, true),
+        AccountMeta::new_readonly(bridge_pda(program_id).0, false),
+        AccountMeta::new(native_vault(program_id).0, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new(token_config(program_id, &NATIVE_SOL_MINT).0, false),
+    ];
+    for (receiver, _) in transfers {
+        accounts.push(AccountMeta::new(*receiver, false));
+    }
+    accounts.push(instructions_sysvar());
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: BridgeInstruction::TransferInNative(TransferInData {
+            nonce,
+            amounts: transfers.iter().map(|(_, amount)| *amount).collect(),
+        })
+        .pack(),
+    }
+}
+
+/// Everything released under one bridge nonce, tokens and SOL.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InboundRelease {
+    pub nonce: u64,
+    pub transfers: Vec<InboundTransfer>,
+    /// Receiving wallets and lamports.
+    pub native: Vec<(Pubkey, u64)>,
+}
+
+/// The `transfer_in` and `transfer_in_native` instructions of a release, whichever it needs.
+/// They belong in the same transaction.
+pub fn transfer_in_release(
+    program_id: &Pubkey,
+    spender: &Pubkey,
+    release: &InboundRelease,
+) -> Vec<Instruction> {
+    let mut instructions = vec![];
+    if !release.transfers.is_empty() {
+        instructions.push(transfer_in(
+            program_id,
+            spender,
+            release.nonce,
+            &release.transfers,
+        ));
+    }
+    if !release.native.is_empty() {
+        instructions.push(transfer_in_native(
+            program_id,
+            spender,
+            release.nonce,
+            &release.native,
+        ));
+    }
+    instructions
+}
+
+/// Refunds a pending receipt. `mint` and `source` come from the receipt; for native SOL the
+/// mint is `NATIVE_SOL_MINT` and `source` the sender's wallet.
+pub fn refund(
+    program_id: &Pubkey,
+    spender: &Pubkey,
+    sequence: u64,
+    mint: &Pubkey,
+    source: &Pubkey,
+) -> Instruction {
+    let transfer_program = if *mint == NATIVE_SOL_MINT {
+        system_program::id()
+    } else {
+        spl_token::id()
+    };
+
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*spender, true),
+            AccountMeta::new_readonly(bridge_pda(program_id).0, false),
+            AccountMeta::new(receipt_address(program_id, sequence).0, false),
+            AccountMeta::new(bridge_vault(program_id, mint), false),
+            AccountMeta::new(*source, false),
+            AccountMeta::new_readonly(transfer_program, false),
+            AccountMeta::new(token_config(program_id, mint).0, false),
+            instructions_sysvar(),
+        ],
+        data: BridgeInstruction::Refund(ReceiptData { sequence }).pack(),
+    }
+}
+
+pub fn mark_delivered(program_id: &Pubkey, spender: &Pubkey, sequence: u64) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*spender, true),
+            AccountMeta::new_readonly(bridge_pda(program_id).0, false),
+            AccountMeta::new(receipt_address(program_id, sequence).0, false),
+            instructions_sysvar(),
+        ],
+        data: BridgeInstruction::MarkDelivered(ReceiptData { sequence }).pack(),
+    }
+}
+
+/// Delivers an inbound message to `receiver_program`. With a non-zero `data.amount`, `token`
+/// pairs the mint with the receiver's token account. `extra_accounts` are passed on to the
+/// receiver.
+pub fn deliver_message(
+    program_id: &Pubkey,
+    spender: &Pubkey,
+    receiver_program: &Pubkey,
+    data: &DeliverMessageData,
+    token: Option<(Pubkey, Pubkey)>,
+    extra_accounts: &[AccountMeta],
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*spender, true),
+        AccountMeta::new_readonly(bridge_pda(program_id).0, false),
+        AccountMeta::new(
+            delivery_record(program_id, data.source_chain_id, data.nonce).0,
+            false,
+        ),
+        AccountMeta::new_readonly(message_authority(program_id).0, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(*receiver_program, false),
+    ];
+    if let Some((mint, receiver_token)) = token {
+        accounts.push(AccountMeta::new_readonly(spl_token::id(), false));
+        accounts.push(AccountMeta::new(bridge_ata(program_id, &mint), false));
+        accounts.push(AccountMeta::new(receiver_token, false));
+        accounts.push(AccountMeta::new(token_config(program_id, &mint).0, false));
+    }
+    accounts.extend_from_slice(extra_accounts);
+    accounts.push(instructions_sysvar());
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: BridgeInstruction::DeliverMessage(data.clone()).pack(),
+    }
+}
@@ -0,0 +1,12 @@
+pub mod backend;
+pub mod client;
+pub mod compute_budget;
+pub mod error;
+pub mod instruction;
+pub mod offline;
+
+pub use backend::BridgeBackend;
+pub use client::BridgeClient;
+pub use compute_budget::ComputeBudgetPolicy;
+pub use error::ClientError;
+pub use offline::OfflineTransaction;
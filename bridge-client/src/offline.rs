@@ -0,0 +1,124 @@
+//! Transactions signed away from the client. Spenders are threshold signers that can take
+//! longer than a blockhash lifetime to sign, so these transactions use a durable nonce
+//! instead: they stay valid until the nonce account is advanced, which the transaction itself
+//! does first.
+
+use solana_program::{hash::Hash, instruction::Instruction, message::Message, pubkey::Pubkey};
+use solana_sdk::{
+    signature::{Keypair, Signature, Signer},
+    system_instruction,
+    transaction::Transaction,
+};
+
+use crate::error::ClientError;
+
+/// A durable nonce transaction waiting for its signatures. Its message is fixed when it is
+/// built: `message_bytes` is what every signer signs, and is enough to rebuild the transaction
+/// anywhere with `from_message_bytes`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OfflineTransaction {
+    transaction: Transaction,
+}
+
+impl OfflineTransaction {
+    /// Advances `nonce_account` and runs `instructions`. `nonce` is the value currently
+    /// stored in the nonce account, and `nonce_authority` must sign as well.
+    pub fn new(
+        instructions: &[Instruction],
+        payer: &Pubkey,
+        nonce_account: &Pubkey,
+        nonce_authority: &Pubkey,
+        nonce: Hash,
+    ) -> Self {
+        let mut all_instructions = vec![system_instruction::advance_nonce_account(
+            nonce_account,
+            nonce_authority,
+        )];
+        all_instructions.extend_from_slice(instructions);
+        let message = Message::new_with_blockhash(&all_instructions, Some(payer), &nonce);
+
+        OfflineTransaction {
+            transaction: Transaction::new_unsigned(message),
+        }
+    }
+
+    /// Rebuilds an unsigned transaction from exported message bytes.
+    pub fn from_message_bytes(bytes: &[u8]) -> Result<Self, ClientError> {
+        let message: Message = bincode::deserialize(bytes)
+            .map_err(|e| ClientError::Signing(format!("invalid message: {}", e)))?;
+        if message.header.num_required_signatures == 0 {
+            return Err(ClientError::Signing("message has no signers".to_string()));
+        }
+
+        Ok(OfflineTransaction {
+            transaction: Transaction::new_unsigned(message),
+        })
+    }
+
+    /// The bytes each signer signs.
+    pub fn message_bytes(&self) -> Vec<u8> {
+        self.transaction.message_data()
+    }
+
+    /// The nonce the transaction was built against.
+    pub fn nonce(&self) -> Hash {
+        self.transaction.message.recent_blockhash
+    }
+
+    /// Every key that must sign, the fee payer first.
+    pub fn signers(&self) -> &[Pubkey] {
+        let count = self.transaction.message.header.num_required_signatures as usize;
+        &self.transaction.message.account_keys[..count]
+    }
+
+    pub fn missing_signers(&self) -> Vec<Pubkey> {
+        self.signers()
+            .iter()
+            .zip(&self.transaction.signatures)
+            .filter(|(_, signature)| **signature == Signature::default())
+            .map(|(signer, _)| *signer)
+            .collect()
+    }
+
+    /// Adds a signature made elsewhere. It must be `signer`'s signature of `message_bytes`.
+    pub fn add_signature(
+        &mut self,
+        signer: &Pubkey,
+        signature: Signature,
+    ) -> Result<(), ClientError> {
+        let position = self
+            .signers()
+            .iter()
+            .position(|key| key == signer)
+            .ok_or_else(|| ClientError::Signing(format!("{} is not a signer", signer)))?;
+        if !signature.verify(signer.as_ref(), &self.message_bytes()) {
+            return Err(ClientError::Signing(format!(
+                "invalid signature for {}",
+                signer
+            )));
+        }
+
+        self.transaction.signatures[position] = signature;
+        Ok(())
+    }
+
+    /// Signs with a key at hand, e.g. the fee payer's.
+    pub fn sign(&mut self, keypair: &Keypair) -> Result<(), ClientError> {
+        let signature = keypair.sign_message(&self.message_bytes());
+        self.add_signature(&keypair.pubkey(), signature)
+    }
+
+    /// The transaction ready to send, once every signer has signed.
+    pub fn into_transaction(self) -> Result<Transaction, ClientError> {
+        let missing = self.missing_signers();
+        if !missing.is_empty() {
+            let missing: Vec<String> = missing.iter().map(|key| key.to_string()).collect();
+            return Err(ClientError::Signing(format!(
+                "missing signatures from {}",
+                missing.join(", ")
+            )));
+        }
+
+        Ok(self.transaction)
+    }
+}
@@ -0,0 +1,470 @@
+use {
+    sisu_bridge::processor::Processor,
+    sisu_bridge::state::{
+        AdminOperation, BridgeQuery, BridgeReturnData, ChangeAdminData, ReceiptStatus,
+        SendMessageData, SpenderOverlap, TokenInfo, TransferOutData,
+    },
+    sisu_bridge_client::{
+        compute_budget,
+        instruction::{self, InboundRelease, InboundTransfer},
+        BridgeClient, ClientError, ComputeBudgetPolicy, OfflineTransaction,
+    },
+    solana_program::{program_pack::Pack, pubkey::Pubkey, system_instruction},
+    solana_program_test::{processor, BanksClient, ProgramTest},
+    solana_sdk::{
+        compute_budget::ComputeBudgetInstruction,
+        signature::{Keypair, Signer},
+    },
+    spl_associated_token_account::{
+        get_associated_token_address, instruction::create_associated_token_account,
+    },
+    spl_token::state::{Account as SplTokenAccount, Mint},
+};
+
+async fn setup() -> (BridgeClient<BanksClient>, Keypair) {
+    let program_id = Pubkey::new_unique();
+    let (banks_client, payer, _) = ProgramTest::new(
+        "sisu_bridge",
+        program_id,
+        processor!(Processor::process_instruction),
+    )
+    .start()
+    .await;
+
+    let mut client = BridgeClient::new(banks_client, program_id);
+    client.initialize(&payer).await.unwrap();
+
+    (client, payer)
+}
+
+// Creates a mint with the bridge vault, and a user account holding `amount` that the bridge
+// pda may spend.
+async fn setup_token(
+    client: &mut BridgeClient<BanksClient>,
+    payer: &Keypair,
+    amount: u64,
+) -> (Pubkey, Pubkey) {
+    let mint = Keypair::new();
+    let rent = client.backend().get_rent().await.unwrap();
+    let user_ata = get_associated_token_address(&payer.pubkey(), &mint.pubkey());
+    let bridge_pda = client.bridge_pda();
+
+    let instructions = [
+        system_instruction::create_account(
+            &payer.pubkey(),
+            &mint.pubkey(),
+            rent.minimum_balance(Mint::LEN),
+            Mint::LEN as u64,
+            &spl_token::id(),
+        ),
+        spl_token::instruction::initialize_mint(
+            &spl_token::id(),
+            &mint.pubkey(),
+            &payer.pubkey(),
+            None,
+            8,
+        )
+        .unwrap(),
+        create_associated_token_account(
+            &payer.pubkey(),
+            &bridge_pda,
+            &mint.pubkey(),
+            &spl_token::id(),
+        ),
+        create_associated_token_account(
+            &payer.pubkey(),
+            &payer.pubkey(),
+            &mint.pubkey(),
+            &spl_token::id(),
+        ),
+        spl_token::instruction::mint_to(
+            &spl_token::id(),
+            &mint.pubkey(),
+            &user_ata,
+            &payer.pubkey(),
+            &[],
+            amount,
+        )
+        .unwrap(),
+        spl_token::instruction::approve(
+            &spl_token::id(),
+            &user_ata,
+            &bridge_pda,
+            &payer.pubkey(),
+            &[],
+            amount,
+        )
+        .unwrap(),
+    ];
+    let recent_blockhash = client.backend().get_latest_blockhash().await.unwrap();
+    let transaction = solana_sdk::transaction::Transaction::new_signed_with_payer(
+        &instructions,
+        Some(&payer.pubkey()),
+        &[payer, &mint],
+        recent_blockhash,
+    );
+    client
+        .backend()
+        .process_transaction(transaction)
+        .await
+        .unwrap();
+    client
+        .register_token(payer, &mint.pubkey(), true)
+        .await
+        .unwrap();
+
+    (mint.pubkey(), user_ata)
+}
+
+// A new keypair holding 1 SOL, for the keys that must not be the admin.
+async fn funded_keypair(client: &mut BridgeClient<BanksClient>, payer: &Keypair) -> Keypair {
+    let keypair = Keypair::new();
+    let fund = system_instruction::transfer(&payer.pubkey(), &keypair.pubkey(), 1_000_000_000);
+    client.send(&[fund], payer).await.unwrap();
+    keypair
+}
+
+// A funded spender, made current by the admin.
+async fn add_spender(client: &mut BridgeClient<BanksClient>, admin: &Keypair) -> Keypair {
+    let spender = funded_keypair(client, admin).await;
+    client.add_spender(admin, &spender.pubkey()).await.unwrap();
+    spender
+}
+
+async fn token_balance(client: &mut BridgeClient<BanksClient>, address: Pubkey) -> u64 {
+    let account = client
+        .backend()
+        .get_account(address)
+        .await
+        .unwrap()
+        .unwrap();
+    SplTokenAccount::unpack(&account.data).unwrap().amount
+}
+
+#[tokio::test]
+async fn test_initialize_and_add_spender() {
+    let (mut client, payer) = setup().await;
+
+    // The admin holds no hot role.
+    let state = client.get_state().await.unwrap();
+    assert_eq!(state.admin, payer.pubkey());
+    assert_eq!(state.guardian, Pubkey::default());
+    assert_eq!(state.spenders, [Pubkey::default(); 2]);
+    assert_eq!(state.out_sequence, 0);
+
+    let spender = Pubkey::new_unique();
+    client.add_spender(&payer, &spender).await.unwrap();
+    let state = client.get_state().await.unwrap();
+    assert!(state.spenders.contains(&spender));
+
+    let next_spender = Pubkey::new_unique();
+    client
+        .activate_spender(&payer, &next_spender, SpenderOverlap::Seconds(3600))
+        .await
+        .unwrap();
+    let epoch = client.get_spender_epoch().await.unwrap();
+    assert_eq!(epoch.epoch, 2);
+    assert_eq!(epoch.current, next_spender);
+    assert_eq!(epoch.previous, spender);
+    assert_eq!(epoch.previous_valid_until_slot, u64::MAX);
+}
+
+#[tokio::test]
+async fn test_queue_and_cancel_operation() {
+    let (mut client, payer) = setup().await;
+    let guardian = funded_keypair(&mut client, &payer).await;
+    client
+        .set_timelock(&payer, &guardian.pubkey(), 600)
+        .await
+        .unwrap();
+
+    let operation = AdminOperation::ChangeAdmin(ChangeAdminData {
+        new_admin: Pubkey::new_unique(),
+    });
+    client
+        .queue_operation(&payer, 7, operation.clone())
+        .await
+        .unwrap();
+    let pending = client.get_pending_operation(7).await.unwrap();
+    assert_eq!(pending.operation, operation);
+    assert!(client.execute_operation(&payer, 7).await.is_err());
+
+    client.cancel_operation(&guardian, 7).await.unwrap();
+    assert!(matches!(
+        client.get_pending_operation(7).await,
+        Err(ClientError::AccountNotFound(_))
+    ));
+    assert_eq!(client.get_state().await.unwrap().admin, payer.pubkey());
+}
+
+#[tokio::test]
+async fn test_transfer_round_trip() {
+    let (mut client, payer) = setup().await;
+    let (mint, user_ata) = setup_token(&mut client, &payer, 1000).await;
+
+    let data = TransferOutData {
+        amount: 700,
+        token_address: mint.to_string(),
+        chain_id: 189985,
+        recipient: "0x8095f5b69F2970f38DC6eBD2682ed71E4939f988".to_string(),
+    };
+    let (_, sequence) = client.transfer_out(&payer, &mint, &data).await.unwrap();
+    assert_eq!(sequence, 0);
+    assert_eq!(token_balance(&mut client, user_ata).await, 300);
+
+    let receipt = client.get_receipt(sequence).await.unwrap();
+    assert_eq!(receipt.amount, 700);
+    assert_eq!(receipt.status, ReceiptStatus::Pending);
+
+    // Release part of the locked tokens back to the user.
+    let spender = add_spender(&mut client, &payer).await;
+    client
+        .transfer_in(
+            &spender,
+            1,
+            &[InboundTransfer {
+                mint,
+                receiver: user_ata,
+                amount: 200,
+            }],
+        )
+        .await
+        .unwrap();
+    assert_eq!(token_balance(&mut client, user_ata).await, 500);
+    let program_id = *client.program_id();
+    assert_eq!(
+        token_balance(&mut client, instruction::bridge_ata(&program_id, &mint)).await,
+        500
+    );
+    let token_config = client.get_token_config(&mint).await.unwrap();
+    assert_eq!((token_config.locked, token_config.released), (700, 200));
+}
+
+#[tokio::test]
+async fn test_query() {
+    let (mut client, payer) = setup().await;
+    let (mint, _) = setup_token(&mut client, &payer, 1000).await;
+
+    let data = TransferOutData {
+        amount: 400,
+        token_address: mint.to_string(),
+        chain_id: 189985,
+        recipient: "0x8095f5b69F2970f38DC6eBD2682ed71E4939f988".to_string(),
+    };
+    client.transfer_out(&payer, &mint, &data).await.unwrap();
+
+    let token = client
+        .query(&payer, BridgeQuery::Token, Some(&mint))
+        .await
+        .unwrap();
+    assert_eq!(
+        token,
+        BridgeReturnData::Token(TokenInfo {
+            mint,
+            enabled: true,
+            locked: 400,
+            released: 0,
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_refund() {
+    let (mut client, payer) = setup().await;
+    let (mint, user_ata) = setup_token(&mut client, &payer, 1000).await;
+
+    let data = TransferOutData {
+        amount: 1000,
+        token_address: mint.to_string(),
+        chain_id: 189985,
+        recipient: "not-a-valid-recipient".to_string(),
+    };
+    let (_, sequence) = client.transfer_out(&payer, &mint, &data).await.unwrap();
+    assert_eq!(token_balance(&mut client, user_ata).await, 0);
+
+    let spender = add_spender(&mut client, &payer).await;
+    client.refund(&spender, sequence).await.unwrap();
+    assert_eq!(token_balance(&mut client, user_ata).await, 1000);
+    assert_eq!(
+        client.get_receipt(sequence).await.unwrap().status,
+        ReceiptStatus::Refunded
+    );
+}
+
+#[tokio::test]
+async fn test_send_message() {
+    let (mut client, payer) = setup().await;
+
+    let data = SendMessageData {
+        chain_id: 189985,
+        target: "0x8095f5b69F2970f38DC6eBD2682ed71E4939f988".to_string(),
+        payload: b"hello".to_vec(),
+    };
+    for expected in 0..2 {
+        let (_, sequence) = client.send_message(&payer, &data).await.unwrap();
+        assert_eq!(sequence, expected);
+    }
+    assert_eq!(client.get_state().await.unwrap().message_sequence, 2);
+}
+
+#[tokio::test]
+async fn test_offline_transfer_in() {
+    let program_id = Pubkey::new_unique();
+    let mut context = ProgramTest::new(
+        "sisu_bridge",
+        program_id,
+        processor!(Processor::process_instruction),
+    )
+    .start_with_context()
+    .await;
+    let payer = Keypair::from_bytes(&context.payer.to_bytes()).unwrap();
+    let mut client = BridgeClient::new(context.banks_client.clone(), program_id);
+    client.initialize(&payer).await.unwrap();
+    let (mint, user_ata) = setup_token(&mut client, &payer, 1000).await;
+    let data = TransferOutData {
+        amount: 1000,
+        token_address: mint.to_string(),
+        chain_id: 189985,
+        recipient: "0x8095f5b69F2970f38DC6eBD2682ed71E4939f988".to_string(),
+    };
+    client.transfer_out(&payer, &mint, &data).await.unwrap();
+
+    // A relayer pays and owns the nonce, the spender signs offline.
+    let spender = Keypair::new();
+    client.add_spender(&payer, &spender.pubkey()).await.unwrap();
+    let relayer = Keypair::new();
+    let fund = system_instruction::transfer(&payer.pubkey(), &relayer.pubkey(), 1_000_000_000);
+    client.send(&[fund], &payer).await.unwrap();
+    let nonce_account = Keypair::new();
+    client
+        .create_nonce_account(&relayer, &nonce_account, &relayer.pubkey())
+        .await
+        .unwrap();
+    let (authority, nonce) = client.get_nonce(&nonce_account.pubkey()).await.unwrap();
+    assert_eq!(authority, relayer.pubkey());
+
+    let transfers = [InboundTransfer {
+        mint,
+        receiver: user_ata,
+        amount: 400,
+    }];
+    let offline = client
+        .offline_transfer_in(
+            &spender.pubkey(),
+            &relayer.pubkey(),
+            &nonce_account.pubkey(),
+            1,
+            &transfers,
+        )
+        .await
+        .unwrap();
+    assert_eq!(offline.nonce(), nonce);
+    assert_eq!(offline.signers(), [relayer.pubkey(), spender.pubkey()]);
+
+    // The signers only see the exported bytes, and the signatures come back separately.
+    let message = offline.message_bytes();
+    let spender_signature = spender.sign_message(&message);
+    let relayer_signature = relayer.sign_message(&message);
+    let mut imported = OfflineTransaction::from_message_bytes(&message).unwrap();
+    assert_eq!(imported, offline);
+    assert!(matches!(
+        imported.add_signature(&spender.pubkey(), relayer_signature),
+        Err(ClientError::Signing(_))
+    ));
+    assert!(imported
+        .add_signature(&Pubkey::new_unique(), spender_signature)
+        .is_err());
+    imported
+        .add_signature(&spender.pubkey(), spender_signature)
+        .unwrap();
+    assert_eq!(imported.missing_signers(), [relayer.pubkey()]);
+    assert!(client.submit_offline(imported.clone()).await.is_err());
+    imported
+        .add_signature(&relayer.pubkey(), relayer_signature)
+        .unwrap();
+
+    // The nonce only moves forward once the cluster has moved to a new blockhash.
+    context.get_new_latest_blockhash().await.unwrap();
+    client.submit_offline(imported.clone()).await.unwrap();
+    assert_eq!(token_balance(&mut client, user_ata).await, 400);
+    let (_, advanced) = client.get_nonce(&nonce_account.pubkey()).await.unwrap();
+    assert_ne!(advanced, nonce);
+
+    // The advanced nonce makes the same transaction invalid.
+    context.get_new_latest_blockhash().await.unwrap();
+    assert!(client.submit_offline(imported).await.is_err());
+    assert_eq!(token_balance(&mut client, user_ata).await, 400);
+}
+
+#[tokio::test]
+async fn test_transfer_in_releases() {
+    let (mut client, payer) = setup().await;
+    let (mint, user_ata) = setup_token(&mut client, &payer, 1000).await;
+    let data = TransferOutData {
+        amount: 1000,
+        token_address: mint.to_string(),
+        chain_id: 189985,
+        recipient: "0x8095f5b69F2970f38DC6eBD2682ed71E4939f988".to_string(),
+    };
+    client.transfer_out(&payer, &mint, &data).await.unwrap();
+    let spender = add_spender(&mut client, &payer).await;
+
+    let release = |nonce, count| InboundRelease {
+        nonce,
+        transfers: vec![
+            InboundTransfer {
+                mint,
+                receiver: user_ata,
+                amount: 10,
+            };
+            count
+        ],
+        native: vec![],
+    };
+    // Room for two releases of two transfers per transaction.
+    let policy = ComputeBudgetPolicy {
+        margin_percent: 20,
+        unit_price: 5,
+        max_units: 70_000,
+    };
+    let releases: Vec<_> = (1..=5).map(|nonce| release(nonce, 2)).collect();
+
+    let program_id = *client.program_id();
+    let transactions = compute_budget::split_releases(
+        &program_id,
+        &spender.pubkey(),
+        &spender.pubkey(),
+        &releases,
+        &policy,
+    )
+    .unwrap();
+    assert_eq!(
+        transactions.iter().map(Vec::len).collect::<Vec<_>>(),
+        [4, 4, 3]
+    );
+    let release_units = releases[0].transfers.len() as u64 * 11_000 + 5_000;
+    assert_eq!(
+        transactions[0][0],
+        ComputeBudgetInstruction::set_compute_unit_limit((2 * release_units * 120 / 100) as u32)
+    );
+    assert_eq!(
+        transactions[0][1],
+        ComputeBudgetInstruction::set_compute_unit_price(5)
+    );
+
+    client.set_compute_budget(Some(policy));
+    let signatures = client
+        .transfer_in_releases(&spender, &releases)
+        .await
+        .unwrap();
+    assert_eq!(signatures.len(), 3);
+    assert_eq!(token_balance(&mut client, user_ata).await, 100);
+
+    // A release that does not fit on its own is not split across transactions.
+    let err = client
+        .transfer_in_releases(&spender, &[release(6, 1), release(7, 7)])
+        .await
+        .unwrap_err();
+    assert!(matches!(err, ClientError::BatchTooLarge(7)));
+    assert_eq!(token_balance(&mut client, user_ata).await, 100);
+}
@@ -0,0 +1,230 @@
+// The accounts in the IDL of the bridge match the ones the instruction builders pass.
+
+use {
+    sisu_bridge::{
+        idl::{idl, IdlAccount},
+        state::{
+            AdminOperation, BridgeQuery, DecommissionData, DeliverMessageData, Role,
+            SendMessageData, SpenderOverlap, TransferOutData, TransferOutNativeData,
+            NATIVE_SOL_MINT,
+        },
+    },
+    sisu_bridge_client::instruction::{self, InboundTransfer},
+    solana_program::{
+        instruction::{AccountMeta, Instruction},
+        pubkey::Pubkey,
+    },
+    std::collections::BTreeSet,
+};
+
+// The IDL accounts of `ix`, with each repeated account passed once and the optional ones only
+// if `with_optional`.
+fn check_accounts(ix: &Instruction, with_optional: bool) {
+    let idl = idl(None);
+    let instruction = idl.instruction(ix.data[0]).unwrap();
+    let expected: Vec<&IdlAccount> = instruction
+        .accounts
+        .iter()
+        .filter(|account| with_optional || !account.optional)
+        .collect();
+
+    assert_eq!(
+        ix.accounts.len(),
+        expected.len(),
+        "{}: number of accounts",
+        instruction.name
+    );
+    for (meta, account) in ix.accounts.iter().zip(expected) {
+        assert_eq!(
+            (meta.is_signer, meta.is_writable),
+            (account.signer, account.writable),
+            "{}: flags of {}",
+            instruction.name,
+            account.name
+        );
+        if let Some(address) = &account.address {
+            assert_eq!(&meta.pubkey.to_string(), address, "{}", account.name);
+        }
+    }
+}
+
+#[test]
+fn test_idl_accounts_match_builders() {
+    let program_id = Pubkey::new_unique();
+    let signer = Pubkey::new_unique();
+    let key = Pubkey::new_unique();
+    let mint = Pubkey::new_unique();
+    let transfer_out = TransferOutData {
+        amount: 1,
+        token_address: "0x1234".to_string(),
+        chain_id: 1,
+        recipient: "someone".to_string(),
+    };
+    let deliver = DeliverMessageData {
+        source_chain_id: 1,
+        nonce: 2,
+        sender: "0xabcd".to_string(),
+        payload: vec![1],
+        amount: 5,
+    };
+
+    let instructions = vec![
+        (instruction::initialize(&program_id, &signer), false),
+        (
+            instruction::transfer_out(&program_id, &signer, &mint, 0, &transfer_out),
+            false,
+        ),
+        (
+            instruction::transfer_in(
+                &program_id,
+                &signer,
+                1,
+                &[InboundTransfer {
+                    mint,
+                    receiver: key,
+                    amount: 1,
+                }],
+            ),
+            false,
+        ),
+        (instruction::add_spender(&program_id, &signer, &key), false),
+        (
+            instruction::remove_spender(&program_id, &signer, &key),
+            false,
+        ),
+        (instruction::change_admin(&program_id, &signer, &key), false),
+        (
+            instruction::transfer_out_native(
+                &program_id,
+                &signer,
+                0,
+                &TransferOutNativeData {
+                    amount: 1,
+                    chain_id: 1,
+                    recipient: "someone".to_string(),
+                },
+            ),
+            false,
+        ),
+        (
+            instruction::transfer_in_native(&program_id, &signer, 1, &[(key, 1)]),
+            false,
+        ),
+        (
+            instruction::refund(&program_id, &signer, 0, &mint, &key),
+            false,
+        ),
+        (
+            instruction::refund(&program_id, &signer, 0, &NATIVE_SOL_MINT, &key),
+            false,
+        ),
+        (instruction::mark_delivered(&program_id, &signer, 0), false),
+        (instruction::set_paused(&program_id, &signer, true), false),
+        (
+            instruction::register_token(&program_id, &signer, &mint, true),
+            false,
+        ),
+        (
+            instruction::register_token(&program_id, &signer, &NATIVE_SOL_MINT, true),
+            true,
+        ),
+        (
+            instruction::send_message(
+                &program_id,
+                &signer,
+                &SendMessageData {
+                    chain_id: 1,
+                    target: "0xabcd".to_string(),
+                    payload: vec![1],
+                },
+            ),
+            false,
+        ),
+        (
+            instruction::transfer_out_with_payload(
+                &program_id,
+                &signer,
+                &mint,
+                0,
+                &transfer_out,
+                vec![1],
+            ),
+            false,
+        ),
+        (
+            instruction::deliver_message(
+                &program_id,
+                &signer,
+                &key,
+                &deliver,
+                Some((mint, key)),
+                &[AccountMeta::new_readonly(Pubkey::new_unique(), false)],
+            ),
+            true,
+        ),
+        (
+            instruction::activate_spender(&program_id, &signer, &key, SpenderOverlap::Slots(1)),
+            false,
+        ),
+        (
+            instruction::set_timelock(&program_id, &signer, &key, 60),
+            false,
+        ),
+        (
+            instruction::queue_operation(
+                &program_id,
+                &signer,
+                1,
+                AdminOperation::Decommission(DecommissionData { treasury: key }),
+            ),
+            false,
+        ),
+        (
+            instruction::execute_operation(&program_id, &signer, 1),
+            false,
+        ),
+        (
+            instruction::cancel_operation(&program_id, &signer, 1),
+            false,
+        ),
+        (instruction::decommission(&program_id, &signer, &key), false),
+        (
+            instruction::sweep_vault(&program_id, &signer, &mint, &key),
+            false,
+        ),
+        (instruction::close_bridge(&program_id, &signer, &key), false),
+        (instruction::reconcile(&program_id, &signer, &mint), false),
+        (
+            instruction::query(&program_id, BridgeQuery::Token, Some(&mint)),
+            true,
+        ),
+        (
+            instruction::query(&program_id, BridgeQuery::Config, None),
+            false,
+        ),
+        (
+            instruction::set_cpi_caller(&program_id, &signer, &key, true),
+            false,
+        ),
+        (
+            instruction::set_role(&program_id, &signer, &key, Role::Relayer, true),
+            false,
+        ),
+        (instruction::migrate(&program_id, &signer), false),
+    ];
+
+    let mut tags = BTreeSet::new();
+    for (ix, with_optional) in &instructions {
+        check_accounts(ix, *with_optional);
+        tags.insert(ix.data[0]);
+    }
+
+    // Batch transfers out and compact transfers in have no builder.
+    let missing: Vec<&str> = idl(None)
+        .instructions
+        .iter()
+        .filter(|ix| !tags.contains(&ix.discriminator[0]))
+        .map(|ix| ix.name)
+        .collect();
+    assert_eq!(missing, ["transfer_out_batch", "transfer_in_compact"]);
+}
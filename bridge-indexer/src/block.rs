@@ -0,0 +1,82 @@
+//! The subset of the `getBlock` JSON response (`"encoding": "json"`) that the indexer reads.
+
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Block {
+    pub block_height: Option<u64>,
+    pub block_time: Option<i64>,
+    pub parent_slot: u64,
+    pub transactions: Vec<BlockTransaction>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct BlockTransaction {
+    pub meta: Option<TransactionMeta>,
+    pub transaction: TransactionInner,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionMeta {
+    pub err: Option<serde_json::Value>,
+    #[serde(default)]
+    pub inner_instructions: Option<Vec<InnerInstructions>>,
+    #[serde(default)]
+    pub log_messages: Option<Vec<String>>,
+    #[serde(default)]
+    pub loaded_addresses: Option<LoadedAddresses>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct InnerInstructions {
+    // Index of the outer instruction that produced these.
+    pub index: usize,
+    pub instructions: Vec<CompiledInstruction>,
+}
+
+// Accounts pulled in from address lookup tables by v0 transactions.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct LoadedAddresses {
+    pub writable: Vec<String>,
+    pub readonly: Vec<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TransactionInner {
+    pub signatures: Vec<String>,
+    pub message: Message,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Message {
+    pub account_keys: Vec<String>,
+    pub instructions: Vec<CompiledInstruction>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CompiledInstruction {
+    pub program_id_index: usize,
+    pub accounts: Vec<usize>,
+    pub data: String, // base58
+}
+
+impl BlockTransaction {
+    /// Static account keys followed by the ones loaded from lookup tables, which is the order
+    /// instruction account indexes refer to.
+    pub fn account_keys(&self) -> Vec<String> {
+        let mut keys = self.transaction.message.account_keys.clone();
+        if let Some(loaded) = self.meta.as_ref().and_then(|m| m.loaded_addresses.as_ref()) {
+            keys.extend(loaded.writable.iter().cloned());
+            keys.extend(loaded.readonly.iter().cloned());
+        }
+        keys
+    }
+
+    pub fn succeeded(&self) -> bool {
+        self.meta.as_ref().map_or(true, |meta| meta.err.is_none())
+    }
+}
@@ -0,0 +1,49 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::IndexerError;
+
+/// The last slot whose records have been fully handed to the sink.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Checkpoint {
+    pub last_slot: u64,
+}
+
+pub trait CheckpointStore {
+    fn load(&mut self) -> Result<Option<Checkpoint>, IndexerError>;
+
+    fn save(&mut self, checkpoint: Checkpoint) -> Result<(), IndexerError>;
+}
+
+/// Keeps the checkpoint as a small JSON file. Saves go through a temporary file and a rename so
+/// that a crash never leaves a half written checkpoint behind.
+pub struct FileCheckpoint {
+    path: PathBuf,
+}
+
+impl FileCheckpoint {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileCheckpoint { path: path.into() }
+    }
+}
+
+impl CheckpointStore for FileCheckpoint {
+    fn load(&mut self) -> Result<Option<Checkpoint>, IndexerError> {
+        match fs::read(&self.path) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save(&mut self, checkpoint: Checkpoint) -> Result<(), IndexerError> {
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_vec(&checkpoint)?)?;
+        fs::rename(&tmp_path, &self.path)?;
+
+        Ok(())
+    }
+}
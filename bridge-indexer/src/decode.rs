@@ -0,0 +1,49 @@
+use borsh::BorshDeserialize;
+use sisu_bridge::state::{BridgeEvent, BridgeInstruction};
+
+use crate::error::IndexerError;
+
+const PROGRAM_DATA_PREFIX: &str = "Program data: ";
+
+/// Decodes bridge instruction data exactly like the program does.
+pub fn decode_instruction(data: &[u8]) -> Result<BridgeInstruction, IndexerError> {
+    BridgeInstruction::unpack(data).map_err(|e| IndexerError::Decode(e.to_string()))
+}
+
+/// Decodes one base64 field of a `Program data:` log line of the bridge.
+pub fn decode_event(field: &str) -> Result<BridgeEvent, IndexerError> {
+    let bytes =
+        base64::decode(field).map_err(|e| IndexerError::Decode(format!("event base64: {}", e)))?;
+    BridgeEvent::try_from_slice(&bytes).map_err(|e| IndexerError::Decode(format!("event: {}", e)))
+}
+
+/// Extracts the `Program data:` fields logged by `program_id` from a transaction's log
+/// messages, still base64 encoded. `sol_log_data` output is attributed to whichever program is
+/// on top of the invoke stack, so data logged by other programs is never mixed in.
+pub fn event_fields<'a>(program_id: &str, logs: &'a [String]) -> Vec<&'a str> {
+    let mut stack: Vec<&str> = vec![];
+    let mut fields = vec![];
+
+    for line in logs {
+        if let Some(data) = line.strip_prefix(PROGRAM_DATA_PREFIX) {
+            if stack.last() == Some(&program_id) {
+                fields.extend(data.split(' '));
+            }
+            continue;
+        }
+
+        let mut words = line.split(' ');
+        if words.next() != Some("Program") {
+            continue;
+        }
+        match (words.next(), words.next()) {
+            (Some(id), Some("invoke")) => stack.push(id),
+            (Some(_), Some("success")) | (Some(_), Some("failed:")) => {
+                stack.pop();
+            }
+            _ => {}
+        }
+    }
+
+    fields
+}
@@ -0,0 +1,224 @@
+use serde::Serialize;
+use sisu_bridge::state::{BridgeEvent, BridgeInstruction};
+use solana_program::pubkey::Pubkey;
+
+use crate::block::{Block, BlockTransaction, CompiledInstruction};
+use crate::checkpoint::{Checkpoint, CheckpointStore};
+use crate::decode::{decode_event, decode_instruction, event_fields};
+use crate::error::IndexerError;
+use crate::source::BlockSource;
+
+// Printed as one JSON object per line, in the schema of the `serde` feature of `sisu-bridge`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", content = "data")]
+pub enum Record {
+    Instruction(InstructionRecord),
+    Event(EventRecord),
+    Unknown(UnknownRecord),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InstructionRecord {
+    pub slot: u64,
+    pub signature: String,
+    // Index of the top level instruction, and the position inside its inner instructions when
+    // the bridge was reached through CPI.
+    pub instruction_index: usize,
+    pub inner_index: Option<usize>,
+    #[serde(with = "sisu_bridge::json::pubkeys")]
+    pub accounts: Vec<Pubkey>,
+    pub instruction: BridgeInstruction,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EventRecord {
+    pub slot: u64,
+    pub signature: String,
+    pub event: BridgeEvent,
+}
+
+// A bridge instruction or event that does not decode, for instance one added by a newer
+// program. It is passed on as it was found rather than holding up the whole slot.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UnknownRecord {
+    pub slot: u64,
+    pub signature: String,
+    // Where the instruction sits, as in `InstructionRecord`. Both are none for an event.
+    pub instruction_index: Option<usize>,
+    pub inner_index: Option<usize>,
+    // Base58 instruction data, or the base64 field of the `Program data:` line.
+    pub data: String,
+    pub error: String,
+}
+
+/// Walks blocks from a `BlockSource` and turns everything that touched the bridge into
+/// records. Progress is checkpointed after every slot so a restarted indexer resumes where it
+/// stopped.
+pub struct Indexer<S: BlockSource, C: CheckpointStore> {
+    program_id: Pubkey,
+    source: S,
+    checkpoint: C,
+    next_slot: u64,
+}
+
+impl<S: BlockSource, C: CheckpointStore> Indexer<S, C> {
+    /// `start_slot` is only used when there is no checkpoint yet.
+    pub fn new(
+        program_id: Pubkey,
+        source: S,
+        mut checkpoint: C,
+        start_slot: u64,
+    ) -> Result<Self, IndexerError> {
+        let next_slot = match checkpoint.load()? {
+            Some(saved) => saved.last_slot + 1,
+            None => start_slot,
+        };
+
+        Ok(Indexer {
+            program_id,
+            source,
+            checkpoint,
+            next_slot,
+        })
+    }
+
+    pub fn next_slot(&self) -> u64 {
+        self.next_slot
+    }
+
+    /// Indexes every slot up to the latest one of the source and returns how many slots were
+    /// processed. A slot is only checkpointed once all its records were accepted by `sink`.
+    pub fn poll<F>(&mut self, mut sink: F) -> Result<u64, IndexerError>
+    where
+        F: FnMut(Record) -> Result<(), IndexerError>,
+    {
+        let latest = self.source.latest_slot()?;
+        let mut processed = 0;
+
+        while self.next_slot <= latest {
+            let slot = self.next_slot;
+            if let Some(block) = self.source.get_block(slot)? {
+                for record in decode_block(&self.program_id, slot, &block)? {
+                    sink(record)?;
+                }
+            }
+
+            self.checkpoint.save(Checkpoint { last_slot: slot })?;
+            self.next_slot += 1;
+            processed += 1;
+        }
+
+        Ok(processed)
+    }
+}
+
+/// Decodes the bridge instructions, top level and inner, and the bridge events of every
+/// successful transaction in `block`, in execution order. Whatever of the bridge does not
+/// decode comes out as an `UnknownRecord`.
+pub fn decode_block(
+    program_id: &Pubkey,
+    slot: u64,
+    block: &Block,
+) -> Result<Vec<Record>, IndexerError> {
+    let program_id_str = program_id.to_string();
+    let mut records = vec![];
+
+    for tx in block.transactions.iter().filter(|tx| tx.succeeded()) {
+        let keys = tx.account_keys();
+        let signature = tx.transaction.signatures[0].clone();
+        let inner_instructions = tx
+            .meta
+            .as_ref()
+            .and_then(|meta| meta.inner_instructions.as_ref());
+
+        for (i, ix) in tx.transaction.message.instructions.iter().enumerate() {
+            let inner = inner_instructions
+                .and_then(|groups| groups.iter().find(|group| group.index == i))
+                .map(|group| group.instructions.as_slice())
+                .unwrap_or_default();
+
+            let positions = std::iter::once((None, ix))
+                .chain(inner.iter().enumerate().map(|(j, ix)| (Some(j), ix)));
+            for (inner_index, ix) in positions {
+                if key_at(&keys, ix.program_id_index)? != program_id_str {
+                    continue;
+                }
+
+                let decoded = instruction_data(ix).and_then(|data| {
+                    Ok((instruction_accounts(&keys, ix)?, decode_instruction(&data)?))
+                });
+                records.push(match decoded {
+                    Ok((accounts, instruction)) => Record::Instruction(InstructionRecord {
+                        slot,
+                        signature: signature.clone(),
+                        instruction_index: i,
+                        inner_index,
+                        accounts,
+                        instruction,
+                    }),
+                    Err(e) => Record::Unknown(UnknownRecord {
+                        slot,
+                        signature: signature.clone(),
+                        instruction_index: Some(i),
+                        inner_index,
+                        data: ix.data.clone(),
+                        error: e.to_string(),
+                    }),
+                });
+            }
+        }
+
+        for field in event_fields(&program_id_str, log_messages(tx)) {
+            records.push(match decode_event(field) {
+                Ok(event) => Record::Event(EventRecord {
+                    slot,
+                    signature: signature.clone(),
+                    event,
+                }),
+                Err(e) => Record::Unknown(UnknownRecord {
+                    slot,
+                    signature: signature.clone(),
+                    instruction_index: None,
+                    inner_index: None,
+                    data: field.to_string(),
+                    error: e.to_string(),
+                }),
+            });
+        }
+    }
+
+    Ok(records)
+}
+
+fn key_at(keys: &[String], index: usize) -> Result<&str, IndexerError> {
+    keys.get(index)
+        .map(|key| key.as_str())
+        .ok_or_else(|| IndexerError::Decode(format!("account index {} out of range", index)))
+}
+
+fn instruction_accounts(
+    keys: &[String],
+    ix: &CompiledInstruction,
+) -> Result<Vec<Pubkey>, IndexerError> {
+    ix.accounts
+        .iter()
+        .map(|index| {
+            key_at(keys, *index)?
+                .parse()
+                .map_err(|_| IndexerError::Decode(format!("pubkey at index {}", index)))
+        })
+        .collect()
+}
+
+fn instruction_data(ix: &CompiledInstruction) -> Result<Vec<u8>, IndexerError> {
+    bs58::decode(&ix.data)
+        .into_vec()
+        .map_err(|e| IndexerError::Decode(format!("instruction base58: {}", e)))
+}
+
+fn log_messages(tx: &BlockTransaction) -> &[String] {
+    tx.meta
+        .as_ref()
+        .and_then(|meta| meta.log_messages.as_deref())
+        .unwrap_or_default()
+}
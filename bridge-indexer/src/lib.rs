@@ -0,0 +1,9 @@
+pub mod block;
+pub mod checkpoint;
+pub mod decode;
+pub mod error;
+pub mod indexer;
+pub mod source;
+
+pub use error::IndexerError;
+pub use indexer::{Indexer, Record};
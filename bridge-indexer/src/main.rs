@@ -0,0 +1,57 @@
+use std::{env, process, thread, time::Duration};
+
+use sisu_bridge_indexer::{
+    checkpoint::FileCheckpoint,
+    source::{BlockSource, RpcBlockSource},
+    Indexer, IndexerError, Record,
+};
+use solana_program::pubkey::Pubkey;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 4 {
+        eprintln!(
+            "usage: {} <rpc-url> <program-id> <checkpoint-file> [start-slot]",
+            args[0]
+        );
+        process::exit(1);
+    }
+
+    let program_id: Pubkey = args[2].parse().expect("invalid program id");
+    let mut source = RpcBlockSource::new(args[1].clone());
+    // Without a checkpoint or a start slot, indexing starts at the current slot.
+    let start_slot = match args.get(4) {
+        Some(slot) => slot.parse().expect("invalid start slot"),
+        None => source.latest_slot().expect("cannot get the current slot"),
+    };
+
+    let mut indexer = Indexer::new(
+        program_id,
+        source,
+        FileCheckpoint::new(&args[3]),
+        start_slot,
+    )
+    .expect("cannot load checkpoint");
+    // Stdout only carries records.
+    eprintln!("Indexing {} from slot {}", program_id, indexer.next_slot());
+
+    loop {
+        let result = indexer.poll(|record| -> Result<(), IndexerError> {
+            if let Record::Unknown(unknown) = &record {
+                eprintln!(
+                    "slot {}: cannot decode {} of {}: {}",
+                    unknown.slot, unknown.data, unknown.signature, unknown.error
+                );
+            }
+            println!("{}", serde_json::to_string(&record)?);
+            Ok(())
+        });
+        if let Err(e) = result {
+            eprintln!("slot {}: {}", indexer.next_slot(), e);
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
@@ -0,0 +1,68 @@
+use serde_json::json;
+use solana_client::{
+    client_error::ClientErrorKind,
+    rpc_client::RpcClient,
+    rpc_request::{RpcError, RpcRequest},
+};
+use solana_sdk::commitment_config::CommitmentConfig;
+
+use crate::block::Block;
+use crate::error::IndexerError;
+
+// RPC error codes for slots that were skipped by the leader or are missing from long term
+// storage. Neither will ever have a block.
+const SLOT_SKIPPED: i64 = -32007;
+const LONG_TERM_STORAGE_SLOT_SKIPPED: i64 = -32009;
+
+pub trait BlockSource {
+    fn latest_slot(&mut self) -> Result<u64, IndexerError>;
+
+    /// Returns `None` for slots without a block.
+    fn get_block(&mut self, slot: u64) -> Result<Option<Block>, IndexerError>;
+}
+
+/// Reads confirmed blocks from a JSON RPC node.
+pub struct RpcBlockSource {
+    client: RpcClient,
+}
+
+impl RpcBlockSource {
+    pub fn new(url: String) -> Self {
+        RpcBlockSource {
+            client: RpcClient::new_with_commitment(url, CommitmentConfig::confirmed()),
+        }
+    }
+}
+
+impl BlockSource for RpcBlockSource {
+    fn latest_slot(&mut self) -> Result<u64, IndexerError> {
+        self.client
+            .get_slot()
+            .map_err(|e| IndexerError::Rpc(e.to_string()))
+    }
+
+    fn get_block(&mut self, slot: u64) -> Result<Option<Block>, IndexerError> {
+        let config = json!({
+            "encoding": "json",
+            "transactionDetails": "full",
+            "rewards": false,
+            "commitment": "confirmed",
+            "maxSupportedTransactionVersion": 0,
+        });
+
+        match self
+            .client
+            .send::<Block>(RpcRequest::GetBlock, json!([slot, config]))
+        {
+            Ok(block) => Ok(Some(block)),
+            Err(e) => match e.kind() {
+                ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. })
+                    if *code == SLOT_SKIPPED || *code == LONG_TERM_STORAGE_SLOT_SKIPPED =>
+                {
+                    Ok(None)
+                }
+                _ => Err(IndexerError::Rpc(e.to_string())),
+            },
+        }
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+
+use sisu_bridge::state::{
+    BridgeEvent, BridgeInstruction, DeliveredEvent, ReceiptData, TransferOutData, NATIVE_SOL_MINT,
+};
+use sisu_bridge_indexer::{
+    block::Block,
+    checkpoint::{Checkpoint, CheckpointStore, FileCheckpoint},
+    decode::decode_instruction,
+    indexer::{decode_block, EventRecord, InstructionRecord, UnknownRecord},
+    source::BlockSource,
+    Indexer, IndexerError, Record,
+};
+use solana_program::pubkey::Pubkey;
+
+const PROGRAM_ID: &str = "HguMTvmDfspHuEWycDSP1XtVQJi47hVNAyLbFEf2EJEQ";
+const USER: &str = "LQVcTQajEfHFgC7dJeWJ6R3uBsqZrSdp9rTzv344p4A";
+const INTEGRATOR: &str = "2WraZXRoxBBz4bmAPRZgSX9Wob9CuqEiMV9BT6cSwnLn";
+const RECEIPT_5: &str = "BsK7FgYnyycj3AVYdgWjVze7jxCo8mgJadCivHRdnM2q";
+
+// Recorded `getBlock` responses. Slot 1001 was skipped.
+struct FixtureSource {
+    blocks: HashMap<u64, &'static str>,
+}
+
+impl FixtureSource {
+    fn new() -> Self {
+        let mut blocks = HashMap::new();
+        blocks.insert(1000, include_str!("fixtures/block-1000.json"));
+        blocks.insert(1002, include_str!("fixtures/block-1002.json"));
+        blocks.insert(1003, include_str!("fixtures/block-1003.json"));
+        FixtureSource { blocks }
+    }
+}
+
+impl BlockSource for FixtureSource {
+    fn latest_slot(&mut self) -> Result<u64, IndexerError> {
+        Ok(1003)
+    }
+
+    fn get_block(&mut self, slot: u64) -> Result<Option<Block>, IndexerError> {
+        match self.blocks.get(&slot) {
+            Some(json) => Ok(Some(serde_json::from_str(json)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+struct MemoryCheckpoint {
+    saved: Option<Checkpoint>,
+}
+
+impl CheckpointStore for MemoryCheckpoint {
+    fn load(&mut self) -> Result<Option<Checkpoint>, IndexerError> {
+        Ok(self.saved)
+    }
+
+    fn save(&mut self, checkpoint: Checkpoint) -> Result<(), IndexerError> {
+        self.saved = Some(checkpoint);
+        Ok(())
+    }
+}
+
+fn key(s: &str) -> Pubkey {
+    s.parse().unwrap()
+}
+
+#[test]
+fn test_decode_block() {
+    let block = FixtureSource::new().get_block(1000).unwrap().unwrap();
+    let records = decode_block(&key(PROGRAM_ID), 1000, &block).unwrap();
+
+    // The failed transaction contributes nothing, and the integrator's own log data is not
+    // mistaken for a bridge event.
+    assert_eq!(records.len(), 4);
+
+    match &records[0] {
+        Record::Instruction(InstructionRecord {
+            instruction_index: 0,
+            inner_index: None,
+            accounts,
+            instruction: BridgeInstruction::TransferOut(data),
+            ..
+        }) => {
+            assert_eq!(accounts[0], key(USER));
+            assert_eq!(
+                data,
+                &TransferOutData {
+                    amount: 900,
+                    token_address: "0x1234".to_string(),
+                    chain_id: 189985,
+                    recipient: "0x8095f5b69F2970f38DC6eBD2682ed71E4939f988".to_string(),
+                }
+            );
+        }
+        other => panic!("unexpected record {:?}", other),
+    }
+
+    match &records[1] {
+        Record::Event(EventRecord {
+            event: BridgeEvent::TransferOut(event),
+            ..
+        }) => {
+            assert_eq!(event.sequence, 5);
+            assert_eq!(event.sender, key(USER));
+            assert_eq!(event.amount, 900);
+        }
+        other => panic!("unexpected record {:?}", other),
+    }
+
+    // Deposit made by another program through CPI.
+    match &records[2] {
+        Record::Instruction(InstructionRecord {
+            instruction_index: 0,
+            inner_index: Some(0),
+            accounts,
+            instruction: BridgeInstruction::TransferOutNative(data),
+            ..
+        }) => {
+            assert_eq!(accounts[0], key(INTEGRATOR));
+            assert_eq!(data.amount, 2_000_000_000);
+            assert_eq!(data.chain_id, 97);
+        }
+        other => panic!("unexpected record {:?}", other),
+    }
+
+    match &records[3] {
+        Record::Event(EventRecord {
+            event: BridgeEvent::TransferOut(event),
+            ..
+        }) => {
+            assert_eq!(event.sequence, 6);
+            assert_eq!(event.mint, NATIVE_SOL_MINT);
+        }
+        other => panic!("unexpected record {:?}", other),
+    }
+}
+
+#[test]
+fn test_lookup_table_accounts() {
+    let block = FixtureSource::new().get_block(1002).unwrap().unwrap();
+    let records = decode_block(&key(PROGRAM_ID), 1002, &block).unwrap();
+
+    assert_eq!(records.len(), 2);
+    match &records[0] {
+        Record::Instruction(InstructionRecord {
+            accounts,
+            instruction: BridgeInstruction::MarkDelivered(ReceiptData { sequence: 5 }),
+            ..
+        }) => assert_eq!(accounts[2], key(RECEIPT_5)),
+        other => panic!("unexpected record {:?}", other),
+    }
+    match &records[1] {
+        Record::Event(EventRecord { event, .. }) => {
+            assert_eq!(
+                event,
+                &BridgeEvent::Delivered(DeliveredEvent { sequence: 5 })
+            )
+        }
+        other => panic!("unexpected record {:?}", other),
+    }
+}
+
+#[test]
+fn test_undecodable_records() {
+    let block = FixtureSource::new().get_block(1003).unwrap().unwrap();
+    let records = decode_block(&key(PROGRAM_ID), 1003, &block).unwrap();
+
+    // What does not decode is passed on raw, and the rest of the transaction still decodes.
+    assert_eq!(records.len(), 4);
+    match &records[0] {
+        Record::Unknown(UnknownRecord {
+            slot: 1003,
+            instruction_index: Some(0),
+            inner_index: None,
+            data,
+            ..
+        }) => assert_eq!(data, "2Uess"),
+        other => panic!("unexpected record {:?}", other),
+    }
+    match &records[1] {
+        Record::Instruction(InstructionRecord {
+            instruction_index: 1,
+            instruction: BridgeInstruction::MarkDelivered(ReceiptData { sequence: 6 }),
+            ..
+        }) => {}
+        other => panic!("unexpected record {:?}", other),
+    }
+    match &records[2] {
+        Record::Unknown(UnknownRecord {
+            instruction_index: None,
+            inner_index: None,
+            data,
+            ..
+        }) => assert_eq!(data, "/wE="),
+        other => panic!("unexpected record {:?}", other),
+    }
+    match &records[3] {
+        Record::Event(EventRecord { event, .. }) => {
+            assert_eq!(
+                event,
+                &BridgeEvent::Delivered(DeliveredEvent { sequence: 6 })
+            )
+        }
+        other => panic!("unexpected record {:?}", other),
+    }
+
+    let unknown = serde_json::to_value(&records[0]).unwrap();
+    assert_eq!(unknown["type"], "Unknown");
+    assert_eq!(unknown["data"]["data"], "2Uess");
+}
+
+#[test]
+fn test_record_json() {
+    let block = FixtureSource::new().get_block(1002).unwrap().unwrap();
+    let records = decode_block(&key(PROGRAM_ID), 1002, &block).unwrap();
+
+    let instruction = serde_json::to_value(&records[0]).unwrap();
+    assert_eq!(instruction["type"], "Instruction");
+    assert_eq!(instruction["data"]["accounts"][2], RECEIPT_5);
+    assert_eq!(
+        instruction["data"]["instruction"],
+        serde_json::json!({"type": "MarkDelivered", "data": {"sequence": 5}})
+    );
+
+    let event = serde_json::to_value(&records[1]).unwrap();
+    assert_eq!(event["type"], "Event");
+    assert_eq!(event["data"]["slot"], 1002);
+    assert_eq!(
+        event["data"]["event"],
+        serde_json::json!({"type": "Delivered", "data": {"sequence": 5}})
+    );
+}
+
+#[test]
+fn test_decode_instruction_round_trip() {
+    let instruction = BridgeInstruction::MarkDelivered(ReceiptData { sequence: 5 });
+    let mut data = instruction.pack();
+    assert_eq!(decode_instruction(&data).unwrap(), instruction);
+
+    data.push(0);
+    assert!(matches!(
+        decode_instruction(&data),
+        Err(IndexerError::Decode(_))
+    ));
+}
+
+#[test]
+fn test_resume_from_checkpoint() {
+    let mut checkpoint = MemoryCheckpoint::default();
+    let mut indexer = Indexer::new(
+        key(PROGRAM_ID),
+        FixtureSource::new(),
+        checkpoint.clone(),
+        1000,
+    )
+    .unwrap();
+
+    let mut records = vec![];
+    let processed = indexer
+        .poll(|record| {
+            records.push(record);
+            Ok(())
+        })
+        .unwrap();
+    // The slot with undecodable records is indexed like any other.
+    assert_eq!(processed, 4);
+    assert_eq!(records.len(), 10);
+    assert_eq!(indexer.next_slot(), 1004);
+
+    // A sink failure stops before the checkpoint moves past the failing slot.
+    checkpoint.save(Checkpoint { last_slot: 999 }).unwrap();
+    let mut indexer =
+        Indexer::new(key(PROGRAM_ID), FixtureSource::new(), checkpoint, 1000).unwrap();
+    let result = indexer.poll(|_| Err(IndexerError::Decode("sink is down".to_string())));
+    assert!(result.is_err());
+    assert_eq!(indexer.next_slot(), 1000);
+
+    // Nothing left to do once everything up to the latest slot is checkpointed.
+    let mut done = MemoryCheckpoint::default();
+    done.save(Checkpoint { last_slot: 1003 }).unwrap();
+    let mut indexer = Indexer::new(key(PROGRAM_ID), FixtureSource::new(), done, 0).unwrap();
+    assert_eq!(indexer.poll(|_| Ok(())).unwrap(), 0);
+}
+
+#[test]
+fn test_file_checkpoint() {
+    let path = std::env::temp_dir().join(format!("sisu-indexer-{}.json", std::process::id()));
+    let mut store = FileCheckpoint::new(&path);
+    assert_eq!(store.load().unwrap(), None);
+
+    store.save(Checkpoint { last_slot: 42 }).unwrap();
+    assert_eq!(
+        FileCheckpoint::new(&path).load().unwrap(),
+        Some(Checkpoint { last_slot: 42 })
+    );
+
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,20 @@
+//! Prints the IDL of the bridge program as JSON, see `sisu_bridge::idl`. The program id, if
+//! given, is recorded as the IDL's address.
+//!
+//!     sisu-bridge-idl [PROGRAM_ID] > idl.json
+
+use std::{env, process};
+
+use solana_program::pubkey::Pubkey;
+
+fn main() {
+    let address = env::args().nth(1).map(|arg| {
+        arg.parse::<Pubkey>().unwrap_or_else(|_| {
+            eprintln!("invalid program id {}", arg);
+            process::exit(1);
+        })
+    });
+
+    let idl = sisu_bridge::idl::idl(address.as_ref());
+    println!("{}", serde_json::to_string_pretty(&idl).unwrap());
+}
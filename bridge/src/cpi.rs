@@ -0,0 +1,149 @@
+//! Helpers for programs that bridge tokens on behalf of their users. Depend on the crate with
+//! the `cpi` feature so that its entrypoint is left out.
+
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    program::{get_return_data, invoke_signed},
+    pubkey::Pubkey,
+};
+
+use crate::state::{BridgeInstruction, BridgeReturnData, SendMessageData, TransferOutData};
+
+/// Accounts of `TransferOut`, in the order the bridge expects them.
+pub struct TransferOut<'a> {
+    pub bridge_program: AccountInfo<'a>,
+    /// Owner of `user_token`. Usually a pda of the calling program, which signs for it.
+    pub user: AccountInfo<'a>,
+    pub token_program: AccountInfo<'a>,
+    pub user_token: AccountInfo<'a>,
+    /// The bridge vault, the associated token account of `bridge_pda` for the mint.
+    pub bridge_token: AccountInfo<'a>,
+    pub bridge_pda: AccountInfo<'a>,
+    /// Receipt pda of the bridge's current `out_sequence`.
+    pub receipt: AccountInfo<'a>,
+    pub system_program: AccountInfo<'a>,
+    pub token_config: AccountInfo<'a>,
+    /// Funds the receipt. Defaults to `user`, which then has to hold lamports.
+    pub payer: Option<AccountInfo<'a>>,
+}
+
+impl<'a> TransferOut<'a> {
+    fn instruction(&self, data: TransferOutData) -> Instruction {
+        let mut accounts = vec![
+            AccountMeta::new(*self.user.key, true),
+            AccountMeta::new_readonly(*self.token_program.key, false),
+            AccountMeta::new(*self.user_token.key, false),
+            AccountMeta::new(*self.bridge_token.key, false),
+            AccountMeta::new(*self.bridge_pda.key, false),
+            AccountMeta::new(*self.receipt.key, false),
+            AccountMeta::new_readonly(*self.system_program.key, false),
+            AccountMeta::new(*self.token_config.key, false),
+        ];
+        if let Some(payer) = &self.payer {
+            accounts.push(AccountMeta::new(*payer.key, true));
+        }
+
+        Instruction {
+            program_id: *self.bridge_program.key,
+            accounts,
+            data: BridgeInstruction::TransferOut(data).pack(),
+        }
+    }
+
+    fn account_infos(&self) -> Vec<AccountInfo<'a>> {
+        let mut infos = vec![
+            self.user.clone(),
+            self.token_program.clone(),
+            self.user_token.clone(),
+            self.bridge_token.clone(),
+            self.bridge_pda.clone(),
+            self.receipt.clone(),
+            self.system_program.clone(),
+            self.token_config.clone(),
+            self.bridge_program.clone(),
+        ];
+        if let Some(payer) = &self.payer {
+            infos.push(payer.clone());
+        }
+        infos
+    }
+}
+
+/// Approves the bridge pda for exactly `data.amount` of `user_token` and locks it in the
+/// bridge. `signer_seeds` sign for `user`, and for `payer` if it is a pda too.
+pub fn transfer_out(
+    accounts: &TransferOut,
+    data: TransferOutData,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    invoke_signed(
+        &spl_token::instruction::approve(
+            accounts.token_program.key,
+            accounts.user_token.key,
+            accounts.bridge_pda.key,
+            accounts.user.key,
+            &[],
+            data.amount,
+        )?,
+        &[
+            accounts.user_token.clone(),
+            accounts.bridge_pda.clone(),
+            accounts.user.clone(),
+            accounts.token_program.clone(),
+        ],
+        signer_seeds,
+    )?;
+
+    invoke_signed(
+        &accounts.instruction(data),
+        &accounts.account_infos(),
+        signer_seeds,
+    )
+}
+
+/// Sequence of the receipt created by the last `transfer_out` into `bridge_program`, taken from
+/// its return data. Call it right after `transfer_out`, before any other CPI.
+pub fn transfer_out_sequence(bridge_program: &Pubkey) -> Option<u64> {
+    let (program_id, data) = get_return_data()?;
+    if program_id != *bridge_program {
+        return None;
+    }
+    match BridgeReturnData::unpack(&data) {
+        Ok(BridgeReturnData::Sequence(sequence)) => Some(sequence),
+        _ => None,
+    }
+}
+
+/// Accounts of `SendMessage`.
+pub struct SendMessage<'a> {
+    pub bridge_program: AccountInfo<'a>,
+    /// Reported as the sender of the message. Usually a pda of the calling program.
+    pub sender: AccountInfo<'a>,
+    pub bridge_pda: AccountInfo<'a>,
+}
+
+/// Sends `data.payload` to `data.target` on `data.chain_id`. `signer_seeds` sign for `sender`.
+pub fn send_message(
+    accounts: &SendMessage,
+    data: SendMessageData,
+    signer_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    invoke_signed(
+        &Instruction {
+            program_id: *accounts.bridge_program.key,
+            accounts: vec![
+                AccountMeta::new_readonly(*accounts.sender.key, true),
+                AccountMeta::new(*accounts.bridge_pda.key, false),
+            ],
+            data: BridgeInstruction::SendMessage(data).pack(),
+        },
+        &[
+            accounts.sender.clone(),
+            accounts.bridge_pda.clone(),
+            accounts.bridge_program.clone(),
+        ],
+        signer_seeds,
+    )
+}
@@ -1,9 +1,8 @@
 use crate::processor::Processor;
-use solana_program::{account_info::AccountInfo, entrypoint::ProgramResult, msg, pubkey::Pubkey};
+use solana_program::{
+    account_info::AccountInfo, entrypoint, entrypoint::ProgramResult, pubkey::Pubkey,
+};
 
-#[cfg(not(feature = "no-entrypoint"))]
-use solana_program::entrypoint;
-#[cfg(not(feature = "no-entrypoint"))]
 entrypoint!(process_instruction);
 
 fn process_instruction(
@@ -11,12 +10,5 @@ fn process_instruction(
     accounts: &[AccountInfo], // Data to read/write
     instruction_data: &[u8],  // Parameter Input
 ) -> ProgramResult {
-    msg!(
-        "process_instruction: {}: {} accounts, data={:?}",
-        program_id,
-        accounts.len(),
-        instruction_data
-    );
-
     Processor::process_instruction(program_id, accounts, instruction_data)
 }
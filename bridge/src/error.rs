@@ -8,6 +8,81 @@ pub enum BridgeError {
 
     #[error("Not a bridge admin.")]
     NotAnAdmin,
+
+    #[error("Receipt has already been delivered or refunded.")]
+    ReceiptNotPending,
+
+    #[error("Bridge is paused.")]
+    BridgePaused,
+
+    #[error("Token is not enabled on the bridge.")]
+    TokenNotEnabled,
+
+    #[error("Unknown instruction.")]
+    InvalidInstruction,
+
+    #[error("Cannot decode instruction payload.")]
+    MalformedInstruction,
+
+    #[error("Instruction data has trailing bytes.")]
+    TrailingInstructionData,
+
+    #[error("Payload exceeds the maximum length.")]
+    PayloadTooLarge,
+
+    #[error("Message has already been delivered.")]
+    MessageAlreadyDelivered,
+
+    #[error("Spender of a previous epoch has expired.")]
+    SpenderExpired,
+
+    #[error("Operation must be queued behind the timelock.")]
+    TimelockRequired,
+
+    #[error("Queued operation is not ready yet.")]
+    OperationNotReady,
+
+    #[error("Not the bridge guardian.")]
+    NotAGuardian,
+
+    #[error("Bridge is decommissioned.")]
+    BridgeDecommissioned,
+
+    #[error("Bridge has not been decommissioned.")]
+    NotDecommissioned,
+
+    #[error("Token vaults must be swept first.")]
+    TokensRemaining,
+
+    #[error("Release exceeds the tokens locked for this mint.")]
+    ExceedsLocked,
+
+    #[error("Return data is malformed or has an unknown version.")]
+    MalformedReturnData,
+
+    #[error("Instruction must be called at the top level or by an allowed program.")]
+    UntrustedCaller,
+
+    #[error("The list of allowed CPI callers is full.")]
+    TooManyCpiCallers,
+
+    #[error("Signer does not hold a role the instruction requires.")]
+    MissingRole,
+
+    #[error("Relayer and guardian keys cannot hold config roles.")]
+    RoleConflict,
+
+    #[error("The list of role holders is full.")]
+    TooManyRoleHolders,
+
+    #[error("Not a bridge spender.")]
+    NotASpender,
+
+    #[error("Bridge account has an older layout, migrate it first.")]
+    MigrationRequired,
+
+    #[error("Bridge account already has the current layout.")]
+    AlreadyMigrated,
 }
 
 impl From<BridgeError> for ProgramError {
@@ -0,0 +1,1269 @@
+//! Interface description of the program in the Anchor IDL format (spec 0.1.0), behind the `idl`
+//! feature. Clients generate their instruction, account and event layouts from it instead of
+//! copying them; `idl.json` at the crate root is the checked-in output of the `sisu-bridge-idl`
+//! binary.
+//!
+//! The bridge is not an Anchor program, so a few things differ from what Anchor generates:
+//! - discriminators are the one-byte borsh enum tags, accounts have none at all,
+//! - `VarU64` is a u64 alias with its own `leb128` serialization,
+//! - `returns` is `BridgeReturnData`, written with `RETURN_DATA_VERSION` in front,
+//! - accounts marked `repeated` are passed once per item of the batch, in order, and may be
+//!   absent when the batch is empty. Consumers that do not know the field can read the
+//!   instruction docs, which say the same.
+
+use serde::{ser::SerializeMap, Serialize, Serializer};
+use solana_program::{pubkey::Pubkey, system_program, sysvar};
+
+use crate::error::BridgeError;
+
+pub const IDL_SPEC: &str = "0.1.0";
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Idl {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    pub metadata: IdlMetadata,
+    pub instructions: Vec<IdlInstruction>,
+    pub accounts: Vec<IdlAccountDef>,
+    pub events: Vec<IdlEvent>,
+    pub errors: Vec<IdlErrorCode>,
+    pub types: Vec<IdlTypeDef>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct IdlMetadata {
+    pub name: &'static str,
+    pub version: &'static str,
+    pub spec: &'static str,
+    pub description: &'static str,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct IdlInstruction {
+    pub name: &'static str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub docs: Vec<&'static str>,
+    pub discriminator: Vec<u8>,
+    pub accounts: Vec<IdlAccount>,
+    pub args: Vec<IdlField>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub returns: Option<IdlType>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct IdlAccount {
+    pub name: &'static str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub docs: Vec<&'static str>,
+    #[serde(skip_serializing_if = "is_false")]
+    pub writable: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub signer: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub optional: bool,
+    #[serde(skip_serializing_if = "is_false")]
+    pub repeated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct IdlAccountDef {
+    pub name: &'static str,
+    pub discriminator: Vec<u8>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct IdlEvent {
+    pub name: &'static str,
+    pub discriminator: Vec<u8>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct IdlErrorCode {
+    pub code: u32,
+    pub name: String,
+    pub msg: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct IdlField {
+    pub name: &'static str,
+    #[serde(rename = "type")]
+    pub ty: IdlType,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct IdlTypeDef {
+    pub name: &'static str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub docs: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub serialization: Option<IdlSerialization>,
+    #[serde(rename = "type")]
+    pub ty: IdlTypeDefTy,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum IdlSerialization {
+    Custom(&'static str),
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum IdlTypeDefTy {
+    Struct { fields: Vec<IdlField> },
+    Enum { variants: Vec<IdlVariant> },
+    Type { alias: IdlType },
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct IdlVariant {
+    pub name: &'static str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<IdlType>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IdlType {
+    Bool,
+    U8,
+    U32,
+    U64,
+    I64,
+    String,
+    Bytes,
+    Pubkey,
+    Vec(Box<IdlType>),
+    Array(Box<IdlType>, usize),
+    Defined(&'static str),
+}
+
+impl Serialize for IdlType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Defined {
+            name: &'static str,
+        }
+
+        let primitive = match self {
+            IdlType::Bool => "bool",
+            IdlType::U8 => "u8",
+            IdlType::U32 => "u32",
+            IdlType::U64 => "u64",
+            IdlType::I64 => "i64",
+            IdlType::String => "string",
+            IdlType::Bytes => "bytes",
+            IdlType::Pubkey => "pubkey",
+            IdlType::Vec(ty) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("vec", ty)?;
+                return map.end();
+            }
+            IdlType::Array(ty, len) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("array", &(ty, len))?;
+                return map.end();
+            }
+            IdlType::Defined(name) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("defined", &Defined { name })?;
+                return map.end();
+            }
+        };
+        serializer.serialize_str(primitive)
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+impl Idl {
+    pub fn type_def(&self, name: &str) -> Option<&IdlTypeDef> {
+        self.types.iter().find(|ty| ty.name == name)
+    }
+
+    pub fn instruction(&self, discriminator: u8) -> Option<&IdlInstruction> {
+        self.instructions
+            .iter()
+            .find(|ix| ix.discriminator == [discriminator])
+    }
+}
+
+impl IdlAccount {
+    fn new(name: &'static str) -> Self {
+        IdlAccount {
+            name,
+            ..IdlAccount::default()
+        }
+    }
+
+    fn writable(mut self) -> Self {
+        self.writable = true;
+        self
+    }
+
+    fn signer(mut self) -> Self {
+        self.signer = true;
+        self
+    }
+
+    fn optional(mut self) -> Self {
+        self.optional = true;
+        self
+    }
+
+    fn repeated(mut self) -> Self {
+        self.repeated = true;
+        self
+    }
+
+    fn address(mut self, address: Pubkey) -> Self {
+        self.address = Some(address.to_string());
+        self
+    }
+
+    fn docs(mut self, docs: &'static str) -> Self {
+        self.docs.push(docs);
+        self
+    }
+}
+
+fn field(name: &'static str, ty: IdlType) -> IdlField {
+    IdlField { name, ty }
+}
+
+fn defined(name: &'static str) -> IdlType {
+    IdlType::Defined(name)
+}
+
+fn vec(ty: IdlType) -> IdlType {
+    IdlType::Vec(Box::new(ty))
+}
+
+fn array(ty: IdlType, len: usize) -> IdlType {
+    IdlType::Array(Box::new(ty), len)
+}
+
+fn struct_def(name: &'static str, fields: Vec<IdlField>) -> IdlTypeDef {
+    IdlTypeDef {
+        name,
+        docs: vec![],
+        serialization: None,
+        ty: IdlTypeDefTy::Struct { fields },
+    }
+}
+
+fn enum_def(name: &'static str, variants: Vec<(&'static str, Option<IdlType>)>) -> IdlTypeDef {
+    let variants = variants
+        .into_iter()
+        .map(|(name, ty)| IdlVariant {
+            name,
+            fields: ty.into_iter().collect(),
+        })
+        .collect();
+    IdlTypeDef {
+        name,
+        docs: vec![],
+        serialization: None,
+        ty: IdlTypeDefTy::Enum { variants },
+    }
+}
+
+/// Layouts of `state`, in the order they are declared there.
+fn types() -> Vec<IdlTypeDef> {
+    use IdlType::*;
+
+    let transfer_out_event = vec![
+        field("sequence", U64),
+        field("sender", Pubkey),
+        field("mint", Pubkey),
+        field("token_address", String),
+        field("amount", U64),
+        field("chain_id", U64),
+        field("recipient", String),
+    ];
+
+    vec![
+        struct_def(
+            "BridgeStateV0",
+            vec![
+                field("version", U8),
+                field("bump", U8),
+                field("admin", Pubkey),
+                field("spenders", array(Pubkey, 2)),
+                field("spender_index", U8),
+                field("out_sequence", U64),
+                field("paused", Bool),
+                field("message_sequence", U64),
+                field("spender_epoch", U64),
+                field("previous_valid_until_slot", U64),
+                field("previous_valid_until", I64),
+                field("guardian", Pubkey),
+                field("timelock_delay", U64),
+                field("treasury", Pubkey),
+                field("token_count", U32),
+                field("cpi_callers", array(Pubkey, crate::state::MAX_CPI_CALLERS)),
+                field(
+                    "role_holders",
+                    array(Pubkey, crate::state::MAX_ROLE_HOLDERS),
+                ),
+                field("role_masks", array(U8, crate::state::MAX_ROLE_HOLDERS)),
+            ],
+        ),
+        struct_def(
+            "TokenConfig",
+            vec![
+                field("mint", Pubkey),
+                field("enabled", Bool),
+                field("bump", U8),
+                field("locked", U64),
+                field("released", U64),
+            ],
+        ),
+        struct_def(
+            "TransferOutData",
+            vec![
+                field("amount", U64),
+                field("token_address", String),
+                field("chain_id", U64),
+                field("recipient", String),
+            ],
+        ),
+        enum_def(
+            "ReceiptStatus",
+            vec![("Pending", None), ("Delivered", None), ("Refunded", None)],
+        ),
+        struct_def(
+            "TransferReceipt",
+            vec![
+                field("sequence", U64),
+                field("sender", Pubkey),
+                field("source", Pubkey),
+                field("mint", Pubkey),
+                field("amount", U64),
+                field("status", defined("ReceiptStatus")),
+                field("bump", U8),
+            ],
+        ),
+        struct_def(
+            "DeliveryRecord",
+            vec![
+                field("source_chain_id", U64),
+                field("nonce", U64),
+                field("receiver_program", Pubkey),
+                field("bump", U8),
+            ],
+        ),
+        struct_def(
+            "PendingOperation",
+            vec![
+                field("id", U64),
+                field("eta", I64),
+                field("bump", U8),
+                field("operation", defined("AdminOperation")),
+            ],
+        ),
+        enum_def(
+            "AdminOperation",
+            vec![
+                ("ActivateSpender", Some(defined("ActivateSpenderData"))),
+                ("RemoveSpender", Some(defined("AddSpenderData"))),
+                ("ChangeAdmin", Some(defined("ChangeAdminData"))),
+                ("SetTimelock", Some(defined("SetTimelockData"))),
+                ("Decommission", Some(defined("DecommissionData"))),
+                ("SetCpiCaller", Some(defined("SetCpiCallerData"))),
+                ("SetRole", Some(defined("SetRoleData"))),
+            ],
+        ),
+        struct_def("ReceiptData", vec![field("sequence", U64)]),
+        struct_def(
+            "TransferOutBatchData",
+            vec![field("items", vec(defined("TransferOutData")))],
+        ),
+        struct_def(
+            "TransferOutNativeData",
+            vec![
+                field("amount", U64),
+                field("chain_id", U64),
+                field("recipient", String),
+            ],
+        ),
+        struct_def(
+            "SendMessageData",
+            vec![
+                field("chain_id", U64),
+                field("target", String),
+                field("payload", Bytes),
+            ],
+        ),
+        struct_def(
+            "TransferOutWithPayloadData",
+            vec![
+                field("transfer", defined("TransferOutData")),
+                field("payload", Bytes),
+            ],
+        ),
+        struct_def(
+            "DeliverMessageData",
+            vec![
+                field("source_chain_id", U64),
+                field("nonce", U64),
+                field("sender", String),
+                field("payload", Bytes),
+                field("amount", U64),
+            ],
+        ),
+        struct_def(
+            "TransferInData",
+            vec![field("nonce", U64), field("amounts", vec(U64))],
+        ),
+        IdlTypeDef {
+            name: "VarU64",
+            docs: vec!["LEB128 encoded u64: 7 bits per byte, low bits first, shortest form only."],
+            serialization: Some(IdlSerialization::Custom("leb128")),
+            ty: IdlTypeDefTy::Type { alias: U64 },
+        },
+        struct_def(
+            "CompactTransferItem",
+            vec![
+                field("vault_index", U8),
+                field("receiver_index", U8),
+                field("config_index", U8),
+                field("amount", defined("VarU64")),
+            ],
+        ),
+        struct_def(
+            "TransferInCompactData",
+            vec![
+                field("nonce", U64),
+                field("items", vec(defined("CompactTransferItem"))),
+            ],
+        ),
+        enum_def(
+            "BridgeEvent",
+            vec![
+                ("TransferOut", Some(defined("TransferOutEvent"))),
+                ("Refunded", Some(defined("RefundedEvent"))),
+                ("Delivered", Some(defined("DeliveredEvent"))),
+                ("MessageSent", Some(defined("MessageEvent"))),
+                (
+                    "TransferOutWithPayload",
+                    Some(defined("TransferOutWithPayloadEvent")),
+                ),
+                ("MessageDelivered", Some(defined("MessageDeliveredEvent"))),
+                ("SpenderActivated", Some(defined("SpenderActivatedEvent"))),
+                ("OperationQueued", Some(defined("OperationQueuedEvent"))),
+                ("OperationExecuted", Some(defined("OperationEvent"))),
+                ("OperationCancelled", Some(defined("OperationEvent"))),
+                ("VaultSwept", Some(defined("VaultSweptEvent"))),
+                ("Reconciled", Some(defined("ReconciledEvent"))),
+            ],
+        ),
+        struct_def(
+            "ReconciledEvent",
+            vec![
+                field("mint", Pubkey),
+                field("locked", U64),
+                field("released", U64),
+                field("balance", U64),
+                field("surplus", U64),
+                field("shortfall", U64),
+            ],
+        ),
+        struct_def(
+            "VaultSweptEvent",
+            vec![
+                field("mint", Pubkey),
+                field("amount", U64),
+                field("treasury", Pubkey),
+            ],
+        ),
+        struct_def(
+            "OperationQueuedEvent",
+            vec![
+                field("id", U64),
+                field("eta", I64),
+                field("operation", defined("AdminOperation")),
+            ],
+        ),
+        struct_def("OperationEvent", vec![field("id", U64)]),
+        struct_def("TransferOutEvent", transfer_out_event),
+        struct_def(
+            "RefundedEvent",
+            vec![
+                field("sequence", U64),
+                field("destination", Pubkey),
+                field("mint", Pubkey),
+                field("amount", U64),
+            ],
+        ),
+        struct_def("DeliveredEvent", vec![field("sequence", U64)]),
+        struct_def(
+            "MessageEvent",
+            vec![
+                field("sequence", U64),
+                field("sender", Pubkey),
+                field("chain_id", U64),
+                field("target", String),
+                field("payload", Bytes),
+            ],
+        ),
+        struct_def(
+            "TransferOutWithPayloadEvent",
+            vec![
+                field("transfer", defined("TransferOutEvent")),
+                field("payload", Bytes),
+            ],
+        ),
+        struct_def(
+            "SpenderActivatedEvent",
+            vec![
+                field("epoch", U64),
+                field("spender", Pubkey),
+                field("previous", Pubkey),
+                field("previous_valid_until_slot", U64),
+                field("previous_valid_until", I64),
+            ],
+        ),
+        struct_def(
+            "MessageDeliveredEvent",
+            vec![
+                field("source_chain_id", U64),
+                field("nonce", U64),
+                field("receiver_program", Pubkey),
+                field("amount", U64),
+            ],
+        ),
+        struct_def("AddSpenderData", vec![field("spender", Pubkey)]),
+        enum_def(
+            "SpenderOverlap",
+            vec![("Slots", Some(U64)), ("Seconds", Some(U64))],
+        ),
+        struct_def(
+            "ActivateSpenderData",
+            vec![
+                field("spender", Pubkey),
+                field("overlap", defined("SpenderOverlap")),
+            ],
+        ),
+        struct_def("ChangeAdminData", vec![field("new_admin", Pubkey)]),
+        struct_def("SetPausedData", vec![field("paused", Bool)]),
+        struct_def(
+            "SetTimelockData",
+            vec![field("guardian", Pubkey), field("delay", U64)],
+        ),
+        struct_def(
+            "QueueOperationData",
+            vec![
+                field("id", U64),
+                field("operation", defined("AdminOperation")),
+            ],
+        ),
+        struct_def("DecommissionData", vec![field("treasury", Pubkey)]),
+        struct_def("OperationData", vec![field("id", U64)]),
+        struct_def(
+            "SetCpiCallerData",
+            vec![field("program", Pubkey), field("allowed", Bool)],
+        ),
+        enum_def(
+            "Role",
+            vec![
+                ("Admin", None),
+                ("Guardian", None),
+                ("TokenRegistrar", None),
+                ("Relayer", None),
+            ],
+        ),
+        struct_def(
+            "SetRoleData",
+            vec![
+                field("holder", Pubkey),
+                field("role", defined("Role")),
+                field("granted", Bool),
+            ],
+        ),
+        struct_def("RegisterTokenData", vec![field("enabled", Bool)]),
+        enum_def(
+            "BridgeQuery",
+            vec![
+                ("Config", None),
+                ("Spenders", None),
+                ("Token", None),
+            ],
+        ),
+        enum_def(
+            "BridgeReturnData",
+            vec![
+                ("Sequence", Some(U64)),
+                ("Config", Some(defined("ConfigInfo"))),
+                ("Spenders", Some(defined("SpendersInfo"))),
+                ("Token", Some(defined("TokenInfo"))),
+            ],
+        ),
+        struct_def(
+            "ConfigInfo",
+            vec![
+                field("admin", Pubkey),
+                field("guardian", Pubkey),
+                field("paused", Bool),
+                field("treasury", Pubkey),
+                field("timelock_delay", U64),
+                field("token_count", U32),
+                field("out_sequence", U64),
+                field("message_sequence", U64),
+            ],
+        ),
+        struct_def(
+            "SpendersInfo",
+            vec![
+                field("epoch", U64),
+                field("current", Pubkey),
+                field("previous", Pubkey),
+                field("previous_valid_until_slot", U64),
+                field("previous_valid_until", I64),
+                field("previous_active", Bool),
+            ],
+        ),
+        struct_def(
+            "TokenInfo",
+            vec![
+                field("mint", Pubkey),
+                field("enabled", Bool),
+                field("locked", U64),
+                field("released", U64),
+            ],
+        ),
+    ]
+}
+
+/// An instruction: its name, docs, accounts and the type of its payload, if any. The payload
+/// fields become the args, except for enums which are a single arg.
+struct InstructionDef {
+    name: &'static str,
+    docs: &'static str,
+    accounts: Vec<IdlAccount>,
+    payload: Option<&'static str>,
+    returns: bool,
+}
+
+fn ix(
+    name: &'static str,
+    docs: &'static str,
+    accounts: Vec<IdlAccount>,
+    payload: Option<&'static str>,
+) -> InstructionDef {
+    InstructionDef {
+        name,
+        docs,
+        accounts,
+        payload,
+        returns: false,
+    }
+}
+
+fn returning(mut def: InstructionDef) -> InstructionDef {
+    def.returns = true;
+    def
+}
+
+fn bridge() -> IdlAccount {
+    IdlAccount::new("bridge").docs("The bridge pda, seeds [\"SisuBridge\"].")
+}
+
+fn system() -> IdlAccount {
+    IdlAccount::new("system_program").address(system_program::id())
+}
+
+/// The token config of native SOL, seeds ["SisuBridgeToken", NATIVE_SOL_MINT].
+fn native_config() -> IdlAccount {
+    IdlAccount::new("native_config")
+}
+
+fn token_program() -> IdlAccount {
+    IdlAccount::new("token_program")
+}
+
+/// Last account of the instructions that only allowed programs may call through CPI.
+fn instructions_sysvar() -> IdlAccount {
+    IdlAccount::new("instructions_sysvar").address(sysvar::instructions::id())
+}
+
+/// `signer` and the bridge, the accounts of the admin instructions that only change the state.
+fn admin_accounts(signer: &'static str) -> Vec<IdlAccount> {
+    vec![
+        IdlAccount::new(signer).signer(),
+        bridge().writable(),
+        instructions_sysvar(),
+    ]
+}
+
+/// Every instruction in tag order, see `BridgeInstruction`.
+fn instruction_defs() -> Vec<InstructionDef> {
+    let transfer_out_accounts = || {
+        vec![
+            IdlAccount::new("user").writable().signer(),
+            token_program(),
+            IdlAccount::new("user_token").writable(),
+            IdlAccount::new("vault")
+                .writable()
+                .docs("The associated token account of the bridge pda for the mint."),
+            bridge().writable(),
+            IdlAccount::new("receipt").writable(),
+            system(),
+            IdlAccount::new("token_config").writable(),
+            IdlAccount::new("payer")
+                .writable()
+                .signer()
+                .optional()
+                .docs("Pays for the receipt instead of the user."),
+        ]
+    };
+    let operation_accounts = |signer: &'static str| {
+        vec![
+            IdlAccount::new(signer).writable().signer(),
+            bridge().writable(),
+            IdlAccount::new("operation").writable(),
+            instructions_sysvar(),
+        ]
+    };
+    let transfer_in_native_accounts = vec![
+        IdlAccount::new("spender").signer(),
+        bridge(),
+        IdlAccount::new("native_vault").writable(),
+        system(),
+        native_config().writable(),
+        IdlAccount::new("receiver")
+            .writable()
+            .repeated()
+            .docs("One per amount."),
+        instructions_sysvar(),
+    ];
+
+    vec![
+        ix(
+            "initialize",
+            "Creates the bridge pda, with the signer as admin.",
+            vec![
+                IdlAccount::new("admin").writable().signer(),
+                bridge().writable(),
+                system(),
+            ],
+            None,
+        ),
+        returning(ix(
+            "transfer_out",
+            "Locks tokens in the vault and records them in a receipt at the next sequence.",
+            transfer_out_accounts(),
+            Some("TransferOutData"),
+        )),
+        ix(
+            "transfer_in",
+            "Releases tokens to receivers, the vault, receiver and token config accounts are \
+             passed once per amount.",
+            vec![
+                IdlAccount::new("spender").signer(),
+                token_program(),
+                bridge(),
+                IdlAccount::new("vault").writable().repeated(),
+                IdlAccount::new("receiver").writable().repeated(),
+                IdlAccount::new("token_config").writable().repeated(),
+                instructions_sysvar(),
+            ],
+            Some("TransferInData"),
+        ),
+        ix(
+            "add_spender",
+            "Makes the spender current at once, the previous one expires after this slot. Same \
+             as activate_spender with an overlap of Slots(0).",
+            admin_accounts("admin"),
+            Some("AddSpenderData"),
+        ),
+        ix(
+            "remove_spender",
+            "",
+            admin_accounts("admin"),
+            Some("AddSpenderData"),
+        ),
+        ix(
+            "change_admin",
+            "",
+            admin_accounts("admin"),
+            Some("ChangeAdminData"),
+        ),
+        returning(ix(
+            "transfer_out_native",
+            "Locks lamports in the native vault and records them in a receipt.",
+            vec![
+                IdlAccount::new("user").writable().signer(),
+                bridge().writable(),
+                IdlAccount::new("native_vault").writable(),
+                system(),
+                IdlAccount::new("receipt").writable(),
+                native_config().writable(),
+            ],
+            Some("TransferOutNativeData"),
+        )),
+        ix(
+            "transfer_in_native",
+            "Releases lamports from the native vault, one receiver per amount.",
+            transfer_in_native_accounts,
+            Some("TransferInData"),
+        ),
+        returning(ix(
+            "transfer_out_batch",
+            "Several transfer_out at once, the user token, vault, receipt and token config \
+             accounts are passed once per item.",
+            vec![
+                IdlAccount::new("user").writable().signer(),
+                token_program(),
+                bridge().writable(),
+                system(),
+                IdlAccount::new("user_token").writable().repeated(),
+                IdlAccount::new("vault")
+                    .writable()
+                    .repeated()
+                    .docs("The associated token account of the bridge pda for the mint."),
+                IdlAccount::new("receipt").writable().repeated(),
+                IdlAccount::new("token_config").writable().repeated(),
+            ],
+            Some("TransferOutBatchData"),
+        )),
+        ix(
+            "transfer_in_compact",
+            "transfer_in with the vault, receiver and token config of each item given as \
+             indexes into the accounts that follow the bridge.",
+            vec![
+                IdlAccount::new("spender").signer(),
+                token_program(),
+                bridge(),
+                IdlAccount::new("item_accounts")
+                    .writable()
+                    .repeated()
+                    .docs("Vaults, receivers and token configs, in any order."),
+                instructions_sysvar(),
+            ],
+            Some("TransferInCompactData"),
+        ),
+        ix(
+            "refund",
+            "Returns the amount of a pending receipt to its source. For native SOL the vault is \
+             the native vault, the transfer program the system program, and the token config \
+             the native config.",
+            vec![
+                IdlAccount::new("spender").signer(),
+                bridge(),
+                IdlAccount::new("receipt").writable(),
+                IdlAccount::new("vault").writable(),
+                IdlAccount::new("source").writable(),
+                IdlAccount::new("transfer_program"),
+                IdlAccount::new("token_config").writable(),
+                instructions_sysvar(),
+            ],
+            Some("ReceiptData"),
+        ),
+        ix(
+            "mark_delivered",
+            "",
+            vec![
+                IdlAccount::new("spender").signer(),
+                bridge(),
+                IdlAccount::new("receipt").writable(),
+                instructions_sysvar(),
+            ],
+            Some("ReceiptData"),
+        ),
+        ix(
+            "set_paused",
+            "Guardians may pause as well, only admins resume.",
+            admin_accounts("admin"),
+            Some("SetPausedData"),
+        ),
+        ix(
+            "register_token",
+            "Creates or updates the token config of a mint, as an admin or a token registrar. \
+             A new config counts from zero. Native SOL is registered with the system program as \
+             the mint, and a new native config tops the native vault up to its rent reserve.",
+            vec![
+                IdlAccount::new("admin").writable().signer(),
+                bridge().writable(),
+                IdlAccount::new("token_config").writable(),
+                IdlAccount::new("mint"),
+                system(),
+                IdlAccount::new("native_vault")
+                    .writable()
+                    .optional()
+                    .docs("Only for native SOL."),
+                instructions_sysvar(),
+            ],
+            Some("RegisterTokenData"),
+        ),
+        ix(
+            "send_message",
+            "",
+            vec![IdlAccount::new("sender").signer(), bridge().writable()],
+            Some("SendMessageData"),
+        ),
+        returning(ix(
+            "transfer_out_with_payload",
+            "transfer_out with a payload for the recipient contract.",
+            transfer_out_accounts(),
+            Some("TransferOutWithPayloadData"),
+        )),
+        ix(
+            "deliver_message",
+            "Delivers a message to the receiver program. The token accounts are only passed \
+             with a non-zero amount.",
+            vec![
+                IdlAccount::new("spender").writable().signer(),
+                bridge(),
+                IdlAccount::new("delivery_record").writable(),
+                IdlAccount::new("message_authority"),
+                system(),
+                IdlAccount::new("receiver_program"),
+                token_program().optional(),
+                IdlAccount::new("vault").writable().optional(),
+                IdlAccount::new("receiver_token").writable().optional(),
+                IdlAccount::new("token_config").writable().optional(),
+                IdlAccount::new("receiver_accounts")
+                    .repeated()
+                    .docs("Passed on to the receiver program, with the flags it needs."),
+                instructions_sysvar(),
+            ],
+            Some("DeliverMessageData"),
+        ),
+        ix(
+            "activate_spender",
+            "",
+            admin_accounts("admin"),
+            Some("ActivateSpenderData"),
+        ),
+        ix(
+            "set_timelock",
+            "",
+            admin_accounts("admin"),
+            Some("SetTimelockData"),
+        ),
+        ix(
+            "queue_operation",
+            "",
+            vec![
+                IdlAccount::new("admin").writable().signer(),
+                bridge(),
+                IdlAccount::new("operation").writable(),
+                system(),
+                instructions_sysvar(),
+            ],
+            Some("QueueOperationData"),
+        ),
+        ix(
+            "execute_operation",
+            "",
+            operation_accounts("admin"),
+            Some("OperationData"),
+        ),
+        ix(
+            "cancel_operation",
+            "",
+            operation_accounts("guardian"),
+            Some("OperationData"),
+        ),
+        ix(
+            "decommission",
+            "",
+            admin_accounts("admin"),
+            Some("DecommissionData"),
+        ),
+        ix(
+            "sweep_vault",
+            "Moves a token account of the bridge pda to the treasury and closes it. Sweeping the \
+             associated token account also closes the token config of a registered mint.",
+            vec![
+                IdlAccount::new("admin").signer(),
+                bridge().writable(),
+                token_program(),
+                IdlAccount::new("token_config").writable(),
+                IdlAccount::new("vault")
+                    .writable()
+                    .docs("Any token account of the bridge pda."),
+                IdlAccount::new("treasury_token").writable(),
+                IdlAccount::new("treasury").writable(),
+                instructions_sysvar(),
+            ],
+            None,
+        ),
+        ix(
+            "close_bridge",
+            "Sweeps the native vault and leaves an empty account in place of the bridge pda, so \
+             that it cannot be initialized again.",
+            vec![
+                IdlAccount::new("admin").signer(),
+                bridge().writable(),
+                IdlAccount::new("native_vault").writable(),
+                IdlAccount::new("treasury").writable(),
+                system(),
+                native_config()
+                    .writable()
+                    .docs("Closed along with the bridge, if native SOL is registered."),
+                instructions_sysvar(),
+            ],
+            None,
+        ),
+        ix(
+            "reconcile",
+            "Compares a vault with the counters of its token config. The vault of native SOL is \
+             the native vault.",
+            vec![
+                IdlAccount::new("admin").signer(),
+                bridge(),
+                IdlAccount::new("token_config"),
+                IdlAccount::new("vault"),
+                instructions_sysvar(),
+            ],
+            None,
+        ),
+        returning(ix(
+            "query",
+            "Read-only, answered with return data. The token config is only passed to Token \
+             queries.",
+            vec![bridge(), IdlAccount::new("token_config").optional()],
+            Some("BridgeQuery"),
+        )),
+        ix(
+            "set_cpi_caller",
+            "",
+            admin_accounts("admin"),
+            Some("SetCpiCallerData"),
+        ),
+        ix(
+            "set_role",
+            "Relayer and guardian keys cannot be granted config roles.",
+            admin_accounts("admin"),
+            Some("SetRoleData"),
+        ),
+        ix(
+            "migrate",
+            "Grows a bridge account of an older version to the current layout. The admin pays the \
+             rent of the new bytes, and leaves the spender slots if it held any.",
+            vec![
+                IdlAccount::new("admin").writable().signer(),
+                bridge().writable(),
+                system(),
+                instructions_sysvar(),
+            ],
+            None,
+        ),
+    ]
+}
+
+/// The error with the next code. The match is exhaustive so that a new error cannot be left out
+/// of the IDL.
+fn next_error(error: BridgeError) -> Option<BridgeError> {
+    use BridgeError::*;
+
+    match error {
+        NotImplemented => Some(NotAnAdmin),
+        NotAnAdmin => Some(ReceiptNotPending),
+        ReceiptNotPending => Some(BridgePaused),
+        BridgePaused => Some(TokenNotEnabled),
+        TokenNotEnabled => Some(InvalidInstruction),
+        InvalidInstruction => Some(MalformedInstruction),
+        MalformedInstruction => Some(TrailingInstructionData),
+        TrailingInstructionData => Some(PayloadTooLarge),
+        PayloadTooLarge => Some(MessageAlreadyDelivered),
+        MessageAlreadyDelivered => Some(SpenderExpired),
+        SpenderExpired => Some(TimelockRequired),
+        TimelockRequired => Some(OperationNotReady),
+        OperationNotReady => Some(NotAGuardian),
+        NotAGuardian => Some(BridgeDecommissioned),
+        BridgeDecommissioned => Some(NotDecommissioned),
+        NotDecommissioned => Some(TokensRemaining),
+        TokensRemaining => Some(ExceedsLocked),
+        ExceedsLocked => Some(MalformedReturnData),
+        MalformedReturnData => Some(UntrustedCaller),
+        UntrustedCaller => Some(TooManyCpiCallers),
+        TooManyCpiCallers => Some(MissingRole),
+        MissingRole => Some(RoleConflict),
+        RoleConflict => Some(TooManyRoleHolders),
+        TooManyRoleHolders => Some(NotASpender),
+        NotASpender => Some(MigrationRequired),
+        MigrationRequired => Some(AlreadyMigrated),
+        AlreadyMigrated => None,
+    }
+}
+
+/// The IDL of the program, deployed at `address` if given.
+pub fn idl(address: Option<&Pubkey>) -> Idl {
+    let types = types();
+    let find = |name: &str| types.iter().find(|ty| ty.name == name).unwrap();
+
+    let instructions = instruction_defs()
+        .into_iter()
+        .enumerate()
+        .map(|(tag, def)| {
+            let args = match def.payload.map(|name| &find(name).ty) {
+                None => vec![],
+                Some(IdlTypeDefTy::Struct { fields }) => fields.clone(),
+                Some(_) => vec![field("query", defined(def.payload.unwrap()))],
+            };
+            IdlInstruction {
+                name: def.name,
+                docs: Some(def.docs)
+                    .filter(|docs| !docs.is_empty())
+                    .into_iter()
+                    .collect(),
+                discriminator: vec![tag as u8],
+                accounts: def.accounts,
+                args,
+                returns: Some(defined("BridgeReturnData")).filter(|_| def.returns),
+            }
+        })
+        .collect();
+
+    let accounts = [
+        "BridgeStateV0",
+        "TokenConfig",
+        "TransferReceipt",
+        "DeliveryRecord",
+        "PendingOperation",
+    ]
+    .into_iter()
+    .map(|name| IdlAccountDef {
+        name,
+        discriminator: vec![],
+    })
+    .collect();
+
+    let events = match &find("BridgeEvent").ty {
+        IdlTypeDefTy::Enum { variants } => variants
+            .iter()
+            .enumerate()
+            .map(|(tag, variant)| IdlEvent {
+                name: variant.name,
+                discriminator: vec![tag as u8],
+            })
+            .collect::<Vec<_>>(),
+        _ => unreachable!(),
+    };
+    // Anchor looks an event's fields up under its own name.
+    let event_types: Vec<IdlTypeDef> = match &find("BridgeEvent").ty {
+        IdlTypeDefTy::Enum { variants } => variants
+            .iter()
+            .map(|variant| match &variant.fields[..] {
+                [IdlType::Defined(payload)] => IdlTypeDef {
+                    name: variant.name,
+                    ..find(payload).clone()
+                },
+                _ => unreachable!(),
+            })
+            .collect(),
+        _ => unreachable!(),
+    };
+
+    let errors = std::iter::successors(Some(BridgeError::NotImplemented), |error| {
+        next_error(*error)
+    })
+    .map(|error| IdlErrorCode {
+        code: error as u32,
+        name: format!("{:?}", error),
+        msg: error.to_string(),
+    })
+    .collect();
+
+    Idl {
+        address: address.map(|address| address.to_string()),
+        metadata: IdlMetadata {
+            name: "sisu_bridge",
+            version: env!("CARGO_PKG_VERSION"),
+            spec: IDL_SPEC,
+            description: "Sisu token and message bridge",
+        },
+        instructions,
+        accounts,
+        events,
+        errors,
+        types: types.iter().cloned().chain(event_types).collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::BridgeInstruction;
+
+    // `idl.json` is what the `sisu-bridge-idl` binary prints, regenerate it after changing the
+    // interface.
+    #[test]
+    fn test_idl_up_to_date() {
+        let expected: serde_json::Value =
+            serde_json::from_str(include_str!("../idl.json")).unwrap();
+        assert_eq!(
+            serde_json::to_value(idl(None)).unwrap(),
+            expected,
+            "idl.json is out of date, run `cargo run --features idl --bin sisu-bridge-idl > idl.json`"
+        );
+    }
+
+    #[test]
+    fn test_idl_covers_program() {
+        let idl = idl(None);
+
+        // One instruction per tag, and none past the last.
+        for (tag, instruction) in idl.instructions.iter().enumerate() {
+            assert_eq!(instruction.discriminator, [tag as u8]);
+        }
+        let tags = idl.instructions.len() as u8;
+        assert!(BridgeInstruction::unpack(&[tags]).is_err());
+        assert_eq!(
+            BridgeInstruction::unpack(&[0]),
+            Ok(BridgeInstruction::Initialize)
+        );
+
+        // Error codes are listed in order, from the first.
+        for (code, error) in idl.errors.iter().enumerate() {
+            assert_eq!(error.code, code as u32);
+        }
+
+        // Every guarded instruction takes the instructions sysvar last.
+        let sysvar = sysvar::instructions::id().to_string();
+        for instruction in &idl.instructions {
+            let last = instruction.accounts.last().and_then(|a| a.address.clone());
+            let guarded = instruction.name != "initialize"
+                && !instruction.name.starts_with("transfer_out")
+                && instruction.name != "send_message"
+                && instruction.name != "query";
+            assert_eq!(
+                last.as_ref() == Some(&sysvar),
+                guarded,
+                "{}",
+                instruction.name
+            );
+        }
+
+        // Every defined type is declared.
+        fn check(idl: &Idl, ty: &IdlType) {
+            match ty {
+                IdlType::Vec(ty) | IdlType::Array(ty, _) => check(idl, ty),
+                IdlType::Defined(name) => assert!(idl.type_def(name).is_some(), "{}", name),
+                _ => {}
+            }
+        }
+        for def in &idl.types {
+            match &def.ty {
+                IdlTypeDefTy::Struct { fields } => {
+                    fields.iter().for_each(|field| check(&idl, &field.ty))
+                }
+                IdlTypeDefTy::Enum { variants } => variants
+                    .iter()
+                    .flat_map(|variant| &variant.fields)
+                    .for_each(|ty| check(&idl, ty)),
+                IdlTypeDefTy::Type { alias } => check(&idl, alias),
+            }
+        }
+        for account in &idl.accounts {
+            assert!(idl.type_def(account.name).is_some());
+        }
+    }
+}
@@ -0,0 +1,316 @@
+//! JSON representation of the bridge types, behind the `serde` feature. Pubkeys are base58,
+//! amounts decimal strings so that no JSON reader rounds them, byte payloads 0x prefixed hex,
+//! and enums carry their variant in `type` and its payload, if any, in `data`.
+//!
+//! The modules here are `#[serde(with = ...)]` adapters for the fields of `state`.
+
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+use solana_program::pubkey::Pubkey;
+
+use crate::state::VarU64;
+
+pub mod pubkey {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(key: &Pubkey, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(key)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pubkey, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse()
+            .map_err(|_| D::Error::custom(format!("invalid pubkey {}", s)))
+    }
+}
+
+/// Fixed arrays and vectors of pubkeys.
+pub mod pubkeys {
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    struct Key(#[serde(with = "pubkey")] Pubkey);
+
+    pub fn serialize<S: Serializer, K: AsRef<[Pubkey]>>(
+        keys: &K,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(keys.as_ref().iter().map(|key| Key(*key)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, K: TryFrom<Vec<Pubkey>>>(
+        deserializer: D,
+    ) -> Result<K, D::Error> {
+        let keys: Vec<Key> = Vec::deserialize(deserializer)?;
+        let len = keys.len();
+        K::try_from(keys.into_iter().map(|key| key.0).collect())
+            .map_err(|_| D::Error::invalid_length(len, &"the number of keys of the field"))
+    }
+}
+
+pub mod amount {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(amount: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(amount)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse()
+            .map_err(|_| D::Error::custom(format!("invalid amount {}", s)))
+    }
+}
+
+pub mod amounts {
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    struct Amount(#[serde(with = "amount")] u64);
+
+    pub fn serialize<S: Serializer>(amounts: &[u64], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(amounts.iter().map(|amount| Amount(*amount)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u64>, D::Error> {
+        let amounts: Vec<Amount> = Vec::deserialize(deserializer)?;
+        Ok(amounts.into_iter().map(|amount| amount.0).collect())
+    }
+}
+
+pub mod bytes {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("0x{}", hex::encode(bytes)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        let digits = s
+            .strip_prefix("0x")
+            .ok_or_else(|| D::Error::custom("bytes must be 0x prefixed hex"))?;
+        hex::decode(digits).map_err(D::Error::custom)
+    }
+}
+
+impl Serialize for VarU64 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        amount::serialize(&self.0, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for VarU64 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        amount::deserialize(deserializer).map(VarU64)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::state::{
+        AdminOperation, BridgeEvent, BridgeInstruction, BridgeReturnData, BridgeStateV0,
+        ChangeAdminData, CompactTransferItem, ReceiptStatus, SendMessageData, SpenderOverlap,
+        TransferInCompactData, TransferInData, TransferOutData, TransferOutEvent, TransferReceipt,
+        VarU64, MAX_CPI_CALLERS, MAX_ROLE_HOLDERS,
+    };
+    use serde::{de::DeserializeOwned, Serialize};
+    use serde_json::{json, Value};
+    use solana_program::pubkey::Pubkey;
+    use std::fmt::Debug;
+
+    fn assert_round_trip<T>(value: &T, expected: Value)
+    where
+        T: Serialize + DeserializeOwned + PartialEq + Debug,
+    {
+        let encoded = serde_json::to_value(value).unwrap();
+        assert_eq!(encoded, expected);
+        assert_eq!(&serde_json::from_value::<T>(encoded).unwrap(), value);
+    }
+
+    #[test]
+    fn test_instruction_json() {
+        let transfer_out = TransferOutData {
+            amount: u64::MAX,
+            token_address: "0x1234".to_string(),
+            chain_id: 123,
+            recipient: "someone".to_string(),
+        };
+        assert_round_trip(
+            &BridgeInstruction::TransferOut(transfer_out),
+            json!({
+                "type": "TransferOut",
+                "data": {
+                    "amount": "18446744073709551615",
+                    "token_address": "0x1234",
+                    "chain_id": 123,
+                    "recipient": "someone",
+                },
+            }),
+        );
+        assert_round_trip(
+            &BridgeInstruction::TransferIn(TransferInData {
+                nonce: 7,
+                amounts: vec![1, 2],
+            }),
+            json!({"type": "TransferIn", "data": {"nonce": 7, "amounts": ["1", "2"]}}),
+        );
+        assert_round_trip(
+            &BridgeInstruction::TransferInCompact(TransferInCompactData {
+                nonce: 7,
+                items: vec![CompactTransferItem {
+                    vault_index: 0,
+                    receiver_index: 1,
+                    config_index: 2,
+                    amount: VarU64(300),
+                }],
+            }),
+            json!({
+                "type": "TransferInCompact",
+                "data": {
+                    "nonce": 7,
+                    "items": [{
+                        "vault_index": 0,
+                        "receiver_index": 1,
+                        "config_index": 2,
+                        "amount": "300",
+                    }],
+                },
+            }),
+        );
+        assert_round_trip(
+            &BridgeInstruction::SendMessage(SendMessageData {
+                chain_id: 1,
+                target: "0xabcd".to_string(),
+                payload: vec![0xde, 0xad],
+            }),
+            json!({
+                "type": "SendMessage",
+                "data": {"chain_id": 1, "target": "0xabcd", "payload": "0xdead"},
+            }),
+        );
+        assert_round_trip(
+            &BridgeInstruction::SweepVault,
+            json!({"type": "SweepVault"}),
+        );
+
+        let new_admin = Pubkey::new_unique();
+        assert_round_trip(
+            &BridgeInstruction::QueueOperation(crate::state::QueueOperationData {
+                id: 3,
+                operation: AdminOperation::ChangeAdmin(ChangeAdminData { new_admin }),
+            }),
+            json!({
+                "type": "QueueOperation",
+                "data": {
+                    "id": 3,
+                    "operation": {
+                        "type": "ChangeAdmin",
+                        "data": {"new_admin": new_admin.to_string()},
+                    },
+                },
+            }),
+        );
+        assert_round_trip(
+            &SpenderOverlap::Seconds(60),
+            json!({"type": "Seconds", "data": 60}),
+        );
+    }
+
+    #[test]
+    fn test_event_json() {
+        let sender = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        assert_round_trip(
+            &BridgeEvent::TransferOut(TransferOutEvent {
+                sequence: 4,
+                sender,
+                mint,
+                token_address: "0x1234".to_string(),
+                amount: 900,
+                chain_id: 123,
+                recipient: "someone".to_string(),
+            }),
+            json!({
+                "type": "TransferOut",
+                "data": {
+                    "sequence": 4,
+                    "sender": sender.to_string(),
+                    "mint": mint.to_string(),
+                    "token_address": "0x1234",
+                    "amount": "900",
+                    "chain_id": 123,
+                    "recipient": "someone",
+                },
+            }),
+        );
+        assert_round_trip(
+            &BridgeReturnData::Sequence(9),
+            json!({"type": "Sequence", "data": 9}),
+        );
+    }
+
+    #[test]
+    fn test_account_json() {
+        let key = Pubkey::new_unique();
+        let state = BridgeStateV0 {
+            version: BridgeStateV0::VERSION,
+            bump: 255,
+            admin: key,
+            spenders: [key, Pubkey::default()],
+            spender_index: 0,
+            out_sequence: 1,
+            paused: false,
+            message_sequence: 2,
+            spender_epoch: 3,
+            previous_valid_until_slot: u64::MAX,
+            previous_valid_until: i64::MAX,
+            guardian: key,
+            timelock_delay: 600,
+            treasury: Pubkey::default(),
+            token_count: 1,
+            cpi_callers: [Pubkey::default(); MAX_CPI_CALLERS],
+            role_holders: [Pubkey::default(); MAX_ROLE_HOLDERS],
+            role_masks: [0; MAX_ROLE_HOLDERS],
+        };
+        let encoded = serde_json::to_value(&state).unwrap();
+        assert_eq!(
+            encoded["spenders"],
+            json!([key.to_string(), Pubkey::default().to_string()])
+        );
+        assert_eq!(
+            serde_json::from_value::<BridgeStateV0>(encoded).unwrap(),
+            state
+        );
+
+        // Fixed arrays keep their length.
+        let mut encoded = serde_json::to_value(&state).unwrap();
+        encoded["spenders"] = json!([key.to_string()]);
+        assert!(serde_json::from_value::<BridgeStateV0>(encoded).is_err());
+
+        let receipt = TransferReceipt {
+            sequence: 0,
+            sender: key,
+            source: key,
+            mint: key,
+            amount: 5,
+            status: ReceiptStatus::Refunded,
+            bump: 1,
+        };
+        let encoded = serde_json::to_value(&receipt).unwrap();
+        assert_eq!(encoded["status"], json!("Refunded"));
+        assert_eq!(encoded["amount"], json!("5"));
+        assert_eq!(
+            serde_json::from_value::<TransferReceipt>(encoded).unwrap(),
+            receipt
+        );
+
+        // Amounts are strings, never numbers, and payloads are 0x prefixed.
+        let mut encoded = serde_json::to_value(&receipt).unwrap();
+        encoded["amount"] = json!(5);
+        assert!(serde_json::from_value::<TransferReceipt>(encoded).is_err());
+        assert!(serde_json::from_value::<SendMessageData>(
+            json!({"chain_id": 1, "target": "", "payload": "dead"})
+        )
+        .is_err());
+    }
+}
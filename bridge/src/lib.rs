@@ -1,5 +1,14 @@
+pub mod cpi;
+#[cfg(not(feature = "no-entrypoint"))]
 pub mod entrypoint;
 pub mod error;
+#[cfg(feature = "idl")]
+pub mod idl;
 pub mod instruction;
+#[cfg(feature = "serde")]
+pub mod json;
 pub mod processor;
+pub mod receiver;
 pub mod state;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
@@ -4,17 +4,35 @@ use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint::ProgramResult,
+    instruction::{get_stack_height, AccountMeta, Instruction, TRANSACTION_LEVEL_STACK_HEIGHT},
+    log::sol_log_data,
     msg,
-    program::invoke_signed,
+    program::{invoke, invoke_signed, set_return_data},
     program_error::ProgramError,
+    program_pack::Pack,
     pubkey::Pubkey,
     system_instruction, system_program,
-    sysvar::{rent::Rent, Sysvar},
+    sysvar::{self, clock::Clock, rent::Rent, Sysvar},
 };
 
+use spl_associated_token_account::get_associated_token_address;
+use spl_token::state::Account as SplTokenAccount;
+
 use crate::error::BridgeError;
+use crate::receiver::ReceiveMessage;
 use crate::state::{
-    AddSpenderData, BridgeInstruction, BridgeStateV0, TransferInIx, TransferOutData,
+    ActivateSpenderData, AddSpenderData, AdminOperation, BridgeEvent, BridgeInstruction,
+    BridgeQuery, BridgeReturnData, BridgeStateV0, BridgeStateV0Pod, ConfigInfo, DeliverMessageData,
+    DeliveredEvent, DeliveryRecord, LegacyBridgeState, MessageDeliveredEvent, MessageEvent,
+    OperationData, OperationEvent, OperationQueuedEvent, PendingOperation, QueueOperationData,
+    ReceiptData, ReceiptStatus, ReconciledEvent, RefundedEvent, RegisterTokenData, Role, RoleKeys,
+    SendMessageData, SetCpiCallerData, SetPausedData, SetRoleData, SpenderActivatedEvent,
+    SpenderKeys, SpenderOverlap, SpendersInfo, TokenConfig, TokenInfo, TransferInCompactData,
+    TransferInData, TransferInRef, TransferOutBatchData, TransferOutData, TransferOutEvent,
+    TransferOutNativeData, TransferOutWithPayloadEvent, TransferReceipt, VaultSweptEvent,
+    BRIDGE_SEED, DELIVERY_SEED, MAX_CPI_CALLERS, MAX_PAYLOAD_LEN, MAX_ROLE_HOLDERS,
+    MESSAGE_AUTHORITY_SEED, NATIVE_SOL_MINT, NATIVE_VAULT_SEED, OPERATION_SEED, RECEIPT_SEED,
+    TOKEN_SEED,
 };
 
 pub struct Processor {}
@@ -25,34 +43,172 @@ impl Processor {
         accounts: &[AccountInfo],
         instruction_data: &[u8],
     ) -> ProgramResult {
-        let data_vec = instruction_data.to_vec();
-        assert!(data_vec.len() > 0, "Instruction data cannot be empty");
+        let accounts_iter = &mut accounts.iter();
 
-        let instruction = BridgeInstruction::try_from_slice(&[data_vec[0]])
-            .map_err(|_| ProgramError::InvalidInstructionData)?;
+        // Inbound batches are the largest instructions, their amounts are read in place.
+        if !cfg!(feature = "borsh-state")
+            && instruction_data.first() == Some(&BridgeInstruction::TRANSFER_IN_TAG)
+        {
+            let transfer_in = TransferInRef::unpack(&instruction_data[1..])?;
+            Processor::check_caller(accounts, program_id)?;
+            return Processor::transfer_in(accounts_iter, program_id, transfer_in.amounts());
+        }
 
-        let accounts_iter = &mut accounts.iter();
+        let instruction = BridgeInstruction::unpack(instruction_data)?;
+        if !instruction.allows_cpi() {
+            Processor::check_caller(accounts, program_id)?;
+        }
+        // Checked against the signer by the instructions below that change the configuration.
+        let roles = instruction.required_roles();
 
         match instruction {
-            BridgeInstruction::Initialize => {
-                return Processor::initialize(accounts_iter, program_id);
+            BridgeInstruction::Initialize => Processor::initialize(accounts_iter, program_id),
+            BridgeInstruction::TransferOut(payload) => {
+                Processor::transfer_out(accounts_iter, program_id, payload, vec![])
             }
-
-            BridgeInstruction::TransferOut => {
-                return Processor::transfer_out(accounts_iter, data_vec);
+            // Dispatched above without collecting the amounts, except in the `borsh-state` build.
+            BridgeInstruction::TransferIn(payload) => {
+                Processor::transfer_in(accounts_iter, program_id, payload.amounts.into_iter())
             }
-
-            BridgeInstruction::TransferIn => {
-                return Processor::transfer_in(accounts_iter, data_vec);
+            // Activates the new spender without an overlap, the previous one expires after
+            // this slot.
+            BridgeInstruction::AddSpender(payload) => Processor::admin_operation(
+                accounts_iter,
+                roles,
+                AdminOperation::ActivateSpender(ActivateSpenderData {
+                    spender: payload.spender,
+                    overlap: SpenderOverlap::Slots(0),
+                }),
+            ),
+            BridgeInstruction::RemoveSpender(payload) => Processor::admin_operation(
+                accounts_iter,
+                roles,
+                AdminOperation::RemoveSpender(payload),
+            ),
+            BridgeInstruction::ChangeAdmin(payload) => Processor::admin_operation(
+                accounts_iter,
+                roles,
+                AdminOperation::ChangeAdmin(payload),
+            ),
+            BridgeInstruction::TransferOutNative(payload) => {
+                Processor::transfer_out_native(accounts_iter, program_id, payload)
             }
-
-            BridgeInstruction::AddSpender => {
-                return Processor::add_spender(accounts_iter, data_vec);
+            BridgeInstruction::TransferInNative(payload) => {
+                Processor::transfer_in_native(accounts_iter, program_id, payload)
+            }
+            BridgeInstruction::TransferOutBatch(payload) => {
+                Processor::transfer_out_batch(accounts_iter, program_id, payload)
+            }
+            BridgeInstruction::TransferInCompact(payload) => {
+                Processor::transfer_in_compact(accounts_iter, program_id, payload)
+            }
+            BridgeInstruction::Refund(payload) => {
+                Processor::refund(accounts_iter, program_id, payload)
+            }
+            BridgeInstruction::MarkDelivered(payload) => {
+                Processor::mark_delivered(accounts_iter, program_id, payload)
+            }
+            BridgeInstruction::SetPaused(payload) => {
+                Processor::set_paused(accounts_iter, roles, payload)
             }
+            BridgeInstruction::RegisterToken(payload) => {
+                Processor::register_token(accounts_iter, program_id, roles, payload)
+            }
+            BridgeInstruction::SendMessage(payload) => {
+                Processor::send_message(accounts_iter, program_id, payload)
+            }
+            BridgeInstruction::TransferOutWithPayload(payload) => Processor::transfer_out(
+                accounts_iter,
+                program_id,
+                payload.transfer,
+                payload.payload,
+            ),
+            BridgeInstruction::DeliverMessage(payload) => {
+                Processor::deliver_message(accounts_iter, program_id, payload)
+            }
+            BridgeInstruction::ActivateSpender(payload) => Processor::admin_operation(
+                accounts_iter,
+                roles,
+                AdminOperation::ActivateSpender(payload),
+            ),
+            BridgeInstruction::SetTimelock(payload) => Processor::admin_operation(
+                accounts_iter,
+                roles,
+                AdminOperation::SetTimelock(payload),
+            ),
+            BridgeInstruction::QueueOperation(payload) => {
+                Processor::queue_operation(accounts_iter, program_id, roles, payload)
+            }
+            BridgeInstruction::ExecuteOperation(payload) => {
+                Processor::execute_operation(accounts_iter, program_id, roles, payload)
+            }
+            BridgeInstruction::CancelOperation(payload) => {
+                Processor::cancel_operation(accounts_iter, program_id, roles, payload)
+            }
+            BridgeInstruction::Decommission(payload) => Processor::admin_operation(
+                accounts_iter,
+                roles,
+                AdminOperation::Decommission(payload),
+            ),
+            BridgeInstruction::SweepVault => {
+                Processor::sweep_vault(accounts_iter, program_id, roles)
+            }
+            BridgeInstruction::CloseBridge => {
+                Processor::close_bridge(accounts_iter, program_id, roles)
+            }
+            BridgeInstruction::Reconcile => Processor::reconcile(accounts_iter, program_id, roles),
+            BridgeInstruction::Query(query) => Processor::query(accounts_iter, program_id, query),
+            BridgeInstruction::SetCpiCaller(payload) => Processor::admin_operation(
+                accounts_iter,
+                roles,
+                AdminOperation::SetCpiCaller(payload),
+            ),
+            BridgeInstruction::SetRole(payload) => {
+                Processor::admin_operation(accounts_iter, roles, AdminOperation::SetRole(payload))
+            }
+            BridgeInstruction::Migrate => Processor::migrate(accounts_iter, program_id, roles),
+        }
+    }
 
-            BridgeInstruction::RemoveSpender => Err(BridgeError::NotImplemented.into()),
-            BridgeInstruction::ChangeAdmin => Err(BridgeError::NotImplemented.into()),
+    /// Lets the instruction through if it is a top-level instruction of the transaction, or if
+    /// an allowed CPI caller called it directly. The instructions sysvar must be among the
+    /// accounts, the caller is read from it.
+    fn check_caller(accounts: &[AccountInfo], program_id: &Pubkey) -> ProgramResult {
+        let instructions = accounts
+            .iter()
+            .find(|account| sysvar::instructions::check_id(account.key))
+            .ok_or_else(|| {
+                msg!("Missing the instructions sysvar");
+                ProgramError::NotEnoughAccountKeys
+            })?;
+        let stack_height = get_stack_height();
+        if stack_height == TRANSACTION_LEVEL_STACK_HEIGHT {
+            return Ok(());
         }
+        // The sysvar only names the top-level program, which need not be the one that called
+        // the bridge once the call is nested any deeper.
+        if stack_height > TRANSACTION_LEVEL_STACK_HEIGHT + 1 {
+            msg!("Nested call at stack height {}", stack_height);
+            return Err(BridgeError::UntrustedCaller.into());
+        }
+
+        let index = sysvar::instructions::load_current_index_checked(instructions)?;
+        let caller =
+            sysvar::instructions::load_instruction_at_checked(index as usize, instructions)?
+                .program_id;
+
+        let (bridge_pda, _) = Pubkey::find_program_address(&[BRIDGE_SEED], program_id);
+        let bridge_pda = accounts
+            .iter()
+            .find(|account| account.key == &bridge_pda)
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let bridge_state = Processor::load_bridge_state(bridge_pda, program_id)?;
+        if bridge_state.is_cpi_caller(&caller) {
+            return Ok(());
+        }
+
+        msg!("Untrusted caller {:?}", caller);
+        Err(BridgeError::UntrustedCaller.into())
     }
 
     fn initialize(accounts_iter: &mut Iter<AccountInfo>, program_id: &Pubkey) -> ProgramResult {
@@ -60,8 +216,6 @@ impl Processor {
         let bridge_pda = next_account_info(accounts_iter)?;
         let sys_program = next_account_info(accounts_iter)?;
 
-        let seed_string = b"SisuBridge";
-
         // Verification
         assert!(user.is_signer, "initialize: User must sign the message");
         assert!(
@@ -72,7 +226,7 @@ impl Processor {
         assert!(system_program::check_id(sys_program.key));
 
         // Check that the bridge pda matches the expected pda.
-        let (calculated_pda, bump) = Pubkey::find_program_address(&[seed_string], program_id);
+        let (calculated_pda, bump) = Pubkey::find_program_address(&[BRIDGE_SEED], program_id);
         assert_eq!(bridge_pda.key, &calculated_pda);
 
         // Create the pda account
@@ -80,21 +234,35 @@ impl Processor {
             &system_instruction::create_account(
                 user.key,
                 bridge_pda.key,
-                Rent::get()?.minimum_balance(99),
-                99,
+                Rent::get()?.minimum_balance(BridgeStateV0::LEN),
+                BridgeStateV0::LEN as u64,
                 &program_id,
             ),
             // making sure downstream program has all necessary data
             &[user.clone(), bridge_pda.clone(), sys_program.clone()],
-            &[&[seed_string, &[bump]]], // signature
+            &[&[BRIDGE_SEED, &[bump]]], // signature
         )?;
 
+        // The admin holds no hot role, the spender and the guardian are set apart from it.
         let bridge_state = BridgeStateV0 {
-            version: 0,
+            version: BridgeStateV0::VERSION,
             bump,
             admin: *user.key,
-            spenders: [*user.key, *user.key],
+            spenders: [Pubkey::default(); 2],
             spender_index: 0,
+            out_sequence: 0,
+            paused: false,
+            message_sequence: 0,
+            spender_epoch: 0,
+            previous_valid_until_slot: 0,
+            previous_valid_until: 0,
+            guardian: Pubkey::default(),
+            timelock_delay: 0,
+            treasury: Pubkey::default(),
+            token_count: 0,
+            cpi_callers: [Pubkey::default(); MAX_CPI_CALLERS],
+            role_holders: [Pubkey::default(); MAX_ROLE_HOLDERS],
+            role_masks: [0; MAX_ROLE_HOLDERS],
         };
 
         msg!("Bridge admin = {:?}", user.key);
@@ -104,32 +272,228 @@ impl Processor {
         Ok(())
     }
 
-    fn transfer_out(accounts_iter: &mut Iter<AccountInfo>, data_vec: Vec<u8>) -> ProgramResult {
+    /// Grows a version 0 bridge account, which held only the admin and the spenders, to the
+    /// current layout. The new fields start out like a fresh bridge, and the admin leaves the
+    /// spender slots like `initialize` leaves them to others now. The admin pays the extra rent.
+    fn migrate(
+        accounts_iter: &mut Iter<AccountInfo>,
+        program_id: &Pubkey,
+        roles: &[Role],
+    ) -> ProgramResult {
+        let user = next_account_info(accounts_iter)?;
+        let bridge_pda = next_account_info(accounts_iter)?;
+        let sys_program = next_account_info(accounts_iter)?;
+
+        assert!(user.is_signer, "migrate: User must sign the message");
+        assert_eq!(bridge_pda.owner, program_id);
+        assert!(system_program::check_id(sys_program.key));
+
+        if bridge_pda.data_len() != LegacyBridgeState::LEN {
+            return Err(BridgeError::AlreadyMigrated.into());
+        }
+        let legacy = LegacyBridgeState::try_from_slice(&bridge_pda.data.borrow())?;
+        let expected_pda =
+            Pubkey::create_program_address(&[BRIDGE_SEED, &[legacy.bump]], program_id)?;
+        assert_eq!(bridge_pda.key, &expected_pda);
+
+        // Both spenders stayed valid until the next change before spender epochs, and so does
+        // the previous one here.
+        let mut bridge_state = BridgeStateV0 {
+            version: BridgeStateV0::VERSION,
+            bump: legacy.bump,
+            admin: legacy.admin,
+            spenders: legacy.spenders,
+            spender_index: legacy.spender_index,
+            out_sequence: 0,
+            paused: false,
+            message_sequence: 0,
+            spender_epoch: 0,
+            previous_valid_until_slot: u64::MAX,
+            previous_valid_until: i64::MAX,
+            guardian: Pubkey::default(),
+            timelock_delay: 0,
+            treasury: Pubkey::default(),
+            token_count: 0,
+            cpi_callers: [Pubkey::default(); MAX_CPI_CALLERS],
+            role_holders: [Pubkey::default(); MAX_ROLE_HOLDERS],
+            role_masks: [0; MAX_ROLE_HOLDERS],
+        };
+        Processor::check_role(&bridge_state, user.key, roles)?;
+        for spender in bridge_state.spenders.iter_mut() {
+            if *spender == legacy.admin {
+                *spender = Pubkey::default();
+            }
+        }
+
+        let rent = Rent::get()?
+            .minimum_balance(BridgeStateV0::LEN)
+            .saturating_sub(bridge_pda.lamports());
+        if rent > 0 {
+            invoke(
+                &system_instruction::transfer(user.key, bridge_pda.key, rent),
+                &[user.clone(), bridge_pda.clone(), sys_program.clone()],
+            )?;
+        }
+        bridge_pda.realloc(BridgeStateV0::LEN, true)?;
+        bridge_state.serialize(&mut *bridge_pda.data.borrow_mut())?;
+
+        msg!("Bridge migrated to version {}", BridgeStateV0::VERSION);
+        Ok(())
+    }
+
+    /// Locks tokens for an outbound transfer. A non-empty `message` is delivered to the
+    /// recipient contract along with the tokens.
+    fn transfer_out(
+        accounts_iter: &mut Iter<AccountInfo>,
+        program_id: &Pubkey,
+        payload: TransferOutData,
+        message: Vec<u8>,
+    ) -> ProgramResult {
         let user = next_account_info(accounts_iter)?;
         let token_program_ai = next_account_info(accounts_iter)?;
         let user_associated_token = next_account_info(accounts_iter)?;
         let bridge_associated_token = next_account_info(accounts_iter)?;
         let bridge_pda = next_account_info(accounts_iter)?;
-
-        msg!(
-            "userATA = {:?}, bridgeAta = {:?}",
-            user_associated_token.key,
-            bridge_associated_token.key
-        );
+        let receipt_ai = next_account_info(accounts_iter)?;
+        let sys_program = next_account_info(accounts_iter)?;
+        let token_config_ai = next_account_info(accounts_iter)?;
+        // Optional, pays for the receipt instead of the user. Lets a program pda that holds no
+        // lamports deposit on behalf of its users.
+        let payer = next_account_info(accounts_iter).unwrap_or(user);
 
         // Validation
         assert!(user.is_signer, "transfer_out: User must sign the message");
+        assert!(payer.is_signer, "transfer_out: Payer must sign the message");
+        assert!(system_program::check_id(sys_program.key));
+        Processor::check_payload_len(&message)?;
 
-        // Payload
-        let payload = TransferOutData::try_from_slice(&data_vec[1..]).unwrap();
-        msg!(
-            "Recipient = {:?} -- Amount = {:?}",
-            payload.recipient,
-            payload.amount
+        // Transfer token to this bridge account.
+        let (bump, sequence) = Processor::outbound_state(bridge_pda)?;
+        Processor::lock_tokens(
+            program_id,
+            user,
+            payer,
+            token_program_ai,
+            user_associated_token,
+            bridge_associated_token,
+            bridge_pda,
+            receipt_ai,
+            sys_program,
+            token_config_ai,
+            bump,
+            sequence,
+            payload,
+            message,
+        )?;
+
+        Processor::set_out_sequence(bridge_pda, sequence + 1)
+    }
+
+    fn transfer_out_batch(
+        accounts_iter: &mut Iter<AccountInfo>,
+        program_id: &Pubkey,
+        batch: TransferOutBatchData,
+    ) -> ProgramResult {
+        let user = next_account_info(accounts_iter)?;
+        let token_program_ai = next_account_info(accounts_iter)?;
+        let bridge_pda = next_account_info(accounts_iter)?;
+        let sys_program = next_account_info(accounts_iter)?;
+
+        assert!(
+            user.is_signer,
+            "transfer_out_batch: User must sign the message"
         );
 
-        // Transfer token to this bridge account.
-        let bridge_state = BridgeStateV0::try_from_slice(&bridge_pda.data.borrow())?;
+        assert!(system_program::check_id(sys_program.key));
+        assert!(
+            batch.items.len() > 0,
+            "transfer_out_batch: batch cannot be empty"
+        );
+
+        // Each item brings its own token accounts, receipt and token config, and gets its own
+        // sequence.
+        let (bump, mut sequence) = Processor::outbound_state(bridge_pda)?;
+        for payload in batch.items {
+            let user_associated_token = next_account_info(accounts_iter)?;
+            let bridge_associated_token = next_account_info(accounts_iter)?;
+            let receipt_ai = next_account_info(accounts_iter)?;
+            let token_config_ai = next_account_info(accounts_iter)?;
+
+            Processor::lock_tokens(
+                program_id,
+                user,
+                user,
+                token_program_ai,
+                user_associated_token,
+                bridge_associated_token,
+                bridge_pda,
+                receipt_ai,
+                sys_program,
+                token_config_ai,
+                bump,
+                sequence,
+                payload,
+                vec![],
+            )?;
+            sequence += 1;
+        }
+
+        Processor::set_out_sequence(bridge_pda, sequence)
+    }
+
+    /// Moves `payload.amount` from the user's token account into the bridge vault and records
+    /// it under `sequence`, with `payer` funding the receipt. `message` is the optional payload
+    /// for the recipient. The caller advances the bridge's `out_sequence`.
+    #[allow(clippy::too_many_arguments)]
+    fn lock_tokens<'a>(
+        program_id: &Pubkey,
+        user: &AccountInfo<'a>,
+        payer: &AccountInfo<'a>,
+        token_program_ai: &AccountInfo<'a>,
+        user_associated_token: &AccountInfo<'a>,
+        bridge_associated_token: &AccountInfo<'a>,
+        bridge_pda: &AccountInfo<'a>,
+        receipt_ai: &AccountInfo<'a>,
+        sys_program: &AccountInfo<'a>,
+        token_config_ai: &AccountInfo<'a>,
+        bump: u8,
+        sequence: u64,
+        payload: TransferOutData,
+        message: Vec<u8>,
+    ) -> ProgramResult {
+        assert!(payload.amount > 0, "Amount must be positive!");
+        assert!(
+            payload.recipient.len() > 0,
+            "transfer_out: recipient cannot be empty"
+        );
+
+        // The bridge pda is only a delegate of the user account, so make sure the signer owns
+        // the tokens and that both accounts hold the same mint.
+        let user_token = SplTokenAccount::unpack(&user_associated_token.data.borrow())?;
+        let bridge_token = SplTokenAccount::unpack(&bridge_associated_token.data.borrow())?;
+        assert_eq!(
+            &user_token.owner, user.key,
+            "transfer_out: user does not own the source account"
+        );
+        assert_eq!(
+            &bridge_token.owner, bridge_pda.key,
+            "transfer_out: bridge pda must own the bridge ata"
+        );
+        assert_eq!(
+            user_token.mint, bridge_token.mint,
+            "transfer_out: token accounts have different mints"
+        );
+        // Locked tokens only ever sit in the associated account, the one that closes the config
+        // when it is swept.
+        assert_eq!(
+            bridge_associated_token.key,
+            &get_associated_token_address(bridge_pda.key, &user_token.mint),
+            "transfer_out: not the bridge ata of the mint"
+        );
+        let mut token_config =
+            Processor::load_token_config(token_config_ai, program_id, &user_token.mint)?;
+        Processor::check_token_enabled(&token_config)?;
+
         invoke_signed(
             &spl_token::instruction::transfer(
                 &spl_token::ID,
@@ -145,21 +509,280 @@ impl Processor {
                 bridge_pda.clone(),
                 token_program_ai.clone(),
             ],
-            &[&[b"SisuBridge", &[bridge_state.bump]]],
+            &[&[BRIDGE_SEED, &[bump]]],
+        )?;
+        token_config.locked = token_config
+            .locked
+            .checked_add(payload.amount)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        token_config.serialize(&mut *token_config_ai.data.borrow_mut())?;
+
+        Processor::record_transfer_out(
+            program_id,
+            payer,
+            receipt_ai,
+            sys_program,
+            *user_associated_token.key,
+            TransferOutEvent {
+                sequence,
+                sender: *user.key,
+                mint: user_token.mint,
+                token_address: payload.token_address,
+                amount: payload.amount,
+                chain_id: payload.chain_id,
+                recipient: payload.recipient,
+            },
+            message,
+        )
+    }
+
+    /// Creates the `Pending` receipt of `event.sequence` (paid for by `payer`) and logs the
+    /// event, with `message` attached when there is one. `source` is where a refund would be
+    /// sent back to.
+    fn record_transfer_out<'a>(
+        program_id: &Pubkey,
+        payer: &AccountInfo<'a>,
+        receipt_ai: &AccountInfo<'a>,
+        sys_program: &AccountInfo<'a>,
+        source: Pubkey,
+        event: TransferOutEvent,
+        message: Vec<u8>,
+    ) -> ProgramResult {
+        let sequence_bytes = event.sequence.to_le_bytes();
+        let (calculated_receipt, bump) =
+            Pubkey::find_program_address(&[RECEIPT_SEED, &sequence_bytes], program_id);
+        assert_eq!(
+            receipt_ai.key, &calculated_receipt,
+            "transfer_out: receipt does not match the next sequence"
+        );
+
+        invoke_signed(
+            &system_instruction::create_account(
+                payer.key,
+                receipt_ai.key,
+                Rent::get()?.minimum_balance(TransferReceipt::LEN),
+                TransferReceipt::LEN as u64,
+                program_id,
+            ),
+            &[payer.clone(), receipt_ai.clone(), sys_program.clone()],
+            &[&[RECEIPT_SEED, &sequence_bytes, &[bump]]],
         )?;
 
+        let receipt = TransferReceipt {
+            sequence: event.sequence,
+            sender: event.sender,
+            source,
+            mint: event.mint,
+            amount: event.amount,
+            status: ReceiptStatus::Pending,
+            bump,
+        };
+        receipt.serialize(&mut *receipt_ai.data.borrow_mut())?;
+        // A batch returns the sequence of its last item.
+        set_return_data(&BridgeReturnData::Sequence(event.sequence).pack());
+
+        if message.is_empty() {
+            Processor::emit(BridgeEvent::TransferOut(event))
+        } else {
+            Processor::emit(BridgeEvent::TransferOutWithPayload(
+                TransferOutWithPayloadEvent {
+                    transfer: event,
+                    payload: message,
+                },
+            ))
+        }
+    }
+
+    /// Logs an arbitrary payload for `data.target` on the destination chain. Messages have their
+    /// own sequence and carry no tokens, so there is no receipt.
+    fn send_message(
+        accounts_iter: &mut Iter<AccountInfo>,
+        program_id: &Pubkey,
+        data: SendMessageData,
+    ) -> ProgramResult {
+        let sender = next_account_info(accounts_iter)?;
+        let bridge_pda = next_account_info(accounts_iter)?;
+
+        assert!(
+            sender.is_signer,
+            "send_message: Sender must sign the message"
+        );
+        assert_eq!(bridge_pda.owner, program_id);
+        assert!(
+            data.target.len() > 0,
+            "send_message: target cannot be empty"
+        );
+        Processor::check_payload_len(&data.payload)?;
+
+        let mut bridge_data = bridge_pda.data.borrow_mut();
+        let bridge_state = BridgeStateV0Pod::load_mut(&mut bridge_data)?;
+        Processor::check_not_paused(bridge_state.paused())?;
+        let sequence = bridge_state.message_sequence();
+        bridge_state.set_message_sequence(sequence + 1);
+
+        Processor::emit(BridgeEvent::MessageSent(MessageEvent {
+            sequence,
+            sender: *sender.key,
+            chain_id: data.chain_id,
+            target: data.target,
+            payload: data.payload,
+        }))
+    }
+
+    fn emit(event: BridgeEvent) -> ProgramResult {
+        sol_log_data(&[&event.try_to_vec()?]);
+
         Ok(())
     }
 
-    fn transfer_in(accounts_iter: &mut Iter<AccountInfo>, data_vec: Vec<u8>) -> ProgramResult {
-        // Authority checking. Make sure the caller is the spender or owner of this bridge pda.
+    /// Executes an inbound message: releases `data.amount` to the receiver's token account if
+    /// there is one, then calls the receiver program. The delivery record makes a second
+    /// delivery of the same message fail, and disappears with the transaction if the receiver
+    /// fails, so the message can be retried.
+    fn deliver_message(
+        accounts_iter: &mut Iter<AccountInfo>,
+        program_id: &Pubkey,
+        data: DeliverMessageData,
+    ) -> ProgramResult {
         let bridge_spender = next_account_info(accounts_iter)?;
-        let token_program_ai = next_account_info(accounts_iter)?;
         let bridge_pda = next_account_info(accounts_iter)?;
+        let record_ai = next_account_info(accounts_iter)?;
+        let message_authority = next_account_info(accounts_iter)?;
+        let sys_program = next_account_info(accounts_iter)?;
+        let receiver_program = next_account_info(accounts_iter)?;
+
+        assert!(
+            bridge_spender.is_signer,
+            "deliver_message: Spender must sign the message"
+        );
+        assert!(system_program::check_id(sys_program.key));
+        assert!(
+            receiver_program.executable,
+            "deliver_message: receiver is not a program"
+        );
+        Processor::check_payload_len(&data.payload)?;
+
+        let bridge_state = Processor::load_bridge_state(bridge_pda, program_id)?;
+        Processor::check_spender(
+            &bridge_state.spender_keys(),
+            &bridge_state.role_keys(),
+            bridge_spender.key,
+        )?;
+        Processor::check_not_paused(bridge_state.paused)?;
+
+        let (calculated_authority, authority_bump) =
+            Pubkey::find_program_address(&[MESSAGE_AUTHORITY_SEED], program_id);
+        assert_eq!(message_authority.key, &calculated_authority);
+
+        // Execute at most once.
+        let chain_bytes = data.source_chain_id.to_le_bytes();
+        let nonce_bytes = data.nonce.to_le_bytes();
+        let (calculated_record, record_bump) =
+            Pubkey::find_program_address(&[DELIVERY_SEED, &chain_bytes, &nonce_bytes], program_id);
+        assert_eq!(
+            record_ai.key, &calculated_record,
+            "deliver_message: record does not match the message"
+        );
+        if record_ai.owner == program_id {
+            return Err(BridgeError::MessageAlreadyDelivered.into());
+        }
+        invoke_signed(
+            &system_instruction::create_account(
+                bridge_spender.key,
+                record_ai.key,
+                Rent::get()?.minimum_balance(DeliveryRecord::LEN),
+                DeliveryRecord::LEN as u64,
+                program_id,
+            ),
+            &[
+                bridge_spender.clone(),
+                record_ai.clone(),
+                sys_program.clone(),
+            ],
+            &[&[DELIVERY_SEED, &chain_bytes, &nonce_bytes, &[record_bump]]],
+        )?;
+        let record = DeliveryRecord {
+            source_chain_id: data.source_chain_id,
+            nonce: data.nonce,
+            receiver_program: *receiver_program.key,
+            bump: record_bump,
+        };
+        record.serialize(&mut *record_ai.data.borrow_mut())?;
+
+        let mut accounts = vec![AccountMeta::new_readonly(*message_authority.key, true)];
+        let mut account_infos = vec![message_authority.clone()];
+        if data.amount > 0 {
+            let token_program_ai = next_account_info(accounts_iter)?;
+            let bridge_ata = next_account_info(accounts_iter)?;
+            let receiver_ata = next_account_info(accounts_iter)?;
+            let token_config_ai = next_account_info(accounts_iter)?;
+            Processor::release_locked_tokens(
+                program_id,
+                token_program_ai,
+                bridge_ata,
+                receiver_ata,
+                bridge_pda,
+                token_config_ai,
+                bridge_state.bump,
+                data.amount,
+            )?;
+            accounts.push(AccountMeta::new(*receiver_ata.key, false));
+            account_infos.push(receiver_ata.clone());
+        }
+        // Everything up to the instructions sysvar, which is for the bridge.
+        let (instructions, extra_accounts) = accounts_iter
+            .as_slice()
+            .split_last()
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        if !sysvar::instructions::check_id(instructions.key) {
+            msg!("deliver_message: last account must be the instructions sysvar");
+            return Err(ProgramError::InvalidArgument);
+        }
+        for account in extra_accounts {
+            accounts.push(AccountMeta {
+                pubkey: *account.key,
+                is_signer: account.is_signer,
+                is_writable: account.is_writable,
+            });
+            account_infos.push(account.clone());
+        }
+        account_infos.push(receiver_program.clone());
+
+        let event = MessageDeliveredEvent {
+            source_chain_id: data.source_chain_id,
+            nonce: data.nonce,
+            receiver_program: *receiver_program.key,
+            amount: data.amount,
+        };
+        invoke_signed(
+            &Instruction {
+                program_id: *receiver_program.key,
+                accounts,
+                data: ReceiveMessage {
+                    source_chain_id: data.source_chain_id,
+                    nonce: data.nonce,
+                    sender: data.sender,
+                    payload: data.payload,
+                    amount: data.amount,
+                }
+                .pack(),
+            },
+            &account_infos,
+            &[&[MESSAGE_AUTHORITY_SEED, &[authority_bump]]],
+        )?;
 
-        let bridge_state = BridgeStateV0::try_from_slice(&bridge_pda.data.borrow())?;
+        Processor::emit(BridgeEvent::MessageDelivered(event))
+    }
 
-        msg!("In the transfer in ....");
+    fn transfer_in(
+        accounts_iter: &mut Iter<AccountInfo>,
+        program_id: &Pubkey,
+        amounts: impl ExactSizeIterator<Item = u64>,
+    ) -> ProgramResult {
+        // Authority checking. Make sure the caller is the spender or owner of this bridge pda.
+        let bridge_spender = next_account_info(accounts_iter)?;
+        let token_program_ai = next_account_info(accounts_iter)?;
+        let bridge_pda = next_account_info(accounts_iter)?;
 
         // Verify that this message is signed by the user.
         assert!(
@@ -168,32 +791,17 @@ impl Processor {
         );
 
         // Check that user is one of the spenders
-        let mut found = false;
-        for spender in bridge_state.spenders {
-            if spender == *bridge_spender.key {
-                found = true;
-                break;
-            }
-        }
-        if found == false {
-            return Err(BridgeError::NotAnAdmin.into());
-        }
+        let bump = Processor::inbound_state(bridge_pda, bridge_spender.key)?;
 
         // Verify that all the tokens must be valid and the bridge has account for each tokens.
-        let transfer_in = TransferInIx::try_from_slice(&data_vec)
-            .unwrap()
-            .transfer_data;
-        assert!(
-            transfer_in.amounts.len() > 0,
-            "amount array length should be positive"
-        );
+        assert!(amounts.len() > 0, "amount array length should be positive");
 
-        for item in transfer_in.amounts.into_iter().enumerate() {
-            let (_, amount): (usize, u64) = item;
+        for amount in amounts {
             assert!(amount > 0, "Amount must be positive!");
 
             let bridge_ata = next_account_info(accounts_iter)?;
             let receiver_ata = next_account_info(accounts_iter)?;
+            let token_config_ai = next_account_info(accounts_iter)?;
 
             // assert_eq!(
             //     bridge_ata.owner, bridge_pda.key,
@@ -201,51 +809,1348 @@ impl Processor {
             // );
 
             // Transfer token from bridge to user.
-            invoke_signed(
-                &spl_token::instruction::transfer(
-                    &spl_token::ID,
-                    bridge_ata.key,
-                    receiver_ata.key,
-                    bridge_pda.key,
-                    &[bridge_pda.key],
-                    amount,
-                )?,
-                &[
-                    bridge_ata.clone(),
-                    receiver_ata.clone(),
-                    bridge_pda.clone(),
-                    token_program_ai.clone(),
-                ],
-                &[&[b"SisuBridge", &[bridge_state.bump]]],
+            Processor::release_locked_tokens(
+                program_id,
+                token_program_ai,
+                bridge_ata,
+                receiver_ata,
+                bridge_pda,
+                token_config_ai,
+                bump,
+                amount,
             )?;
         }
 
         Ok(())
     }
 
-    fn add_spender(accounts_iter: &mut Iter<AccountInfo>, data_vec: Vec<u8>) -> ProgramResult {
-        let user = next_account_info(accounts_iter)?;
+    fn transfer_in_compact(
+        accounts_iter: &mut Iter<AccountInfo>,
+        program_id: &Pubkey,
+        transfer_in: TransferInCompactData,
+    ) -> ProgramResult {
+        let bridge_spender = next_account_info(accounts_iter)?;
+        let token_program_ai = next_account_info(accounts_iter)?;
         let bridge_pda = next_account_info(accounts_iter)?;
-        assert!(user.is_signer, "add_spender: User must sign the message");
 
-        // Get the bridge state.
-        let mut bridge_state = BridgeStateV0::try_from_slice(&bridge_pda.data.borrow())?;
+        let bridge_state = BridgeStateV0::unpack(&bridge_pda.data.borrow())?;
 
-        // Validation
-        assert_eq!(
-            user.key, &bridge_state.admin,
-            "add_spender: user is not an admin"
+        assert!(
+            bridge_spender.is_signer,
+            "transfer_in_compact: User must sign the message"
+        );
+        Processor::check_spender(
+            &bridge_state.spender_keys(),
+            &bridge_state.role_keys(),
+            bridge_spender.key,
+        )?;
+        Processor::check_not_paused(bridge_state.paused)?;
+
+        assert!(
+            transfer_in.items.len() > 0,
+            "item array length should be positive"
+        );
+
+        // Items point into the remaining accounts, so a vault or receiver shared by several
+        // items only has to be passed once.
+        let remaining = accounts_iter.as_slice();
+        for item in transfer_in.items {
+            let amount = item.amount.0;
+            assert!(amount > 0, "Amount must be positive!");
+
+            let bridge_ata = remaining
+                .get(item.vault_index as usize)
+                .ok_or(ProgramError::NotEnoughAccountKeys)?;
+            let receiver_ata = remaining
+                .get(item.receiver_index as usize)
+                .ok_or(ProgramError::NotEnoughAccountKeys)?;
+            let token_config_ai = remaining
+                .get(item.config_index as usize)
+                .ok_or(ProgramError::NotEnoughAccountKeys)?;
+
+            Processor::release_locked_tokens(
+                program_id,
+                token_program_ai,
+                bridge_ata,
+                receiver_ata,
+                bridge_pda,
+                token_config_ai,
+                bridge_state.bump,
+                amount,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Same as `release_tokens`, after counting `amount` as released in the token config of the
+    /// vault's mint. Fails if more would be released than was locked.
+    #[allow(clippy::too_many_arguments)]
+    fn release_locked_tokens<'a>(
+        program_id: &Pubkey,
+        token_program_ai: &AccountInfo<'a>,
+        bridge_ata: &AccountInfo<'a>,
+        receiver_ata: &AccountInfo<'a>,
+        bridge_pda: &AccountInfo<'a>,
+        token_config_ai: &AccountInfo<'a>,
+        bump: u8,
+        amount: u64,
+    ) -> ProgramResult {
+        // The token transfer below checks that this is a token account of the bridge pda.
+        let mint = SplTokenAccount::unpack(&bridge_ata.data.borrow())?.mint;
+        let mut token_config = Processor::load_token_config(token_config_ai, program_id, &mint)?;
+        if amount > token_config.outstanding() {
+            return Err(BridgeError::ExceedsLocked.into());
+        }
+        token_config.released += amount;
+        token_config.serialize(&mut *token_config_ai.data.borrow_mut())?;
+
+        Processor::release_tokens(
+            token_program_ai,
+            bridge_ata,
+            receiver_ata,
+            bridge_pda,
+            bump,
+            amount,
+        )
+    }
+
+    fn release_tokens<'a>(
+        token_program_ai: &AccountInfo<'a>,
+        bridge_ata: &AccountInfo<'a>,
+        receiver_ata: &AccountInfo<'a>,
+        bridge_pda: &AccountInfo<'a>,
+        bump: u8,
+        amount: u64,
+    ) -> ProgramResult {
+        invoke_signed(
+            &spl_token::instruction::transfer(
+                &spl_token::ID,
+                bridge_ata.key,
+                receiver_ata.key,
+                bridge_pda.key,
+                &[bridge_pda.key],
+                amount,
+            )?,
+            &[
+                bridge_ata.clone(),
+                receiver_ata.clone(),
+                bridge_pda.clone(),
+                token_program_ai.clone(),
+            ],
+            &[&[BRIDGE_SEED, &[bump]]],
+        )
+    }
+
+    // Applies an admin operation right away, which is only allowed while there is no timelock
+    // delay. Otherwise it goes through QueueOperation and ExecuteOperation.
+    fn admin_operation(
+        accounts_iter: &mut Iter<AccountInfo>,
+        roles: &[Role],
+        operation: AdminOperation,
+    ) -> ProgramResult {
+        let user = next_account_info(accounts_iter)?;
+        let bridge_pda = next_account_info(accounts_iter)?;
+        assert!(
+            user.is_signer,
+            "admin_operation: User must sign the message"
         );
-        assert_eq!(bridge_state.spenders.len(), 2);
 
-        // Update spender
-        let new_spender = AddSpenderData::try_from_slice(&data_vec).unwrap().spender;
-        let index = bridge_state.spender_index;
-        bridge_state.spenders[((index + 1) % 2) as usize] = new_spender;
+        let mut bridge_state = BridgeStateV0::unpack(&bridge_pda.data.borrow())?;
+        Processor::check_role(&bridge_state, user.key, roles)?;
+        if bridge_state.timelock_delay > 0 {
+            return Err(BridgeError::TimelockRequired.into());
+        }
+
+        Processor::apply_operation(&mut bridge_state, operation)?;
 
-        // Serialize back to the bridge pda.
         bridge_state.serialize(&mut *bridge_pda.data.borrow_mut())?;
 
         Ok(())
     }
+
+    fn apply_operation(
+        bridge_state: &mut BridgeStateV0,
+        operation: AdminOperation,
+    ) -> ProgramResult {
+        match operation {
+            AdminOperation::ActivateSpender(data) => {
+                Processor::check_separation(bridge_state, &data.spender, Role::Relayer)?;
+                Processor::activate_spender(bridge_state, data)
+            }
+            AdminOperation::RemoveSpender(data) => Processor::remove_spender(bridge_state, data),
+            AdminOperation::ChangeAdmin(data) => {
+                let new_admin = data.new_admin;
+                Processor::check_separation(bridge_state, &new_admin, Role::Admin)?;
+                msg!("Bridge admin = {:?}", new_admin);
+                bridge_state.admin = new_admin;
+                Ok(())
+            }
+            AdminOperation::SetTimelock(data) => {
+                Processor::check_separation(bridge_state, &data.guardian, Role::Guardian)?;
+                msg!(
+                    "Bridge guardian = {:?}, timelock delay = {:?}s",
+                    data.guardian,
+                    data.delay
+                );
+                bridge_state.guardian = data.guardian;
+                bridge_state.timelock_delay = data.delay;
+                Ok(())
+            }
+            AdminOperation::SetCpiCaller(data) => Processor::set_cpi_caller(bridge_state, data),
+            AdminOperation::SetRole(data) => Processor::set_role(bridge_state, data),
+            AdminOperation::Decommission(data) => {
+                assert_ne!(
+                    data.treasury,
+                    Pubkey::default(),
+                    "decommission: treasury must be set"
+                );
+                msg!("Bridge decommissioned, treasury = {:?}", data.treasury);
+                bridge_state.paused = true;
+                bridge_state.treasury = data.treasury;
+                Ok(())
+            }
+        }
+    }
+
+    fn set_cpi_caller(bridge_state: &mut BridgeStateV0, data: SetCpiCallerData) -> ProgramResult {
+        assert_ne!(
+            data.program,
+            Pubkey::default(),
+            "set_cpi_caller: program must be set"
+        );
+        msg!("CPI caller {:?} allowed = {:?}", data.program, data.allowed);
+
+        let callers = &mut bridge_state.cpi_callers;
+        if !data.allowed {
+            for caller in callers.iter_mut().filter(|caller| **caller == data.program) {
+                *caller = Pubkey::default();
+            }
+            return Ok(());
+        }
+        if callers.contains(&data.program) {
+            return Ok(());
+        }
+        let free = callers
+            .iter_mut()
+            .find(|caller| **caller == Pubkey::default())
+            .ok_or(BridgeError::TooManyCpiCallers)?;
+        *free = data.program;
+
+        Ok(())
+    }
+
+    fn set_role(bridge_state: &mut BridgeStateV0, data: SetRoleData) -> ProgramResult {
+        assert_ne!(
+            data.holder,
+            Pubkey::default(),
+            "set_role: holder must be set"
+        );
+        msg!(
+            "Role {:?} of {:?} granted = {:?}",
+            data.role,
+            data.holder,
+            data.granted
+        );
+
+        let slot = bridge_state
+            .role_holders
+            .iter()
+            .position(|holder| *holder == data.holder);
+        if !data.granted {
+            if let Some(slot) = slot {
+                bridge_state.role_masks[slot] &= !data.role.bit();
+                // A holder without roles frees its slot.
+                if bridge_state.role_masks[slot] == 0 {
+                    bridge_state.role_holders[slot] = Pubkey::default();
+                }
+            }
+            return Ok(());
+        }
+
+        Processor::check_separation(bridge_state, &data.holder, data.role)?;
+        let slot = match slot {
+            Some(slot) => slot,
+            None => bridge_state
+                .role_holders
+                .iter()
+                .position(|holder| *holder == Pubkey::default())
+                .ok_or(BridgeError::TooManyRoleHolders)?,
+        };
+        bridge_state.role_holders[slot] = data.holder;
+        bridge_state.role_masks[slot] |= data.role.bit();
+
+        Ok(())
+    }
+
+    fn activate_spender(
+        bridge_state: &mut BridgeStateV0,
+        data: ActivateSpenderData,
+    ) -> ProgramResult {
+        // The current spender moves to the previous slot and keeps signing until the deadline.
+        let clock = Clock::get()?;
+        let (until_slot, until) = match data.overlap {
+            SpenderOverlap::Slots(slots) => (clock.slot.saturating_add(slots), i64::MAX),
+            SpenderOverlap::Seconds(seconds) => (
+                u64::MAX,
+                clock
+                    .unix_timestamp
+                    .saturating_add(i64::try_from(seconds).unwrap_or(i64::MAX)),
+            ),
+        };
+        let index = (bridge_state.spender_index + 1) % 2;
+        bridge_state.spenders[index as usize] = data.spender;
+        bridge_state.spender_index = index;
+        bridge_state.spender_epoch += 1;
+        bridge_state.previous_valid_until_slot = until_slot;
+        bridge_state.previous_valid_until = until;
+
+        let keys = bridge_state.spender_keys();
+        Processor::emit(BridgeEvent::SpenderActivated(SpenderActivatedEvent {
+            epoch: bridge_state.spender_epoch,
+            spender: *keys.current(),
+            previous: *keys.previous(),
+            previous_valid_until_slot: until_slot,
+            previous_valid_until: until,
+        }))
+    }
+
+    fn remove_spender(bridge_state: &mut BridgeStateV0, data: AddSpenderData) -> ProgramResult {
+        // An empty slot holds the default pubkey, which nobody can sign for.
+        let spender = data.spender;
+        if spender == Pubkey::default() {
+            return Err(BridgeError::NotASpender.into());
+        }
+        let slot = bridge_state
+            .spenders
+            .iter()
+            .position(|key| *key == spender)
+            .ok_or(BridgeError::NotASpender)?;
+        bridge_state.spenders[slot] = Pubkey::default();
+
+        Ok(())
+    }
+
+    fn queue_operation(
+        accounts_iter: &mut Iter<AccountInfo>,
+        program_id: &Pubkey,
+        roles: &[Role],
+        data: QueueOperationData,
+    ) -> ProgramResult {
+        let user = next_account_info(accounts_iter)?;
+        let bridge_pda = next_account_info(accounts_iter)?;
+        let operation_ai = next_account_info(accounts_iter)?;
+        let sys_program = next_account_info(accounts_iter)?;
+
+        assert!(
+            user.is_signer,
+            "queue_operation: User must sign the message"
+        );
+        assert!(system_program::check_id(sys_program.key));
+
+        let bridge_state = Processor::load_bridge_state(bridge_pda, program_id)?;
+        Processor::check_role(&bridge_state, user.key, roles)?;
+
+        let id = data.id;
+        let (calculated_operation, bump) =
+            Pubkey::find_program_address(&[OPERATION_SEED, &id.to_le_bytes()], program_id);
+        assert_eq!(operation_ai.key, &calculated_operation);
+
+        let delay = i64::try_from(bridge_state.timelock_delay).unwrap_or(i64::MAX);
+        let eta = Clock::get()?.unix_timestamp.saturating_add(delay);
+        let pending = PendingOperation {
+            id,
+            eta,
+            bump,
+            operation: data.operation,
+        };
+        let len = pending.try_to_vec()?.len();
+
+        // Fails if an operation with this id is already queued.
+        invoke_signed(
+            &system_instruction::create_account(
+                user.key,
+                operation_ai.key,
+                Rent::get()?.minimum_balance(len),
+                len as u64,
+                program_id,
+            ),
+            &[user.clone(), operation_ai.clone(), sys_program.clone()],
+            &[&[OPERATION_SEED, &id.to_le_bytes(), &[bump]]],
+        )?;
+        pending.serialize(&mut *operation_ai.data.borrow_mut())?;
+
+        Processor::emit(BridgeEvent::OperationQueued(OperationQueuedEvent {
+            id,
+            eta,
+            operation: pending.operation,
+        }))
+    }
+
+    fn execute_operation(
+        accounts_iter: &mut Iter<AccountInfo>,
+        program_id: &Pubkey,
+        roles: &[Role],
+        data: OperationData,
+    ) -> ProgramResult {
+        let user = next_account_info(accounts_iter)?;
+        let bridge_pda = next_account_info(accounts_iter)?;
+        let operation_ai = next_account_info(accounts_iter)?;
+
+        assert!(
+            user.is_signer,
+            "execute_operation: User must sign the message"
+        );
+
+        let mut bridge_state = Processor::load_bridge_state(bridge_pda, program_id)?;
+        Processor::check_role(&bridge_state, user.key, roles)?;
+
+        let pending = Processor::load_pending_operation(operation_ai, program_id, data.id)?;
+        if Clock::get()?.unix_timestamp < pending.eta {
+            return Err(BridgeError::OperationNotReady.into());
+        }
+
+        Processor::apply_operation(&mut bridge_state, pending.operation)?;
+        bridge_state.serialize(&mut *bridge_pda.data.borrow_mut())?;
+        Processor::close_account(operation_ai, user)?;
+
+        Processor::emit(BridgeEvent::OperationExecuted(OperationEvent {
+            id: data.id,
+        }))
+    }
+
+    // Guardians can cancel a queued operation until it is executed.
+    fn cancel_operation(
+        accounts_iter: &mut Iter<AccountInfo>,
+        program_id: &Pubkey,
+        roles: &[Role],
+        data: OperationData,
+    ) -> ProgramResult {
+        let guardian = next_account_info(accounts_iter)?;
+        let bridge_pda = next_account_info(accounts_iter)?;
+        let operation_ai = next_account_info(accounts_iter)?;
+
+        assert!(
+            guardian.is_signer,
+            "cancel_operation: Guardian must sign the message"
+        );
+
+        let bridge_state = Processor::load_bridge_state(bridge_pda, program_id)?;
+        Processor::check_role(&bridge_state, guardian.key, roles)?;
+
+        Processor::load_pending_operation(operation_ai, program_id, data.id)?;
+        Processor::close_account(operation_ai, guardian)?;
+
+        Processor::emit(BridgeEvent::OperationCancelled(OperationEvent {
+            id: data.id,
+        }))
+    }
+
+    fn set_paused(
+        accounts_iter: &mut Iter<AccountInfo>,
+        roles: &[Role],
+        data: SetPausedData,
+    ) -> ProgramResult {
+        let user = next_account_info(accounts_iter)?;
+        let bridge_pda = next_account_info(accounts_iter)?;
+        assert!(user.is_signer, "set_paused: User must sign the message");
+
+        let mut bridge_state = BridgeStateV0::unpack(&bridge_pda.data.borrow())?;
+        Processor::check_role(&bridge_state, user.key, roles)?;
+        if !data.paused {
+            Processor::check_not_decommissioned(&bridge_state)?;
+        }
+
+        bridge_state.paused = data.paused;
+        msg!("Bridge paused = {:?}", bridge_state.paused);
+
+        bridge_state.serialize(&mut *bridge_pda.data.borrow_mut())?;
+
+        Ok(())
+    }
+
+    fn register_token(
+        accounts_iter: &mut Iter<AccountInfo>,
+        program_id: &Pubkey,
+        roles: &[Role],
+        data: RegisterTokenData,
+    ) -> ProgramResult {
+        let user = next_account_info(accounts_iter)?;
+        let bridge_pda = next_account_info(accounts_iter)?;
+        let token_config_ai = next_account_info(accounts_iter)?;
+        let mint = next_account_info(accounts_iter)?;
+        let sys_program = next_account_info(accounts_iter)?;
+
+        assert!(user.is_signer, "register_token: User must sign the message");
+        assert!(system_program::check_id(sys_program.key));
+        // Native SOL is registered under `NATIVE_SOL_MINT`, which is the system program id.
+        let native = mint.key == &NATIVE_SOL_MINT;
+        assert!(
+            native || mint.owner == &spl_token::ID,
+            "register_token: not an spl mint"
+        );
+
+        let mut bridge_state = Processor::load_bridge_state(bridge_pda, program_id)?;
+        Processor::check_role(&bridge_state, user.key, roles)?;
+        Processor::check_not_decommissioned(&bridge_state)?;
+
+        let enabled = data.enabled;
+
+        // The native vault only follows for native SOL.
+        let native_vault = if native {
+            let native_vault = next_account_info(accounts_iter)?;
+            Processor::native_vault_bump(native_vault, program_id)?;
+            Some(native_vault)
+        } else {
+            None
+        };
+
+        let mut token_config = if token_config_ai.data_is_empty() {
+            let (calculated_config, bump) =
+                Pubkey::find_program_address(&[TOKEN_SEED, mint.key.as_ref()], program_id);
+            assert_eq!(token_config_ai.key, &calculated_config);
+
+            // The native vault is a plain system account and has to stay rent exempt. The
+            // registrar tops it up to the reserve, which is never counted as locked, so the
+            // last lamport locked can still be released.
+            if let Some(native_vault) = native_vault {
+                let vault_reserve = Rent::get()?.minimum_balance(0);
+                let shortfall = vault_reserve.saturating_sub(native_vault.lamports());
+                if shortfall > 0 {
+                    invoke(
+                        &system_instruction::transfer(user.key, native_vault.key, shortfall),
+                        &[user.clone(), native_vault.clone(), sys_program.clone()],
+                    )?;
+                }
+            }
+
+            invoke_signed(
+                &system_instruction::create_account(
+                    user.key,
+                    token_config_ai.key,
+                    Rent::get()?.minimum_balance(TokenConfig::LEN),
+                    TokenConfig::LEN as u64,
+                    program_id,
+                ),
+                &[user.clone(), token_config_ai.clone(), sys_program.clone()],
+                &[&[TOKEN_SEED, mint.key.as_ref(), &[bump]]],
+            )?;
+            bridge_state.token_count += 1;
+            bridge_state.serialize(&mut *bridge_pda.data.borrow_mut())?;
+
+            // Nothing leaves before the mint is registered, so the count starts at zero. Whatever
+            // the vault already holds was donated and shows up as surplus, beyond the reserve
+            // for native SOL.
+            TokenConfig {
+                mint: *mint.key,
+                enabled,
+                bump,
+                locked: 0,
+                released: 0,
+            }
+        } else {
+            Processor::load_token_config(token_config_ai, program_id, mint.key)?
+        };
+
+        msg!("Token {:?} enabled = {:?}", mint.key, enabled);
+        token_config.enabled = enabled;
+        token_config.serialize(&mut *token_config_ai.data.borrow_mut())?;
+
+        Ok(())
+    }
+
+    // Moves a vault's balance to the treasury and closes the vault, and the token config if the
+    // mint is registered.
+    fn sweep_vault(
+        accounts_iter: &mut Iter<AccountInfo>,
+        program_id: &Pubkey,
+        roles: &[Role],
+    ) -> ProgramResult {
+        let user = next_account_info(accounts_iter)?;
+        let bridge_pda = next_account_info(accounts_iter)?;
+        let token_program_ai = next_account_info(accounts_iter)?;
+        let token_config_ai = next_account_info(accounts_iter)?;
+        let vault = next_account_info(accounts_iter)?;
+        let treasury_ata = next_account_info(accounts_iter)?;
+        let treasury = next_account_info(accounts_iter)?;
+
+        assert!(user.is_signer, "sweep_vault: User must sign the message");
+        assert!(spl_token::check_id(token_program_ai.key));
+
+        let mut bridge_state = Processor::load_bridge_state(bridge_pda, program_id)?;
+        Processor::check_role(&bridge_state, user.key, roles)?;
+        Processor::check_decommissioned(&bridge_state)?;
+        assert_eq!(treasury.key, &bridge_state.treasury);
+
+        // Any token account of the bridge pda can be swept, but only the associated one holds
+        // locked tokens. A token can be registered without its vault ever being created.
+        let vault_token = if vault.data_is_empty() {
+            None
+        } else {
+            assert_eq!(vault.owner, &spl_token::ID, "sweep_vault: not a token account");
+            let vault_token = SplTokenAccount::unpack(&vault.data.borrow())?;
+            assert_eq!(
+                &vault_token.owner, bridge_pda.key,
+                "sweep_vault: not a bridge vault"
+            );
+            Some(vault_token)
+        };
+        let mint = match &vault_token {
+            Some(vault_token) => vault_token.mint,
+            None => TokenConfig::try_from_slice(&token_config_ai.data.borrow())?.mint,
+        };
+        // Other accounts only hold donations, sweeping them leaves the config in place.
+        let is_ata = vault.key == &get_associated_token_address(bridge_pda.key, &mint);
+        assert!(
+            is_ata || vault_token.is_some(),
+            "sweep_vault: not the bridge ata of the mint"
+        );
+        let token_config = if is_ata {
+            Processor::load_registered_config(token_config_ai, program_id, &mint)?
+        } else {
+            None
+        };
+
+        let mut amount = 0;
+        if let Some(vault_token) = vault_token {
+            amount = vault_token.amount;
+            if amount > 0 {
+                let treasury_account = SplTokenAccount::unpack(&treasury_ata.data.borrow())?;
+                assert_eq!(
+                    treasury_account.owner, bridge_state.treasury,
+                    "sweep_vault: not a treasury account"
+                );
+                Processor::release_tokens(
+                    token_program_ai,
+                    vault,
+                    treasury_ata,
+                    bridge_pda,
+                    bridge_state.bump,
+                    amount,
+                )?;
+            }
+
+            // The token program refuses to close an account that still holds tokens.
+            invoke_signed(
+                &spl_token::instruction::close_account(
+                    &spl_token::ID,
+                    vault.key,
+                    treasury.key,
+                    bridge_pda.key,
+                    &[bridge_pda.key],
+                )?,
+                &[
+                    vault.clone(),
+                    treasury.clone(),
+                    bridge_pda.clone(),
+                    token_program_ai.clone(),
+                ],
+                &[&[BRIDGE_SEED, &[bridge_state.bump]]],
+            )?;
+        }
+
+        if token_config.is_some() {
+            Processor::close_account(token_config_ai, treasury)?;
+            bridge_state.token_count = bridge_state.token_count.saturating_sub(1);
+            bridge_state.serialize(&mut *bridge_pda.data.borrow_mut())?;
+        }
+
+        Processor::emit(BridgeEvent::VaultSwept(VaultSweptEvent {
+            mint,
+            amount,
+            treasury: *treasury.key,
+        }))
+    }
+
+    // Last step of the decommission: sweeps the native vault and closes the bridge pda. Every
+    // token config must have been swept before, which sweeps the vault holding its locked tokens.
+    // Donations to other bridge accounts should be swept first too, nothing can move them
+    // afterwards.
+    fn close_bridge(
+        accounts_iter: &mut Iter<AccountInfo>,
+        program_id: &Pubkey,
+        roles: &[Role],
+    ) -> ProgramResult {
+        let user = next_account_info(accounts_iter)?;
+        let bridge_pda = next_account_info(accounts_iter)?;
+        let native_vault = next_account_info(accounts_iter)?;
+        let treasury = next_account_info(accounts_iter)?;
+        let sys_program = next_account_info(accounts_iter)?;
+        let token_config_ai = next_account_info(accounts_iter)?;
+
+        assert!(user.is_signer, "close_bridge: User must sign the message");
+        assert!(system_program::check_id(sys_program.key));
+
+        let bridge_state = Processor::load_bridge_state(bridge_pda, program_id)?;
+        Processor::check_role(&bridge_state, user.key, roles)?;
+        Processor::check_decommissioned(&bridge_state)?;
+        assert_eq!(treasury.key, &bridge_state.treasury);
+        // The native vault is swept here, so only the native config may still be registered.
+        let native_config =
+            Processor::load_registered_config(token_config_ai, program_id, &NATIVE_SOL_MINT)?;
+        if bridge_state.token_count > u32::from(native_config.is_some()) {
+            return Err(BridgeError::TokensRemaining.into());
+        }
+        if native_config.is_some() {
+            Processor::close_account(token_config_ai, treasury)?;
+        }
+
+        let vault_bump = Processor::native_vault_bump(native_vault, program_id)?;
+        let amount = native_vault.lamports();
+        if amount > 0 {
+            invoke_signed(
+                &system_instruction::transfer(native_vault.key, treasury.key, amount),
+                &[native_vault.clone(), treasury.clone(), sys_program.clone()],
+                &[&[NATIVE_VAULT_SEED, &[vault_bump]]],
+            )?;
+            Processor::emit(BridgeEvent::VaultSwept(VaultSweptEvent {
+                mint: NATIVE_SOL_MINT,
+                amount,
+                treasury: *treasury.key,
+            }))?;
+        }
+
+        // An empty account of the program stays behind as a tombstone. Without it the bridge
+        // could be initialized again, and its sequences would restart at 0 and collide with the
+        // receipts of this one.
+        let tombstone_rent = Rent::get()?.minimum_balance(0);
+        let lamports = bridge_pda.lamports().saturating_sub(tombstone_rent);
+        bridge_pda.data.borrow_mut().fill(0);
+        bridge_pda.realloc(0, false)?;
+        **bridge_pda.lamports.borrow_mut() -= lamports;
+        **treasury.lamports.borrow_mut() = treasury
+            .lamports()
+            .checked_add(lamports)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+
+        msg!("Bridge closed");
+        Ok(())
+    }
+
+    // Compares a vault with the counters of its token config and logs the result.
+    fn reconcile(
+        accounts_iter: &mut Iter<AccountInfo>,
+        program_id: &Pubkey,
+        roles: &[Role],
+    ) -> ProgramResult {
+        let user = next_account_info(accounts_iter)?;
+        let bridge_pda = next_account_info(accounts_iter)?;
+        let token_config_ai = next_account_info(accounts_iter)?;
+        let vault = next_account_info(accounts_iter)?;
+
+        assert!(user.is_signer, "reconcile: User must sign the message");
+
+        let bridge_state = Processor::load_bridge_state(bridge_pda, program_id)?;
+        Processor::check_role(&bridge_state, user.key, roles)?;
+
+        let mint = TokenConfig::try_from_slice(&token_config_ai.data.borrow())?.mint;
+        let token_config = Processor::load_token_config(token_config_ai, program_id, &mint)?;
+        let balance = if mint == NATIVE_SOL_MINT {
+            let (native_vault, _) = Pubkey::find_program_address(&[NATIVE_VAULT_SEED], program_id);
+            assert_eq!(vault.key, &native_vault, "reconcile: not the native vault");
+            // The rent reserve paid at registration is not bridged funds.
+            vault
+                .lamports()
+                .saturating_sub(Rent::get()?.minimum_balance(0))
+        } else {
+            assert_eq!(
+                vault.key,
+                &get_associated_token_address(bridge_pda.key, &mint),
+                "reconcile: not the bridge vault"
+            );
+            assert_eq!(vault.owner, &spl_token::ID);
+            SplTokenAccount::unpack(&vault.data.borrow())?.amount
+        };
+
+        let outstanding = token_config.outstanding();
+        let event = ReconciledEvent {
+            mint,
+            locked: token_config.locked,
+            released: token_config.released,
+            balance,
+            surplus: balance.saturating_sub(outstanding),
+            shortfall: outstanding.saturating_sub(balance),
+        };
+        msg!(
+            "Token {:?}: outstanding {}, balance {}, surplus {}, shortfall {}",
+            mint,
+            outstanding,
+            balance,
+            event.surplus,
+            event.shortfall
+        );
+        Processor::emit(BridgeEvent::Reconciled(event))
+    }
+
+    // Answers a read-only query with return data. Nothing is written and nobody has to sign.
+    fn query(
+        accounts_iter: &mut Iter<AccountInfo>,
+        program_id: &Pubkey,
+        query: BridgeQuery,
+    ) -> ProgramResult {
+        let bridge_pda = next_account_info(accounts_iter)?;
+        let bridge_state = Processor::load_bridge_state(bridge_pda, program_id)?;
+
+        let data = match query {
+            BridgeQuery::Config => BridgeReturnData::Config(ConfigInfo {
+                admin: bridge_state.admin,
+                guardian: bridge_state.guardian,
+                paused: bridge_state.paused,
+                treasury: bridge_state.treasury,
+                timelock_delay: bridge_state.timelock_delay,
+                token_count: bridge_state.token_count,
+                out_sequence: bridge_state.out_sequence,
+                message_sequence: bridge_state.message_sequence,
+            }),
+            BridgeQuery::Spenders => {
+                let keys = bridge_state.spender_keys();
+                let clock = Clock::get()?;
+                BridgeReturnData::Spenders(SpendersInfo {
+                    epoch: bridge_state.spender_epoch,
+                    current: *keys.current(),
+                    previous: *keys.previous(),
+                    previous_valid_until_slot: bridge_state.previous_valid_until_slot,
+                    previous_valid_until: bridge_state.previous_valid_until,
+                    previous_active: keys.previous_valid_at(clock.slot, clock.unix_timestamp),
+                })
+            }
+            BridgeQuery::Token => {
+                let token_config_ai = next_account_info(accounts_iter)?;
+                let mint = TokenConfig::try_from_slice(&token_config_ai.data.borrow())?.mint;
+                let token_config =
+                    Processor::load_token_config(token_config_ai, program_id, &mint)?;
+                BridgeReturnData::Token(TokenInfo {
+                    mint,
+                    enabled: token_config.enabled,
+                    locked: token_config.locked,
+                    released: token_config.released,
+                })
+            }
+        };
+        set_return_data(&data.pack());
+
+        Ok(())
+    }
+
+    fn transfer_out_native(
+        accounts_iter: &mut Iter<AccountInfo>,
+        program_id: &Pubkey,
+        payload: TransferOutNativeData,
+    ) -> ProgramResult {
+        let user = next_account_info(accounts_iter)?;
+        let bridge_pda = next_account_info(accounts_iter)?;
+        let native_vault = next_account_info(accounts_iter)?;
+        let sys_program = next_account_info(accounts_iter)?;
+        let receipt_ai = next_account_info(accounts_iter)?;
+        let token_config_ai = next_account_info(accounts_iter)?;
+
+        // Validation
+        assert!(
+            user.is_signer,
+            "transfer_out_native: User must sign the message"
+        );
+        assert!(system_program::check_id(sys_program.key));
+        Processor::native_vault_bump(native_vault, program_id)?;
+
+        assert!(payload.amount > 0, "Amount must be positive!");
+        assert!(
+            payload.recipient.len() > 0,
+            "transfer_out_native: recipient cannot be empty"
+        );
+
+        // Unlike the token paths, no transfer signed by the pda vouches for `bridge_pda` here.
+        assert_eq!(bridge_pda.owner, program_id);
+        let (bump, sequence) = Processor::outbound_state(bridge_pda)?;
+        let expected_pda = Pubkey::create_program_address(&[BRIDGE_SEED, &[bump]], program_id)?;
+        assert_eq!(bridge_pda.key, &expected_pda);
+        let mut token_config =
+            Processor::load_token_config(token_config_ai, program_id, &NATIVE_SOL_MINT)?;
+        Processor::check_token_enabled(&token_config)?;
+
+        // Lock the lamports in the vault. The user signs the transfer directly.
+        invoke(
+            &system_instruction::transfer(user.key, native_vault.key, payload.amount),
+            &[user.clone(), native_vault.clone(), sys_program.clone()],
+        )?;
+        token_config.locked = token_config
+            .locked
+            .checked_add(payload.amount)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        token_config.serialize(&mut *token_config_ai.data.borrow_mut())?;
+
+        Processor::record_transfer_out(
+            program_id,
+            user,
+            receipt_ai,
+            sys_program,
+            *user.key,
+            TransferOutEvent {
+                sequence,
+                sender: *user.key,
+                mint: NATIVE_SOL_MINT,
+                token_address: String::new(),
+                amount: payload.amount,
+                chain_id: payload.chain_id,
+                recipient: payload.recipient,
+            },
+            vec![],
+        )?;
+
+        Processor::set_out_sequence(bridge_pda, sequence + 1)
+    }
+
+    fn transfer_in_native(
+        accounts_iter: &mut Iter<AccountInfo>,
+        program_id: &Pubkey,
+        transfer_in: TransferInData,
+    ) -> ProgramResult {
+        let bridge_spender = next_account_info(accounts_iter)?;
+        let bridge_pda = next_account_info(accounts_iter)?;
+        let native_vault = next_account_info(accounts_iter)?;
+        let sys_program = next_account_info(accounts_iter)?;
+        let token_config_ai = next_account_info(accounts_iter)?;
+
+        assert!(
+            bridge_spender.is_signer,
+            "transfer_in_native: User must sign the message"
+        );
+        assert!(system_program::check_id(sys_program.key));
+
+        let bridge_state = Processor::load_bridge_state(bridge_pda, program_id)?;
+        Processor::check_spender(
+            &bridge_state.spender_keys(),
+            &bridge_state.role_keys(),
+            bridge_spender.key,
+        )?;
+        Processor::check_not_paused(bridge_state.paused)?;
+        let vault_bump = Processor::native_vault_bump(native_vault, program_id)?;
+
+        assert!(
+            transfer_in.amounts.len() > 0,
+            "amount array length should be positive"
+        );
+
+        for amount in transfer_in.amounts {
+            assert!(amount > 0, "Amount must be positive!");
+
+            let receiver = next_account_info(accounts_iter)?;
+            Processor::release_locked_lamports(
+                program_id,
+                native_vault,
+                receiver,
+                sys_program,
+                token_config_ai,
+                vault_bump,
+                amount,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn release_locked_lamports<'a>(
+        program_id: &Pubkey,
+        native_vault: &AccountInfo<'a>,
+        receiver: &AccountInfo<'a>,
+        sys_program: &AccountInfo<'a>,
+        token_config_ai: &AccountInfo<'a>,
+        vault_bump: u8,
+        amount: u64,
+    ) -> ProgramResult {
+        let mut token_config =
+            Processor::load_token_config(token_config_ai, program_id, &NATIVE_SOL_MINT)?;
+        if amount > token_config.outstanding() {
+            return Err(BridgeError::ExceedsLocked.into());
+        }
+        token_config.released += amount;
+        token_config.serialize(&mut *token_config_ai.data.borrow_mut())?;
+
+        Processor::release_lamports(native_vault, receiver, sys_program, vault_bump, amount)
+    }
+
+    fn release_lamports<'a>(
+        native_vault: &AccountInfo<'a>,
+        receiver: &AccountInfo<'a>,
+        sys_program: &AccountInfo<'a>,
+        vault_bump: u8,
+        amount: u64,
+    ) -> ProgramResult {
+        // The vault is a plain system account, so it has to stay rent exempt. The reserve paid at
+        // registration covers it as long as only locked lamports are released.
+        let vault_reserve = Rent::get()?.minimum_balance(0);
+        if native_vault.lamports().saturating_sub(amount) < vault_reserve {
+            return Err(ProgramError::InsufficientFunds);
+        }
+
+        invoke_signed(
+            &system_instruction::transfer(native_vault.key, receiver.key, amount),
+            &[native_vault.clone(), receiver.clone(), sys_program.clone()],
+            &[&[NATIVE_VAULT_SEED, &[vault_bump]]],
+        )
+    }
+
+    fn refund(
+        accounts_iter: &mut Iter<AccountInfo>,
+        program_id: &Pubkey,
+        data: ReceiptData,
+    ) -> ProgramResult {
+        let bridge_spender = next_account_info(accounts_iter)?;
+        let bridge_pda = next_account_info(accounts_iter)?;
+        let receipt_ai = next_account_info(accounts_iter)?;
+        // Bridge ata and token program, or native vault and system program for native SOL.
+        let vault = next_account_info(accounts_iter)?;
+        let destination = next_account_info(accounts_iter)?;
+        let transfer_program = next_account_info(accounts_iter)?;
+        let token_config_ai = next_account_info(accounts_iter)?;
+
+        assert!(
+            bridge_spender.is_signer,
+            "refund: User must sign the message"
+        );
+        let bridge_state = Processor::load_bridge_state(bridge_pda, program_id)?;
+        Processor::check_spender(
+            &bridge_state.spender_keys(),
+            &bridge_state.role_keys(),
+            bridge_spender.key,
+        )?;
+
+        let sequence = data.sequence;
+        let mut receipt = Processor::load_pending_receipt(receipt_ai, program_id, sequence)?;
+
+        // Funds only ever go back to the account they came from.
+        assert_eq!(
+            destination.key, &receipt.source,
+            "refund: destination must be the original source account"
+        );
+
+        if receipt.mint == NATIVE_SOL_MINT {
+            assert!(system_program::check_id(transfer_program.key));
+            let vault_bump = Processor::native_vault_bump(vault, program_id)?;
+            Processor::release_locked_lamports(
+                program_id,
+                vault,
+                destination,
+                transfer_program,
+                token_config_ai,
+                vault_bump,
+                receipt.amount,
+            )?;
+        } else {
+            Processor::release_locked_tokens(
+                program_id,
+                transfer_program,
+                vault,
+                destination,
+                bridge_pda,
+                token_config_ai,
+                bridge_state.bump,
+                receipt.amount,
+            )?;
+        }
+
+        receipt.status = ReceiptStatus::Refunded;
+        receipt.serialize(&mut *receipt_ai.data.borrow_mut())?;
+
+        Processor::emit(BridgeEvent::Refunded(RefundedEvent {
+            sequence,
+            destination: *destination.key,
+            mint: receipt.mint,
+            amount: receipt.amount,
+        }))
+    }
+
+    fn mark_delivered(
+        accounts_iter: &mut Iter<AccountInfo>,
+        program_id: &Pubkey,
+        data: ReceiptData,
+    ) -> ProgramResult {
+        let bridge_spender = next_account_info(accounts_iter)?;
+        let bridge_pda = next_account_info(accounts_iter)?;
+        let receipt_ai = next_account_info(accounts_iter)?;
+
+        assert!(
+            bridge_spender.is_signer,
+            "mark_delivered: User must sign the message"
+        );
+        let bridge_state = Processor::load_bridge_state(bridge_pda, program_id)?;
+        Processor::check_spender(
+            &bridge_state.spender_keys(),
+            &bridge_state.role_keys(),
+            bridge_spender.key,
+        )?;
+
+        let sequence = data.sequence;
+        let mut receipt = Processor::load_pending_receipt(receipt_ai, program_id, sequence)?;
+
+        receipt.status = ReceiptStatus::Delivered;
+        receipt.serialize(&mut *receipt_ai.data.borrow_mut())?;
+
+        Processor::emit(BridgeEvent::Delivered(DeliveredEvent { sequence }))
+    }
+
+    // The transfer paths read and update the bridge state in place. The `borsh-state` build
+    // decodes and encodes the whole account instead, as they used to, so that
+    // `test_compute_units` can measure the difference.
+
+    // The bump and the next outbound sequence, unless the bridge is paused.
+    #[cfg(not(feature = "borsh-state"))]
+    fn outbound_state(bridge_pda: &AccountInfo) -> Result<(u8, u64), ProgramError> {
+        let data = bridge_pda.data.borrow();
+        let bridge_state = BridgeStateV0Pod::load(&data)?;
+        Processor::check_not_paused(bridge_state.paused())?;
+
+        Ok((bridge_state.bump, bridge_state.out_sequence()))
+    }
+
+    #[cfg(feature = "borsh-state")]
+    fn outbound_state(bridge_pda: &AccountInfo) -> Result<(u8, u64), ProgramError> {
+        let bridge_state = BridgeStateV0::unpack(&bridge_pda.data.borrow())?;
+        Processor::check_not_paused(bridge_state.paused)?;
+
+        Ok((bridge_state.bump, bridge_state.out_sequence))
+    }
+
+    #[cfg(not(feature = "borsh-state"))]
+    fn set_out_sequence(bridge_pda: &AccountInfo, sequence: u64) -> ProgramResult {
+        BridgeStateV0Pod::load_mut(&mut bridge_pda.data.borrow_mut())?.set_out_sequence(sequence);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "borsh-state")]
+    fn set_out_sequence(bridge_pda: &AccountInfo, sequence: u64) -> ProgramResult {
+        let mut bridge_state = BridgeStateV0::unpack(&bridge_pda.data.borrow())?;
+        bridge_state.out_sequence = sequence;
+        bridge_state.serialize(&mut *bridge_pda.data.borrow_mut())?;
+
+        Ok(())
+    }
+
+    // The bump, if `spender` may release funds and the bridge is not paused.
+    #[cfg(not(feature = "borsh-state"))]
+    fn inbound_state(bridge_pda: &AccountInfo, spender: &Pubkey) -> Result<u8, ProgramError> {
+        let data = bridge_pda.data.borrow();
+        let bridge_state = BridgeStateV0Pod::load(&data)?;
+        Processor::check_spender(
+            &bridge_state.spender_keys(),
+            &bridge_state.role_keys(),
+            spender,
+        )?;
+        Processor::check_not_paused(bridge_state.paused())?;
+
+        Ok(bridge_state.bump)
+    }
+
+    #[cfg(feature = "borsh-state")]
+    fn inbound_state(bridge_pda: &AccountInfo, spender: &Pubkey) -> Result<u8, ProgramError> {
+        let bridge_state = BridgeStateV0::unpack(&bridge_pda.data.borrow())?;
+        Processor::check_spender(
+            &bridge_state.spender_keys(),
+            &bridge_state.role_keys(),
+            spender,
+        )?;
+        Processor::check_not_paused(bridge_state.paused)?;
+
+        Ok(bridge_state.bump)
+    }
+
+    // Reads the bridge state after checking that `bridge_pda` really is this program's bridge
+    // account. Needed wherever no token transfer signed by the pda ties the two together.
+    fn load_bridge_state(
+        bridge_pda: &AccountInfo,
+        program_id: &Pubkey,
+    ) -> Result<BridgeStateV0, ProgramError> {
+        assert_eq!(bridge_pda.owner, program_id);
+        let bridge_state = BridgeStateV0::unpack(&bridge_pda.data.borrow())?;
+        let expected_pda =
+            Pubkey::create_program_address(&[BRIDGE_SEED, &[bridge_state.bump]], program_id)?;
+        assert_eq!(bridge_pda.key, &expected_pda);
+
+        Ok(bridge_state)
+    }
+
+    fn load_pending_receipt(
+        receipt_ai: &AccountInfo,
+        program_id: &Pubkey,
+        sequence: u64,
+    ) -> Result<TransferReceipt, ProgramError> {
+        assert_eq!(receipt_ai.owner, program_id);
+        let receipt = TransferReceipt::try_from_slice(&receipt_ai.data.borrow())?;
+        let expected_receipt = Pubkey::create_program_address(
+            &[RECEIPT_SEED, &sequence.to_le_bytes(), &[receipt.bump]],
+            program_id,
+        )?;
+        assert_eq!(receipt_ai.key, &expected_receipt);
+
+        if receipt.status != ReceiptStatus::Pending {
+            return Err(BridgeError::ReceiptNotPending.into());
+        }
+
+        Ok(receipt)
+    }
+
+    fn load_pending_operation(
+        operation_ai: &AccountInfo,
+        program_id: &Pubkey,
+        id: u64,
+    ) -> Result<PendingOperation, ProgramError> {
+        assert_eq!(operation_ai.owner, program_id);
+        let pending = PendingOperation::try_from_slice(&operation_ai.data.borrow())?;
+        let expected_operation = Pubkey::create_program_address(
+            &[OPERATION_SEED, &id.to_le_bytes(), &[pending.bump]],
+            program_id,
+        )?;
+        assert_eq!(operation_ai.key, &expected_operation);
+
+        Ok(pending)
+    }
+
+    // Moves the rent to `destination` and wipes the data, the runtime then drops the account.
+    fn close_account(account: &AccountInfo, destination: &AccountInfo) -> ProgramResult {
+        let lamports = account.lamports();
+        **destination.lamports.borrow_mut() = destination
+            .lamports()
+            .checked_add(lamports)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        **account.lamports.borrow_mut() = 0;
+        account.data.borrow_mut().fill(0);
+
+        Ok(())
+    }
+
+    // Lets `key` through if it holds one of `roles`. Instructions that were reserved to the admin
+    // or the guardian before there were roles keep their errors.
+    fn check_role(bridge_state: &BridgeStateV0, key: &Pubkey, roles: &[Role]) -> ProgramResult {
+        if roles.iter().any(|role| bridge_state.has_role(key, *role)) {
+            return Ok(());
+        }
+
+        let error = match roles {
+            [Role::Admin] => BridgeError::NotAnAdmin,
+            [Role::Guardian] => BridgeError::NotAGuardian,
+            _ => BridgeError::MissingRole,
+        };
+        Err(error.into())
+    }
+
+    // Relayers and guardians sign day to day with hot keys, which must never hold a config role
+    // as well. The key that initializes the bridge only holds Admin, the hot roles are given to
+    // other keys.
+    fn check_separation(bridge_state: &BridgeStateV0, key: &Pubkey, role: Role) -> ProgramResult {
+        let conflicting = if role.is_config() {
+            Role::HOT
+        } else {
+            Role::CONFIG
+        };
+        if bridge_state.roles_of(key) & conflicting != 0 {
+            msg!("{:?} cannot hold {:?}", key, role);
+            return Err(BridgeError::RoleConflict.into());
+        }
+
+        Ok(())
+    }
+
+    fn check_decommissioned(bridge_state: &BridgeStateV0) -> ProgramResult {
+        if bridge_state.is_decommissioned() {
+            return Ok(());
+        }
+
+        Err(BridgeError::NotDecommissioned.into())
+    }
+
+    fn check_not_decommissioned(bridge_state: &BridgeStateV0) -> ProgramResult {
+        if bridge_state.is_decommissioned() {
+            return Err(BridgeError::BridgeDecommissioned.into());
+        }
+
+        Ok(())
+    }
+
+    fn check_not_paused(paused: bool) -> ProgramResult {
+        if paused {
+            return Err(BridgeError::BridgePaused.into());
+        }
+
+        Ok(())
+    }
+
+    fn check_payload_len(payload: &[u8]) -> ProgramResult {
+        if payload.len() > MAX_PAYLOAD_LEN {
+            return Err(BridgeError::PayloadTooLarge.into());
+        }
+
+        Ok(())
+    }
+
+    fn load_token_config(
+        token_config_ai: &AccountInfo,
+        program_id: &Pubkey,
+        mint: &Pubkey,
+    ) -> Result<TokenConfig, ProgramError> {
+        assert_eq!(token_config_ai.owner, program_id);
+        let token_config = TokenConfig::try_from_slice(&token_config_ai.data.borrow())?;
+        let expected_config = Pubkey::create_program_address(
+            &[TOKEN_SEED, mint.as_ref(), &[token_config.bump]],
+            program_id,
+        )?;
+        assert_eq!(token_config_ai.key, &expected_config);
+
+        Ok(token_config)
+    }
+
+    // The config of `mint`, or none if the mint is unregistered. The account must sit at the
+    // config address either way, so a registered mint cannot pass for an unregistered one.
+    fn load_registered_config(
+        token_config_ai: &AccountInfo,
+        program_id: &Pubkey,
+        mint: &Pubkey,
+    ) -> Result<Option<TokenConfig>, ProgramError> {
+        if token_config_ai.owner == program_id {
+            return Processor::load_token_config(token_config_ai, program_id, mint).map(Some);
+        }
+        let (expected_config, _) =
+            Pubkey::find_program_address(&[TOKEN_SEED, mint.as_ref()], program_id);
+        assert_eq!(
+            token_config_ai.key, &expected_config,
+            "not the token config of the mint"
+        );
+
+        Ok(None)
+    }
+
+    fn check_token_enabled(token_config: &TokenConfig) -> ProgramResult {
+        if token_config.enabled {
+            return Ok(());
+        }
+
+        Err(BridgeError::TokenNotEnabled.into())
+    }
+
+    // The spenders and the holders of the relayer role.
+    fn check_spender(keys: &SpenderKeys, roles: &RoleKeys, key: &Pubkey) -> ProgramResult {
+        if keys.current() == key || roles.has_role(key, Role::Relayer) {
+            return Ok(());
+        }
+        if keys.previous() == key {
+            let clock = Clock::get()?;
+            if keys.previous_valid_at(clock.slot, clock.unix_timestamp) {
+                return Ok(());
+            }
+            return Err(BridgeError::SpenderExpired.into());
+        }
+
+        Err(BridgeError::NotASpender.into())
+    }
+
+    fn native_vault_bump(
+        native_vault: &AccountInfo,
+        program_id: &Pubkey,
+    ) -> Result<u8, ProgramError> {
+        let (calculated_vault, bump) =
+            Pubkey::find_program_address(&[NATIVE_VAULT_SEED], program_id);
+        assert_eq!(native_vault.key, &calculated_vault);
+        assert!(native_vault.is_writable, "Native vault must be writable");
+
+        Ok(bump)
+    }
 }
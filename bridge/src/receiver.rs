@@ -0,0 +1,61 @@
+//! Interface of programs that receive messages through `DeliverMessage`.
+//!
+//! The bridge calls the receiver program with `ReceiveMessage` as instruction data and these
+//! accounts:
+//!
+//! 0. `[signer]` The message authority of the bridge, see `message_authority`.
+//! 1. `[writable]` The receiver's token account, only when `amount` is not zero. The tokens are
+//!    already there when the receiver runs.
+//! 2. Any accounts the relayer passed between the fixed accounts of `DeliverMessage` and the
+//!    instructions sysvar.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::AccountInfo, entrypoint::ProgramResult, program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use crate::state::MESSAGE_AUTHORITY_SEED;
+
+/// Prefix of the instruction data, so that receivers can tell a bridge call apart from their
+/// own instructions.
+pub const RECEIVE_MESSAGE_DISCRIMINATOR: [u8; 8] = *b"sisu:msg";
+
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct ReceiveMessage {
+    pub source_chain_id: u64,
+    pub nonce: u64,
+    pub sender: String,
+    pub payload: Vec<u8>,
+    pub amount: u64,
+}
+
+impl ReceiveMessage {
+    pub fn pack(&self) -> Vec<u8> {
+        let mut data = RECEIVE_MESSAGE_DISCRIMINATOR.to_vec();
+        data.extend(self.try_to_vec().unwrap());
+        data
+    }
+
+    /// `None` if `data` is not a bridge call.
+    pub fn unpack(data: &[u8]) -> Option<Self> {
+        let payload = data.strip_prefix(&RECEIVE_MESSAGE_DISCRIMINATOR)?;
+        ReceiveMessage::try_from_slice(payload).ok()
+    }
+}
+
+pub fn message_authority(bridge_program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[MESSAGE_AUTHORITY_SEED], bridge_program_id)
+}
+
+/// Receivers call this on their first account before trusting a message.
+pub fn check_message_authority(
+    bridge_program_id: &Pubkey,
+    authority: &AccountInfo,
+) -> ProgramResult {
+    if !authority.is_signer || *authority.key != message_authority(bridge_program_id).0 {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    Ok(())
+}
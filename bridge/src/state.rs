@@ -1,64 +1,1135 @@
+use std::io::{Error, ErrorKind, Result as IoResult, Write};
+
 use borsh::{BorshDeserialize, BorshSerialize};
-use solana_program::pubkey::Pubkey;
+use bytemuck::{Pod, Zeroable};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+use crate::error::BridgeError;
+
+pub const BRIDGE_SEED: &[u8] = b"SisuBridge";
+// System-owned PDA that holds the lamports of bridged native SOL.
+pub const NATIVE_VAULT_SEED: &[u8] = b"SisuBridgeVault";
+// Receipts are keyed by the little endian outbound sequence.
+pub const RECEIPT_SEED: &[u8] = b"SisuBridgeReceipt";
+// Token configs are keyed by mint.
+pub const TOKEN_SEED: &[u8] = b"SisuBridgeToken";
+// Delivery records are keyed by the little endian source chain id and nonce.
+pub const DELIVERY_SEED: &[u8] = b"SisuBridgeDelivery";
+// Signs the calls into message receivers. Unlike the bridge pda it has no authority over the
+// vaults, so a receiver cannot reuse its signature to move bridge funds.
+pub const MESSAGE_AUTHORITY_SEED: &[u8] = b"SisuBridgeMessage";
+// Queued admin operations are keyed by the little endian id the admin picked.
+pub const OPERATION_SEED: &[u8] = b"SisuBridgeOperation";
 
+// Mint recorded in events and receipts for native SOL, which has no mint account.
+pub const NATIVE_SOL_MINT: Pubkey = Pubkey::new_from_array([0; 32]);
+
+// The first byte of the instruction data is the variant, followed by its borsh payload.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "data"))]
 #[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
 pub enum BridgeInstruction {
     Initialize,
-    TransferOut,
-    TransferIn,
-    AddSpender,
-    RemoveSpender,
-    ChangeAdmin,
+    TransferOut(TransferOutData),
+    TransferIn(TransferInData),
+    /// Makes the spender current at once, the previous one expires after this slot. Same as
+    /// `ActivateSpender` with `SpenderOverlap::Slots(0)`.
+    AddSpender(AddSpenderData),
+    RemoveSpender(AddSpenderData),
+    ChangeAdmin(ChangeAdminData),
+    TransferOutNative(TransferOutNativeData),
+    TransferInNative(TransferInData),
+    TransferOutBatch(TransferOutBatchData),
+    TransferInCompact(TransferInCompactData),
+    Refund(ReceiptData),
+    MarkDelivered(ReceiptData),
+    SetPaused(SetPausedData),
+    RegisterToken(RegisterTokenData),
+    SendMessage(SendMessageData),
+    TransferOutWithPayload(TransferOutWithPayloadData),
+    DeliverMessage(DeliverMessageData),
+    ActivateSpender(ActivateSpenderData),
+    SetTimelock(SetTimelockData),
+    QueueOperation(QueueOperationData),
+    ExecuteOperation(OperationData),
+    CancelOperation(OperationData),
+    Decommission(DecommissionData),
+    SweepVault,
+    CloseBridge,
+    Reconcile,
+    Query(BridgeQuery),
+    SetCpiCaller(SetCpiCallerData),
+    SetRole(SetRoleData),
+    /// Grows a bridge account of an older layout to the current one, see
+    /// `BridgeStateV0::VERSION`.
+    Migrate,
+}
+
+impl BridgeInstruction {
+    pub const TRANSFER_IN_TAG: u8 = 2;
+    // Tag of the last variant, anything above is not an instruction at all.
+    const LAST_TAG: u8 = 29;
+
+    /// Decodes instruction data, which must hold exactly one instruction.
+    pub fn unpack(input: &[u8]) -> Result<Self, BridgeError> {
+        match input.first() {
+            Some(&tag) if tag <= BridgeInstruction::LAST_TAG => {}
+            _ => return Err(BridgeError::InvalidInstruction),
+        }
+
+        let mut data = input;
+        let instruction = <BridgeInstruction as BorshDeserialize>::deserialize(&mut data)
+            .map_err(|_| BridgeError::MalformedInstruction)?;
+        if !data.is_empty() {
+            return Err(BridgeError::TrailingInstructionData);
+        }
+
+        Ok(instruction)
+    }
+
+    pub fn pack(&self) -> Vec<u8> {
+        self.try_to_vec().unwrap()
+    }
+
+    /// Whether other programs may call the instruction freely. All others must be called at
+    /// the top level of the transaction, or directly from an allowed CPI caller, and take the
+    /// instructions sysvar as their last account.
+    pub fn allows_cpi(&self) -> bool {
+        matches!(
+            self,
+            BridgeInstruction::Initialize
+                | BridgeInstruction::TransferOut(_)
+                | BridgeInstruction::TransferOutNative(_)
+                | BridgeInstruction::TransferOutBatch(_)
+                | BridgeInstruction::SendMessage(_)
+                | BridgeInstruction::TransferOutWithPayload(_)
+                | BridgeInstruction::Query(_)
+        )
+    }
+
+    /// Roles of which the signer, the first account, must hold at least one. Spenders act as
+    /// relayers, see `Processor::check_spender`.
+    pub fn required_roles(&self) -> &'static [Role] {
+        match self {
+            BridgeInstruction::Initialize
+            | BridgeInstruction::TransferOut(_)
+            | BridgeInstruction::TransferOutNative(_)
+            | BridgeInstruction::TransferOutBatch(_)
+            | BridgeInstruction::SendMessage(_)
+            | BridgeInstruction::TransferOutWithPayload(_)
+            | BridgeInstruction::Query(_) => &[],
+            BridgeInstruction::TransferIn(_)
+            | BridgeInstruction::TransferInNative(_)
+            | BridgeInstruction::TransferInCompact(_)
+            | BridgeInstruction::Refund(_)
+            | BridgeInstruction::MarkDelivered(_)
+            | BridgeInstruction::DeliverMessage(_) => &[Role::Relayer],
+            // Pausing is an emergency power, resuming is not.
+            BridgeInstruction::SetPaused(data) if data.paused => &[Role::Guardian, Role::Admin],
+            BridgeInstruction::RegisterToken(_) => &[Role::TokenRegistrar, Role::Admin],
+            BridgeInstruction::CancelOperation(_) => &[Role::Guardian],
+            BridgeInstruction::AddSpender(_)
+            | BridgeInstruction::RemoveSpender(_)
+            | BridgeInstruction::ChangeAdmin(_)
+            | BridgeInstruction::SetPaused(_)
+            | BridgeInstruction::ActivateSpender(_)
+            | BridgeInstruction::SetTimelock(_)
+            | BridgeInstruction::QueueOperation(_)
+            | BridgeInstruction::ExecuteOperation(_)
+            | BridgeInstruction::Decommission(_)
+            | BridgeInstruction::SweepVault
+            | BridgeInstruction::CloseBridge
+            | BridgeInstruction::Reconcile
+            | BridgeInstruction::SetCpiCaller(_)
+            | BridgeInstruction::SetRole(_)
+            | BridgeInstruction::Migrate => &[Role::Admin],
+        }
+    }
+
+    /// Compute units the instruction needs at most, from the size of its batch. Clients use it
+    /// to set the compute unit limit of their transactions.
+    pub fn compute_units(&self) -> u32 {
+        let items = |n: usize| n.min(u32::MAX as usize) as u32;
+        match self {
+            BridgeInstruction::TransferIn(data) => TRANSFER_IN_BASE_UNITS
+                .saturating_add(TRANSFER_IN_ITEM_UNITS.saturating_mul(items(data.amounts.len()))),
+            BridgeInstruction::TransferInCompact(data) => TRANSFER_IN_BASE_UNITS
+                .saturating_add(TRANSFER_IN_ITEM_UNITS.saturating_mul(items(data.items.len()))),
+            BridgeInstruction::TransferInNative(data) => TRANSFER_IN_BASE_UNITS.saturating_add(
+                TRANSFER_IN_NATIVE_ITEM_UNITS.saturating_mul(items(data.amounts.len())),
+            ),
+            BridgeInstruction::TransferOutBatch(data) => {
+                TRANSFER_OUT_UNITS.saturating_mul(items(data.items.len()))
+            }
+            // The receiver runs within the instruction, at a cost only it knows.
+            BridgeInstruction::DeliverMessage(_) => {
+                TRANSFER_OUT_UNITS.saturating_add(RECEIVER_UNITS)
+            }
+            _ => TRANSFER_OUT_UNITS,
+        }
+    }
+}
+
+// Compute budgets, see `BridgeInstruction::compute_units`. `test_compute_units` checks them
+// when the program runs as BPF. Transfers out are the most expensive single instructions.
+pub const TRANSFER_OUT_UNITS: u32 = 30_000;
+pub const TRANSFER_IN_BASE_UNITS: u32 = 5_000;
+// A token transfer CPI signed by the bridge pda, and the update of the token config.
+pub const TRANSFER_IN_ITEM_UNITS: u32 = 11_000;
+// A lamport transfer from the native vault.
+pub const TRANSFER_IN_NATIVE_ITEM_UNITS: u32 = 5_000;
+// What the runtime grants an instruction by default.
+pub const RECEIVER_UNITS: u32 = 200_000;
+
+// Size of the allowlist of programs that may call the guarded instructions through CPI.
+pub const MAX_CPI_CALLERS: usize = 4;
+// Number of keys that can hold roles through `SetRole`.
+pub const MAX_ROLE_HOLDERS: usize = 8;
+
+// Powers over the bridge, see `BridgeInstruction::required_roles`. Besides the holders set
+// with SetRole, the `admin` holds Admin, the `guardian` Guardian and the spenders Relayer.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Role {
+    Admin,
+    Guardian,
+    TokenRegistrar,
+    Relayer,
+}
+
+impl Role {
+    pub const ALL: [Role; 4] = [
+        Role::Admin,
+        Role::Guardian,
+        Role::TokenRegistrar,
+        Role::Relayer,
+    ];
+    // Roles that change the configuration of the bridge.
+    pub const CONFIG: u8 = Role::Admin.bit() | Role::TokenRegistrar.bit();
+    // Roles of the keys that sign day to day, which must never hold a config role as well.
+    pub const HOT: u8 = Role::Guardian.bit() | Role::Relayer.bit();
+
+    pub const fn bit(self) -> u8 {
+        1 << self as u8
+    }
+
+    pub const fn is_config(self) -> bool {
+        Role::CONFIG & self.bit() != 0
+    }
 }
 
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
 pub struct BridgeStateV0 {
     pub version: u8,
-    pub bump: u8,              // 1 byte
-    pub admin: Pubkey,         // 32
-    pub spenders: [Pubkey; 2], // 32 * 2 bytes
-    pub spender_index: u8,     // 1 byte
+    pub bump: u8, // 1 byte
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub admin: Pubkey, // 32
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkeys"))]
+    pub spenders: [Pubkey; 2], // 32 * 2 bytes, the current and the previous spender
+    pub spender_index: u8, // 1 byte, slot of the current spender
+    pub out_sequence: u64, // 8 bytes, next sequence assigned to an outbound transfer
+    pub paused: bool, // 1 byte
+    pub message_sequence: u64, // 8 bytes, next sequence assigned to a message
+    pub spender_epoch: u64, // 8 bytes, epoch of the current spender
+    // 8 bytes each, the previous spender may sign until both the slot and the unix timestamp.
+    pub previous_valid_until_slot: u64,
+    pub previous_valid_until: i64,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub guardian: Pubkey, // 32 bytes, may pause and cancel queued operations
+    pub timelock_delay: u64, // 8 bytes, seconds between queueing and executing an operation
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub treasury: Pubkey, // 32 bytes, receives the funds once decommissioned, default before
+    pub token_count: u32,    // 4 bytes, number of token configs
+    // 32 bytes each, programs allowed to call the guarded instructions, default when unused.
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkeys"))]
+    pub cpi_callers: [Pubkey; MAX_CPI_CALLERS],
+    // 32 bytes each, keys holding roles through SetRole, default when unused.
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkeys"))]
+    pub role_holders: [Pubkey; MAX_ROLE_HOLDERS],
+    pub role_masks: [u8; MAX_ROLE_HOLDERS], // 1 byte each, the `Role::bit`s of each holder
+}
+
+impl BridgeStateV0 {
+    // Bumped whenever the layout changes, accounts of an older version must be migrated first.
+    pub const VERSION: u8 = 1;
+    pub const LEN: usize = 1
+        + 1
+        + 32
+        + 32 * 2
+        + 1
+        + 8
+        + 1
+        + 8
+        + 8
+        + 8
+        + 8
+        + 32
+        + 8
+        + 32
+        + 4
+        + 32 * MAX_CPI_CALLERS
+        + (32 + 1) * MAX_ROLE_HOLDERS;
+
+    /// Decodes the account data, which must be of the current version.
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.first() != Some(&BridgeStateV0::VERSION) {
+            return Err(BridgeError::MigrationRequired.into());
+        }
+        Ok(BridgeStateV0::try_from_slice(data)?)
+    }
+
+    pub fn is_decommissioned(&self) -> bool {
+        self.treasury != Pubkey::default()
+    }
+
+    pub fn is_cpi_caller(&self, program: &Pubkey) -> bool {
+        *program != Pubkey::default() && self.cpi_callers.contains(program)
+    }
+
+    pub fn spender_keys(&self) -> SpenderKeys {
+        SpenderKeys {
+            spenders: &self.spenders,
+            spender_index: self.spender_index,
+            previous_valid_until_slot: self.previous_valid_until_slot,
+            previous_valid_until: self.previous_valid_until,
+        }
+    }
+
+    pub fn role_keys(&self) -> RoleKeys {
+        RoleKeys {
+            holders: &self.role_holders,
+            masks: &self.role_masks,
+        }
+    }
+
+    /// Whether `key` holds `role`. Spenders are checked separately, since they expire.
+    pub fn has_role(&self, key: &Pubkey, role: Role) -> bool {
+        let field = match role {
+            Role::Admin => &self.admin,
+            Role::Guardian => &self.guardian,
+            _ => return self.role_keys().has_role(key, role),
+        };
+        field == key || self.role_keys().has_role(key, role)
+    }
+
+    /// The `Role::bit`s of every role `key` holds, including the spender slots whatever their
+    /// deadline.
+    pub fn roles_of(&self, key: &Pubkey) -> u8 {
+        if *key == Pubkey::default() {
+            return 0;
+        }
+        let mut mask = self.role_keys().mask(key);
+        if self.admin == *key {
+            mask |= Role::Admin.bit();
+        }
+        if self.guardian == *key {
+            mask |= Role::Guardian.bit();
+        }
+        if self.spenders.contains(key) {
+            mask |= Role::Relayer.bit();
+        }
+        mask
+    }
+}
+
+/// The bridge account at version 0, before it grew past the spenders.
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct LegacyBridgeState {
+    pub version: u8,
+    pub bump: u8,
+    pub admin: Pubkey,
+    pub spenders: [Pubkey; 2],
+    pub spender_index: u8,
+}
+
+impl LegacyBridgeState {
+    pub const LEN: usize = 1 + 1 + 32 + 32 * 2 + 1;
+}
+
+/// The keys that hold roles through SetRole, and the roles of each.
+pub struct RoleKeys<'a> {
+    pub holders: &'a [Pubkey; MAX_ROLE_HOLDERS],
+    pub masks: &'a [u8; MAX_ROLE_HOLDERS],
+}
+
+impl<'a> RoleKeys<'a> {
+    pub fn mask(&self, key: &Pubkey) -> u8 {
+        if *key == Pubkey::default() {
+            return 0;
+        }
+        self.holders
+            .iter()
+            .zip(self.masks)
+            .filter(|(holder, _)| *holder == key)
+            .fold(0, |mask, (_, roles)| mask | roles)
+    }
+
+    pub fn has_role(&self, key: &Pubkey, role: Role) -> bool {
+        self.mask(key) & role.bit() != 0
+    }
+}
+
+/// The spenders of the current and the previous epoch. The previous spender stays authorized
+/// until both of its deadlines, see `ActivateSpender`.
+pub struct SpenderKeys<'a> {
+    pub spenders: &'a [Pubkey; 2],
+    pub spender_index: u8,
+    pub previous_valid_until_slot: u64,
+    pub previous_valid_until: i64,
+}
+
+impl<'a> SpenderKeys<'a> {
+    pub fn current(&self) -> &Pubkey {
+        &self.spenders[(self.spender_index % 2) as usize]
+    }
+
+    pub fn previous(&self) -> &Pubkey {
+        &self.spenders[((self.spender_index + 1) % 2) as usize]
+    }
+
+    pub fn previous_valid_at(&self, slot: u64, unix_timestamp: i64) -> bool {
+        slot <= self.previous_valid_until_slot && unix_timestamp <= self.previous_valid_until
+    }
+}
+
+/// Same bytes as the borsh encoding of `BridgeStateV0`, which has no padding or length
+/// prefixes, so the hot paths read and update the account data in place.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct BridgeStateV0Pod {
+    pub version: u8,
+    pub bump: u8,
+    pub admin: Pubkey,
+    pub spenders: [Pubkey; 2],
+    pub spender_index: u8,
+    out_sequence: [u8; 8],
+    paused: u8,
+    message_sequence: [u8; 8],
+    spender_epoch: [u8; 8],
+    previous_valid_until_slot: [u8; 8],
+    previous_valid_until: [u8; 8],
+    pub guardian: Pubkey,
+    timelock_delay: [u8; 8],
+    pub treasury: Pubkey,
+    token_count: [u8; 4],
+    pub cpi_callers: [Pubkey; MAX_CPI_CALLERS],
+    pub role_holders: [Pubkey; MAX_ROLE_HOLDERS],
+    pub role_masks: [u8; MAX_ROLE_HOLDERS],
+}
+
+const _: () = assert!(std::mem::size_of::<BridgeStateV0Pod>() == BridgeStateV0::LEN);
+
+impl BridgeStateV0Pod {
+    pub fn load(data: &[u8]) -> Result<&Self, ProgramError> {
+        if data.first() != Some(&BridgeStateV0::VERSION) {
+            return Err(BridgeError::MigrationRequired.into());
+        }
+        let data = data
+            .get(..BridgeStateV0::LEN)
+            .ok_or(ProgramError::InvalidAccountData)?;
+        bytemuck::try_from_bytes(data).map_err(|_| ProgramError::InvalidAccountData)
+    }
+
+    pub fn load_mut(data: &mut [u8]) -> Result<&mut Self, ProgramError> {
+        if data.first() != Some(&BridgeStateV0::VERSION) {
+            return Err(BridgeError::MigrationRequired.into());
+        }
+        let data = data
+            .get_mut(..BridgeStateV0::LEN)
+            .ok_or(ProgramError::InvalidAccountData)?;
+        bytemuck::try_from_bytes_mut(data).map_err(|_| ProgramError::InvalidAccountData)
+    }
+
+    pub fn out_sequence(&self) -> u64 {
+        u64::from_le_bytes(self.out_sequence)
+    }
+
+    pub fn set_out_sequence(&mut self, sequence: u64) {
+        self.out_sequence = sequence.to_le_bytes();
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused != 0
+    }
+
+    pub fn message_sequence(&self) -> u64 {
+        u64::from_le_bytes(self.message_sequence)
+    }
+
+    pub fn set_message_sequence(&mut self, sequence: u64) {
+        self.message_sequence = sequence.to_le_bytes();
+    }
+
+    pub fn spender_epoch(&self) -> u64 {
+        u64::from_le_bytes(self.spender_epoch)
+    }
+
+    pub fn timelock_delay(&self) -> u64 {
+        u64::from_le_bytes(self.timelock_delay)
+    }
+
+    pub fn token_count(&self) -> u32 {
+        u32::from_le_bytes(self.token_count)
+    }
+
+    pub fn spender_keys(&self) -> SpenderKeys {
+        SpenderKeys {
+            spenders: &self.spenders,
+            spender_index: self.spender_index,
+            previous_valid_until_slot: u64::from_le_bytes(self.previous_valid_until_slot),
+            previous_valid_until: i64::from_le_bytes(self.previous_valid_until),
+        }
+    }
+
+    pub fn role_keys(&self) -> RoleKeys {
+        RoleKeys {
+            holders: &self.role_holders,
+            masks: &self.role_masks,
+        }
+    }
+}
+
+// Only mints with an enabled config can be bridged out.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct TokenConfig {
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub mint: Pubkey, // 32 bytes
+    pub enabled: bool, // 1
+    pub bump: u8,      // 1
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::amount"))]
+    pub locked: u64, // 8, total locked by outbound transfers
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::amount"))]
+    pub released: u64, // 8, total released by inbound transfers and refunds
 }
 
+impl TokenConfig {
+    pub const LEN: usize = 32 + 1 + 1 + 8 + 8;
+
+    /// What the vault should hold. Releases can never take more than this.
+    pub fn outstanding(&self) -> u64 {
+        self.locked.saturating_sub(self.released)
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
 pub struct TransferOutData {
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::amount"))]
     pub amount: u64,
     pub token_address: String,
     pub chain_id: u64,
     pub recipient: String,
 }
 
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone, Copy)]
+pub enum ReceiptStatus {
+    Pending,
+    Delivered,
+    Refunded,
+}
+
+// Created by every outbound transfer so that it can later be refunded exactly once.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct TransferReceipt {
+    pub sequence: u64, // 8 bytes
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub sender: Pubkey, // 32
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub source: Pubkey, // 32, token account (or wallet for native SOL) to refund to
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub mint: Pubkey, // 32
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::amount"))]
+    pub amount: u64, // 8
+    pub status: ReceiptStatus, // 1
+    pub bump: u8,      // 1
+}
+
+impl TransferReceipt {
+    pub const LEN: usize = 8 + 32 + 32 + 32 + 8 + 1 + 1;
+}
+
+// Created when an inbound message is executed, so that it is never executed twice.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct DeliveryRecord {
+    pub source_chain_id: u64, // 8 bytes
+    pub nonce: u64,           // 8
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub receiver_program: Pubkey, // 32
+    pub bump: u8,             // 1
+}
+
+impl DeliveryRecord {
+    pub const LEN: usize = 8 + 8 + 32 + 1;
+}
+
+// An admin operation waiting for its timelock. The account is closed when the operation is
+// executed or cancelled, its size depends on the operation.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct PendingOperation {
+    pub id: u64,  // 8 bytes
+    pub eta: i64, // 8, unix timestamp from which it can be executed
+    pub bump: u8, // 1
+    pub operation: AdminOperation,
+}
+
+// Admin changes that must be queued once the bridge has a timelock delay. The bridge has no fees
+// or transfer limits yet, so there are no operations to change them. They belong here once it
+// does.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "data"))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub enum AdminOperation {
+    ActivateSpender(ActivateSpenderData),
+    RemoveSpender(AddSpenderData),
+    ChangeAdmin(ChangeAdminData),
+    SetTimelock(SetTimelockData),
+    Decommission(DecommissionData),
+    SetCpiCaller(SetCpiCallerData),
+    SetRole(SetRoleData),
+}
+
+// Payload of Refund and MarkDelivered.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct ReceiptData {
+    pub sequence: u64,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct TransferOutBatchData {
+    pub items: Vec<TransferOutData>,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct TransferOutNativeData {
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::amount"))]
+    pub amount: u64, // lamports
+    pub chain_id: u64,
+    pub recipient: String,
+}
+
+// Upper bound of the payload of a message or of a token transfer.
+pub const MAX_PAYLOAD_LEN: usize = 512;
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct SendMessageData {
+    pub chain_id: u64,
+    pub target: String, // contract address on the destination chain
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::bytes"))]
+    pub payload: Vec<u8>,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct TransferOutWithPayloadData {
+    pub transfer: TransferOutData,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::bytes"))]
+    pub payload: Vec<u8>,
+}
+
+// An inbound message for a receiver program, optionally with tokens released to the receiver's
+// token account first. `nonce` is the message sequence on the source chain.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct DeliverMessageData {
+    pub source_chain_id: u64,
+    pub nonce: u64,
+    pub sender: String, // address of the sender on the source chain
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::bytes"))]
+    pub payload: Vec<u8>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::amount"))]
+    pub amount: u64, // zero for a message without tokens
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
 pub struct TransferInData {
     pub nonce: u64,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::amounts"))]
     pub amounts: Vec<u64>,
 }
 
+/// `TransferInData` read in place from the instruction data, so large inbound batches do not
+/// collect their amounts into a vector first.
+#[derive(Debug, Clone, Copy)]
+pub struct TransferInRef<'a> {
+    pub nonce: u64,
+    amounts: &'a [u8],
+}
+
+impl<'a> TransferInRef<'a> {
+    /// Reads the payload that follows the `TransferIn` tag, accepting exactly what
+    /// `BridgeInstruction::unpack` accepts.
+    pub fn unpack(payload: &'a [u8]) -> Result<Self, BridgeError> {
+        if payload.len() < 12 {
+            return Err(BridgeError::MalformedInstruction);
+        }
+        let (nonce, rest) = payload.split_at(8);
+        let (len, amounts) = rest.split_at(4);
+        let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+
+        let expected = len
+            .checked_mul(8)
+            .ok_or(BridgeError::MalformedInstruction)?;
+        if amounts.len() < expected {
+            return Err(BridgeError::MalformedInstruction);
+        }
+        if amounts.len() > expected {
+            return Err(BridgeError::TrailingInstructionData);
+        }
+
+        Ok(TransferInRef {
+            nonce: u64::from_le_bytes(nonce.try_into().unwrap()),
+            amounts,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.amounts.len() / 8
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.amounts.is_empty()
+    }
+
+    pub fn amounts(&self) -> impl ExactSizeIterator<Item = u64> + 'a {
+        self.amounts
+            .chunks_exact(8)
+            .map(|amount| u64::from_le_bytes(amount.try_into().unwrap()))
+    }
+}
+
+// LEB128 encoded u64: 7 bits per byte, so small amounts take 1-3 bytes instead of 8.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct VarU64(pub u64);
+
+impl BorshSerialize for VarU64 {
+    fn serialize<W: Write>(&self, writer: &mut W) -> IoResult<()> {
+        let mut value = self.0;
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                return writer.write_all(&[byte]);
+            }
+            writer.write_all(&[byte | 0x80])?;
+        }
+    }
+}
+
+impl BorshDeserialize for VarU64 {
+    fn deserialize(buf: &mut &[u8]) -> IoResult<Self> {
+        let mut value: u64 = 0;
+        for i in 0..10 {
+            let byte: u8 = BorshDeserialize::deserialize(buf)?;
+            let bits = (byte & 0x7f) as u64;
+            // The 10th byte only has room for the top bit of a u64.
+            if i == 9 && bits > 1 {
+                return Err(Error::new(ErrorKind::InvalidData, "VarU64 overflow"));
+            }
+            value |= bits << (7 * i);
+
+            if byte & 0x80 == 0 {
+                // Only the shortest encoding is accepted, so every amount has one byte form.
+                if byte == 0 && i > 0 {
+                    return Err(Error::new(ErrorKind::InvalidData, "VarU64 not canonical"));
+                }
+                return Ok(VarU64(value));
+            }
+        }
+
+        Err(Error::new(ErrorKind::InvalidData, "VarU64 too long"))
+    }
+}
+
+// One release in a compact TransferIn batch. The indexes point into the accounts that follow
+// the fixed [spender, token program, bridge pda] prefix.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct CompactTransferItem {
+    pub vault_index: u8,
+    pub receiver_index: u8,
+    pub config_index: u8,
+    pub amount: VarU64,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct TransferInCompactData {
+    pub nonce: u64,
+    pub items: Vec<CompactTransferItem>,
+}
+
+// Events are borsh encoded and written with `sol_log_data`, so they show up as
+// "Program data: <base64>" in the transaction logs.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "data"))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub enum BridgeEvent {
+    TransferOut(TransferOutEvent),
+    Refunded(RefundedEvent),
+    Delivered(DeliveredEvent),
+    MessageSent(MessageEvent),
+    TransferOutWithPayload(TransferOutWithPayloadEvent),
+    MessageDelivered(MessageDeliveredEvent),
+    SpenderActivated(SpenderActivatedEvent),
+    OperationQueued(OperationQueuedEvent),
+    OperationExecuted(OperationEvent),
+    OperationCancelled(OperationEvent),
+    VaultSwept(VaultSweptEvent),
+    Reconciled(ReconciledEvent),
+}
+
+// A vault compared with the counters of its token config. A surplus, e.g. tokens sent to the
+// vault directly, is not owed to anyone.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct ReconciledEvent {
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub mint: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::amount"))]
+    pub locked: u64,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::amount"))]
+    pub released: u64,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::amount"))]
+    pub balance: u64,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::amount"))]
+    pub surplus: u64,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::amount"))]
+    pub shortfall: u64,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct VaultSweptEvent {
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub mint: Pubkey, // NATIVE_SOL_MINT for the native vault
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::amount"))]
+    pub amount: u64,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub treasury: Pubkey,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct OperationQueuedEvent {
+    pub id: u64,
+    pub eta: i64,
+    pub operation: AdminOperation,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct OperationEvent {
+    pub id: u64,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct TransferOutEvent {
+    pub sequence: u64,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub sender: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub mint: Pubkey, // NATIVE_SOL_MINT for native SOL
+    pub token_address: String,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::amount"))]
+    pub amount: u64,
+    pub chain_id: u64,
+    pub recipient: String,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
-pub struct TransferInIx {
-    pub bridge_ix: BridgeInstruction,
-    pub transfer_data: TransferInData,
+pub struct RefundedEvent {
+    pub sequence: u64,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub destination: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub mint: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::amount"))]
+    pub amount: u64,
 }
 
-#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct DeliveredEvent {
+    pub sequence: u64,
+}
+
+// Messages are numbered by the bridge's `message_sequence`, apart from token transfers.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct MessageEvent {
+    pub sequence: u64,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub sender: Pubkey,
+    pub chain_id: u64,
+    pub target: String,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::bytes"))]
+    pub payload: Vec<u8>,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct TransferOutWithPayloadEvent {
+    pub transfer: TransferOutEvent,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::bytes"))]
+    pub payload: Vec<u8>,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct SpenderActivatedEvent {
+    pub epoch: u64,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub spender: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub previous: Pubkey,
+    pub previous_valid_until_slot: u64,
+    pub previous_valid_until: i64,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct MessageDeliveredEvent {
+    pub source_chain_id: u64,
+    pub nonce: u64,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub receiver_program: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::amount"))]
+    pub amount: u64,
+}
+
+// Also the payload of RemoveSpender.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
 pub struct AddSpenderData {
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
     pub spender: Pubkey, // 32 bytes
 }
 
-impl TransferInIx {
-    pub fn from_data(data: TransferInData) -> TransferInIx {
-        return TransferInIx {
-            bridge_ix: BridgeInstruction::TransferIn,
-            transfer_data: data,
-        };
+// How long the outgoing spender stays authorized after a new one is activated.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "data"))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone, Copy)]
+pub enum SpenderOverlap {
+    Slots(u64),
+    Seconds(u64),
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct ActivateSpenderData {
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub spender: Pubkey,
+    pub overlap: SpenderOverlap,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct ChangeAdminData {
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub new_admin: Pubkey,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct SetPausedData {
+    pub paused: bool,
+}
+
+// A zero delay lets the admin apply changes directly, as before the timelock existed.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct SetTimelockData {
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub guardian: Pubkey,
+    pub delay: u64,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct QueueOperationData {
+    pub id: u64,
+    pub operation: AdminOperation,
+}
+
+// Pauses the bridge for good. SweepVault and CloseBridge then move everything to `treasury`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct DecommissionData {
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub treasury: Pubkey,
+}
+
+// Payload of ExecuteOperation and CancelOperation.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct OperationData {
+    pub id: u64,
+}
+
+// Adds `program` to the CPI callers, or removes it.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct SetCpiCallerData {
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub program: Pubkey,
+    pub allowed: bool,
+}
+
+// Grants `role` to `holder`, or revokes it. The admin, guardian and spender fields change
+// through their own operations.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct SetRoleData {
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub holder: Pubkey,
+    pub role: Role,
+    pub granted: bool,
+}
+
+// Creates the token config on first use, afterwards only toggles it.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct RegisterTokenData {
+    pub enabled: bool,
+}
+
+// Read-only queries. Each one takes the bridge pda, Token also the token config of the mint,
+// and answers with `BridgeReturnData`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "data"))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub enum BridgeQuery {
+    Config,
+    Spenders,
+    Token,
+}
+
+// First byte of the return data. Existing variants and fields never change within a version,
+// new variants are appended.
+pub const RETURN_DATA_VERSION: u8 = 1;
+
+// Set with `set_return_data` by queries and by outbound transfers, which return their
+// sequence. Encoded as the version byte followed by the borsh encoded variant, so callers do
+// not depend on the account layouts.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "data"))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub enum BridgeReturnData {
+    Sequence(u64),
+    Config(ConfigInfo),
+    Spenders(SpendersInfo),
+    Token(TokenInfo),
+}
+
+impl BridgeReturnData {
+    pub fn pack(&self) -> Vec<u8> {
+        let mut data = vec![RETURN_DATA_VERSION];
+        data.extend(self.try_to_vec().unwrap());
+        data
     }
+
+    pub fn unpack(input: &[u8]) -> Result<Self, BridgeError> {
+        match input.split_first() {
+            Some((&RETURN_DATA_VERSION, data)) => {
+                BridgeReturnData::try_from_slice(data).map_err(|_| BridgeError::MalformedReturnData)
+            }
+            _ => Err(BridgeError::MalformedReturnData),
+        }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct ConfigInfo {
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub admin: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub guardian: Pubkey,
+    pub paused: bool,
+    // Default until the bridge is decommissioned.
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub treasury: Pubkey,
+    pub timelock_delay: u64,
+    pub token_count: u32,
+    pub out_sequence: u64,
+    pub message_sequence: u64,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct SpendersInfo {
+    pub epoch: u64,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub current: Pubkey,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub previous: Pubkey,
+    pub previous_valid_until_slot: u64,
+    pub previous_valid_until: i64,
+    // Whether the previous spender may still sign at the current slot and time.
+    pub previous_active: bool,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone)]
+pub struct TokenInfo {
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::pubkey"))]
+    pub mint: Pubkey,
+    pub enabled: bool,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::amount"))]
+    pub locked: u64,
+    #[cfg_attr(feature = "serde", serde(with = "crate::json::amount"))]
+    pub released: u64,
 }
 
 #[cfg(test)]
 mod test {
+    use crate::error::BridgeError;
+    use crate::state::ActivateSpenderData;
+    use crate::state::AddSpenderData;
+    use crate::state::AdminOperation;
+    use crate::state::BridgeInstruction;
+    use crate::state::BridgeQuery;
+    use crate::state::BridgeReturnData;
+    use crate::state::BridgeStateV0;
+    use crate::state::BridgeStateV0Pod;
+    use crate::state::DeliveryRecord;
+    use crate::state::LegacyBridgeState;
+    use crate::state::OperationData;
+    use crate::state::QueueOperationData;
+    use crate::state::ReceiptStatus;
+    use crate::state::RegisterTokenData;
+    use crate::state::Role;
+    use crate::state::SetPausedData;
+    use crate::state::SetRoleData;
+    use crate::state::SpenderOverlap;
+    use crate::state::TokenConfig;
     use crate::state::TransferInData;
+    use crate::state::TransferInRef;
     use crate::state::TransferOutData;
+    use crate::state::TransferReceipt;
+    use crate::state::VarU64;
+    use crate::state::MAX_CPI_CALLERS;
+    use crate::state::MAX_ROLE_HOLDERS;
+    use crate::state::TRANSFER_IN_BASE_UNITS;
+    use crate::state::TRANSFER_IN_ITEM_UNITS;
+    use crate::state::TRANSFER_OUT_UNITS;
     use borsh::{BorshDeserialize, BorshSerialize};
+    use solana_program::pubkey::Pubkey;
 
     #[test]
     fn test_serialize_transfer_in() {
@@ -84,4 +1155,350 @@ mod test {
         let decoded_a = TransferOutData::try_from_slice(&encoded_a).unwrap();
         assert_eq!(transfer_out, decoded_a);
     }
+
+    #[test]
+    fn test_pack_instruction() {
+        let instructions = vec![
+            BridgeInstruction::Initialize,
+            BridgeInstruction::TransferIn(TransferInData {
+                nonce: 1,
+                amounts: vec![1, 2, 3],
+            }),
+            BridgeInstruction::AddSpender(AddSpenderData {
+                spender: Pubkey::new_unique(),
+            }),
+            BridgeInstruction::RegisterToken(RegisterTokenData { enabled: true }),
+            BridgeInstruction::QueueOperation(QueueOperationData {
+                id: 1,
+                operation: AdminOperation::ActivateSpender(ActivateSpenderData {
+                    spender: Pubkey::new_unique(),
+                    overlap: SpenderOverlap::Seconds(3600),
+                }),
+            }),
+            BridgeInstruction::CancelOperation(OperationData { id: 3 }),
+            BridgeInstruction::SweepVault,
+            BridgeInstruction::Query(BridgeQuery::Token),
+        ];
+        for instruction in instructions {
+            let packed = instruction.pack();
+            assert_eq!(BridgeInstruction::unpack(&packed).unwrap(), instruction);
+        }
+
+        let packed = BridgeInstruction::SetRole(SetRoleData {
+            holder: Pubkey::new_unique(),
+            role: Role::TokenRegistrar,
+            granted: true,
+        })
+        .pack();
+        assert_eq!(packed[33], Role::TokenRegistrar as u8);
+        assert_eq!(
+            BridgeInstruction::Migrate.pack(),
+            [BridgeInstruction::LAST_TAG]
+        );
+
+        assert_eq!(
+            BridgeInstruction::unpack(&[]),
+            Err(BridgeError::InvalidInstruction)
+        );
+        assert_eq!(
+            BridgeInstruction::unpack(&[BridgeInstruction::LAST_TAG + 1]),
+            Err(BridgeError::InvalidInstruction)
+        );
+        // Truncated payload.
+        assert_eq!(
+            BridgeInstruction::unpack(&packed[..1]),
+            Err(BridgeError::MalformedInstruction)
+        );
+        let mut trailing = BridgeInstruction::Initialize.pack();
+        trailing.push(0);
+        assert_eq!(
+            BridgeInstruction::unpack(&trailing),
+            Err(BridgeError::TrailingInstructionData)
+        );
+    }
+
+    #[test]
+    fn test_return_data() {
+        let packed = BridgeReturnData::Sequence(7).pack();
+        assert_eq!(packed, [1, 0, 7, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            BridgeReturnData::unpack(&packed),
+            Ok(BridgeReturnData::Sequence(7))
+        );
+
+        let mut next_version = packed.clone();
+        next_version[0] += 1;
+        assert_eq!(
+            BridgeReturnData::unpack(&next_version),
+            Err(BridgeError::MalformedReturnData)
+        );
+        assert_eq!(
+            BridgeReturnData::unpack(&packed[..5]),
+            Err(BridgeError::MalformedReturnData)
+        );
+        assert_eq!(
+            BridgeReturnData::unpack(&[]),
+            Err(BridgeError::MalformedReturnData)
+        );
+    }
+
+    #[test]
+    fn test_transfer_in_ref() {
+        let data = TransferInData {
+            nonce: 9,
+            amounts: vec![1, u64::MAX, 3],
+        };
+        let packed = BridgeInstruction::TransferIn(data.clone()).pack();
+        assert_eq!(packed[0], BridgeInstruction::TRANSFER_IN_TAG);
+
+        let transfer_in = TransferInRef::unpack(&packed[1..]).unwrap();
+        assert_eq!(transfer_in.nonce, data.nonce);
+        assert_eq!(transfer_in.len(), 3);
+        assert_eq!(transfer_in.amounts().collect::<Vec<_>>(), data.amounts);
+
+        assert_eq!(
+            TransferInRef::unpack(&packed[1..packed.len() - 1]).unwrap_err(),
+            BridgeError::MalformedInstruction
+        );
+        let mut trailing = packed[1..].to_vec();
+        trailing.push(0);
+        assert_eq!(
+            TransferInRef::unpack(&trailing).unwrap_err(),
+            BridgeError::TrailingInstructionData
+        );
+    }
+
+    #[test]
+    fn test_compute_units() {
+        let transfer_in = |n| {
+            BridgeInstruction::TransferIn(TransferInData {
+                nonce: 1,
+                amounts: vec![100; n],
+            })
+        };
+        assert_eq!(
+            transfer_in(1).compute_units(),
+            TRANSFER_IN_BASE_UNITS + TRANSFER_IN_ITEM_UNITS
+        );
+        assert_eq!(
+            transfer_in(16).compute_units() - transfer_in(1).compute_units(),
+            15 * TRANSFER_IN_ITEM_UNITS
+        );
+        assert_eq!(
+            BridgeInstruction::SetPaused(SetPausedData { paused: true }).compute_units(),
+            TRANSFER_OUT_UNITS
+        );
+    }
+
+    #[test]
+    fn test_state_pod() {
+        let mut state = BridgeStateV0 {
+            version: BridgeStateV0::VERSION,
+            bump: 255,
+            admin: Pubkey::new_unique(),
+            spenders: [Pubkey::new_unique(), Pubkey::new_unique()],
+            spender_index: 1,
+            out_sequence: 0x0102_0304_0506_0708,
+            paused: true,
+            message_sequence: 5,
+            spender_epoch: 4,
+            previous_valid_until_slot: 1000,
+            previous_valid_until: 1_700_000_000,
+            guardian: Pubkey::new_unique(),
+            timelock_delay: 86400,
+            treasury: Pubkey::new_unique(),
+            token_count: 3,
+            cpi_callers: [
+                Pubkey::new_unique(),
+                Pubkey::default(),
+                Pubkey::default(),
+                Pubkey::default(),
+            ],
+            role_holders: [Pubkey::new_unique(); MAX_ROLE_HOLDERS],
+            role_masks: [Role::Relayer.bit(); MAX_ROLE_HOLDERS],
+        };
+        let mut data = state.try_to_vec().unwrap();
+
+        let pod = BridgeStateV0Pod::load(&data).unwrap();
+        assert_eq!(pod.bump, state.bump);
+        assert_eq!(pod.admin, state.admin);
+        assert_eq!(pod.spenders, state.spenders);
+        assert_eq!(pod.out_sequence(), state.out_sequence);
+        assert!(pod.paused());
+        assert_eq!(pod.message_sequence(), state.message_sequence);
+        assert_eq!(pod.spender_epoch(), state.spender_epoch);
+        assert_eq!(pod.guardian, state.guardian);
+        assert_eq!(pod.timelock_delay(), state.timelock_delay);
+        assert_eq!(pod.treasury, state.treasury);
+        assert_eq!(pod.token_count(), state.token_count);
+        assert_eq!(pod.cpi_callers, state.cpi_callers);
+        assert_eq!(pod.role_holders, state.role_holders);
+        assert_eq!(pod.role_masks, state.role_masks);
+        assert!(pod
+            .role_keys()
+            .has_role(&state.role_holders[0], Role::Relayer));
+        let keys = pod.spender_keys();
+        assert_eq!(keys.current(), &state.spenders[1]);
+        assert_eq!(keys.previous(), &state.spenders[0]);
+        assert!(keys.previous_valid_at(1000, 1_700_000_000));
+        assert!(!keys.previous_valid_at(1001, 0));
+        assert!(!keys.previous_valid_at(0, 1_700_000_001));
+
+        let pod = BridgeStateV0Pod::load_mut(&mut data).unwrap();
+        pod.set_out_sequence(42);
+        pod.set_message_sequence(43);
+        state.out_sequence = 42;
+        state.message_sequence = 43;
+        assert_eq!(BridgeStateV0::try_from_slice(&data).unwrap(), state);
+
+        assert!(BridgeStateV0Pod::load(&data[..BridgeStateV0::LEN - 1]).is_err());
+        // An account of an older layout is not read until it is migrated.
+        data[0] = 0;
+        assert_eq!(
+            BridgeStateV0Pod::load(&data).err(),
+            Some(BridgeError::MigrationRequired.into())
+        );
+        assert_eq!(
+            BridgeStateV0::unpack(&data),
+            Err(BridgeError::MigrationRequired.into())
+        );
+    }
+
+    #[test]
+    fn test_account_len() {
+        let state = BridgeStateV0 {
+            version: BridgeStateV0::VERSION,
+            bump: 255,
+            admin: Pubkey::new_unique(),
+            spenders: [Pubkey::new_unique(), Pubkey::new_unique()],
+            spender_index: 0,
+            out_sequence: 7,
+            paused: false,
+            message_sequence: 3,
+            spender_epoch: 1,
+            previous_valid_until_slot: u64::MAX,
+            previous_valid_until: 0,
+            guardian: Pubkey::new_unique(),
+            timelock_delay: 0,
+            treasury: Pubkey::default(),
+            token_count: 0,
+            cpi_callers: [Pubkey::default(); MAX_CPI_CALLERS],
+            role_holders: [Pubkey::default(); MAX_ROLE_HOLDERS],
+            role_masks: [0; MAX_ROLE_HOLDERS],
+        };
+        assert_eq!(state.try_to_vec().unwrap().len(), BridgeStateV0::LEN);
+        let legacy = LegacyBridgeState {
+            version: 0,
+            bump: 255,
+            admin: state.admin,
+            spenders: state.spenders,
+            spender_index: 0,
+        };
+        assert_eq!(legacy.try_to_vec().unwrap().len(), LegacyBridgeState::LEN);
+        assert_eq!(LegacyBridgeState::LEN, 99);
+        assert!(!state.is_cpi_caller(&Pubkey::default()));
+
+        let receipt = TransferReceipt {
+            sequence: 1,
+            sender: Pubkey::new_unique(),
+            source: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            amount: 900,
+            status: ReceiptStatus::Pending,
+            bump: 254,
+        };
+        assert_eq!(receipt.try_to_vec().unwrap().len(), TransferReceipt::LEN);
+
+        let record = DeliveryRecord {
+            source_chain_id: 189985,
+            nonce: 7,
+            receiver_program: Pubkey::new_unique(),
+            bump: 252,
+        };
+        assert_eq!(record.try_to_vec().unwrap().len(), DeliveryRecord::LEN);
+
+        let token_config = TokenConfig {
+            mint: Pubkey::new_unique(),
+            enabled: true,
+            bump: 253,
+            locked: 1000,
+            released: 400,
+        };
+        assert_eq!(token_config.outstanding(), 600);
+        assert_eq!(token_config.try_to_vec().unwrap().len(), TokenConfig::LEN);
+    }
+
+    #[test]
+    fn test_roles() {
+        let admin = Pubkey::new_unique();
+        let guardian = Pubkey::new_unique();
+        let registrar = Pubkey::new_unique();
+        let mut role_holders = [Pubkey::default(); MAX_ROLE_HOLDERS];
+        let mut role_masks = [0; MAX_ROLE_HOLDERS];
+        role_holders[1] = registrar;
+        role_masks[1] = Role::TokenRegistrar.bit();
+        let state = BridgeStateV0 {
+            version: BridgeStateV0::VERSION,
+            bump: 255,
+            admin,
+            spenders: [guardian, Pubkey::default()],
+            spender_index: 0,
+            out_sequence: 0,
+            paused: false,
+            message_sequence: 0,
+            spender_epoch: 0,
+            previous_valid_until_slot: 0,
+            previous_valid_until: 0,
+            guardian,
+            timelock_delay: 0,
+            treasury: Pubkey::default(),
+            token_count: 0,
+            cpi_callers: [Pubkey::default(); MAX_CPI_CALLERS],
+            role_holders,
+            role_masks,
+        };
+
+        assert!(state.has_role(&admin, Role::Admin));
+        assert!(!state.has_role(&admin, Role::TokenRegistrar));
+        assert!(state.has_role(&registrar, Role::TokenRegistrar));
+        assert!(!state.has_role(&registrar, Role::Admin));
+        // Spenders are relayers, but only `check_spender` knows whether they expired.
+        assert!(!state.has_role(&guardian, Role::Relayer));
+        assert_eq!(state.roles_of(&guardian), Role::HOT);
+        assert_eq!(state.roles_of(&registrar) & Role::HOT, 0);
+        // Empty slots hold nothing.
+        assert_eq!(state.roles_of(&Pubkey::default()), 0);
+
+        assert!(Role::TokenRegistrar.is_config());
+        assert!(!Role::Guardian.is_config());
+        assert_eq!(
+            BridgeInstruction::SetPaused(SetPausedData { paused: true }).required_roles(),
+            [Role::Guardian, Role::Admin]
+        );
+        assert_eq!(
+            BridgeInstruction::SetPaused(SetPausedData { paused: false }).required_roles(),
+            [Role::Admin]
+        );
+        assert_eq!(
+            BridgeInstruction::RegisterToken(RegisterTokenData { enabled: true }).required_roles(),
+            [Role::TokenRegistrar, Role::Admin]
+        );
+        assert!(BridgeInstruction::Initialize.required_roles().is_empty());
+    }
+
+    #[test]
+    fn test_var_u64() {
+        for (value, len) in [(0, 1), (127, 1), (128, 2), (1_000_000, 3), (u64::MAX, 10)] {
+            let encoded = VarU64(value).try_to_vec().unwrap();
+            assert_eq!(encoded.len(), len);
+            assert_eq!(VarU64::try_from_slice(&encoded).unwrap(), VarU64(value));
+        }
+
+        // Overlong, overflowing and truncated encodings are rejected.
+        assert!(VarU64::try_from_slice(&[0x80, 0x00]).is_err());
+        let mut overflow = vec![0xff; 9];
+        overflow.push(0x02);
+        assert!(VarU64::try_from_slice(&overflow).is_err());
+        assert!(VarU64::try_from_slice(&[0x80]).is_err());
+    }
 }
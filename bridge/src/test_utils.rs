@@ -0,0 +1,479 @@
+//! `ProgramTest` harness for the bridge and for programs built on top of it. Enabled by the
+//! `test-utils` feature.
+//!
+//! ```ignore
+//! let mut context = BridgeTestContext::new().await;
+//! let (mint, vault) = context.add_token(0).await;
+//! let user = context.create_user(1_000_000_000).await;
+//! let user_ata = context.fund_user(&user, &mint, 1000).await;
+//! // ... run your instructions with `context.process`, then:
+//! context.assert_token_balance(&vault, 1000).await;
+//! ```
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    program_pack::Pack,
+    pubkey::Pubkey,
+    system_instruction, system_program,
+    sysvar::{self, clock::Clock},
+};
+use solana_program_test::{
+    processor, BanksClient, BanksClientError, ProgramTest, ProgramTestContext,
+};
+use solana_sdk::{
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+use spl_associated_token_account::{
+    get_associated_token_address, instruction::create_associated_token_account,
+};
+use spl_token::state::{Account as SplTokenAccount, Mint};
+
+use crate::processor::Processor;
+use crate::state::{
+    AddSpenderData, BridgeInstruction, BridgeQuery, BridgeReturnData, BridgeStateV0,
+    RegisterTokenData, TokenConfig, TransferReceipt, BRIDGE_SEED, NATIVE_SOL_MINT,
+    NATIVE_VAULT_SEED, OPERATION_SEED, RECEIPT_SEED, TOKEN_SEED,
+};
+
+pub struct BridgeTestContextBuilder {
+    program_id: Pubkey,
+    program_name: &'static str,
+    program_test: ProgramTest,
+}
+
+impl Default for BridgeTestContextBuilder {
+    fn default() -> Self {
+        BridgeTestContextBuilder {
+            program_id: Pubkey::new_unique(),
+            program_name: "sisu_bridge",
+            program_test: ProgramTest::default(),
+        }
+    }
+}
+
+impl BridgeTestContextBuilder {
+    /// Deploys the bridge at `program_id` instead of a random address.
+    pub fn program_id(mut self, program_id: Pubkey) -> Self {
+        self.program_id = program_id;
+        self
+    }
+
+    /// Loads the program from `<program_name>.so` instead of `sisu_bridge.so`, when it runs as
+    /// BPF.
+    pub fn program_name(mut self, program_name: &'static str) -> Self {
+        self.program_name = program_name;
+        self
+    }
+
+    /// Gives access to the `ProgramTest`, e.g. to add the programs under test.
+    pub fn configure(mut self, f: impl FnOnce(&mut ProgramTest)) -> Self {
+        f(&mut self.program_test);
+        self
+    }
+
+    /// Starts the bank, initializes the bridge with the payer as admin and makes `spender` the
+    /// spender.
+    pub async fn start(mut self) -> BridgeTestContext {
+        self.program_test.add_program(
+            self.program_name,
+            self.program_id,
+            processor!(Processor::process_instruction),
+        );
+        let program_context = self.program_test.start_with_context().await;
+
+        let mut context = BridgeTestContext {
+            program_id: self.program_id,
+            bridge_pda: Pubkey::find_program_address(&[BRIDGE_SEED], &self.program_id).0,
+            spender: Keypair::new(),
+            program_context,
+        };
+        let payer = context.payer().pubkey();
+        let spender = context.spender.pubkey();
+        context
+            .process(
+                &[
+                    Instruction {
+                        program_id: context.program_id,
+                        accounts: vec![
+                            AccountMeta::new(payer, true),
+                            AccountMeta::new(context.bridge_pda, false),
+                            AccountMeta::new_readonly(system_program::id(), false),
+                        ],
+                        data: BridgeInstruction::Initialize.pack(),
+                    },
+                    Instruction {
+                        program_id: context.program_id,
+                        accounts: vec![
+                            AccountMeta::new_readonly(payer, true),
+                            AccountMeta::new(context.bridge_pda, false),
+                            AccountMeta::new_readonly(sysvar::instructions::id(), false),
+                        ],
+                        data: BridgeInstruction::AddSpender(AddSpenderData { spender }).pack(),
+                    },
+                    // The spender pays for delivery records.
+                    system_instruction::transfer(&payer, &spender, 10_000_000_000),
+                ],
+                &[],
+            )
+            .await
+            .unwrap();
+        context
+    }
+}
+
+/// A running bank with an initialized bridge. Setup helpers panic on failure, `process` and
+/// `execute` return the error so that tests can assert on it.
+pub struct BridgeTestContext {
+    pub program_id: Pubkey,
+    pub bridge_pda: Pubkey,
+    /// The current spender, a funded key apart from the admin.
+    pub spender: Keypair,
+    pub program_context: ProgramTestContext,
+}
+
+impl BridgeTestContext {
+    pub fn builder() -> BridgeTestContextBuilder {
+        BridgeTestContextBuilder::default()
+    }
+
+    pub async fn new() -> Self {
+        BridgeTestContext::builder().start().await
+    }
+
+    pub fn banks_client(&mut self) -> &mut BanksClient {
+        &mut self.program_context.banks_client
+    }
+
+    /// Funded account that pays for every transaction. It is the bridge admin.
+    pub fn payer(&self) -> &Keypair {
+        &self.program_context.payer
+    }
+
+    /// Sends `instructions` in one transaction, paid and signed by the payer and `signers`.
+    pub async fn process(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<(), BanksClientError> {
+        let mut all_signers = vec![&self.program_context.payer];
+        all_signers.extend_from_slice(signers);
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.program_context.payer.pubkey()),
+            &all_signers,
+            self.program_context.last_blockhash,
+        );
+        self.program_context
+            .banks_client
+            .process_transaction(transaction)
+            .await
+    }
+
+    pub async fn execute(&mut self, instruction: Instruction) -> Result<(), BanksClientError> {
+        self.process(&[instruction], &[]).await
+    }
+
+    /// Sends `instruction` signed by the spender as well, for the instructions relayers sign.
+    pub async fn relay(&mut self, instruction: Instruction) -> Result<(), BanksClientError> {
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&self.program_context.payer.pubkey()),
+            &[&self.program_context.payer, &self.spender],
+            self.program_context.last_blockhash,
+        );
+        self.program_context
+            .banks_client
+            .process_transaction(transaction)
+            .await
+    }
+
+    /// Simulates `instruction`, paid and signed by the payer, and decodes what the bridge
+    /// returned. Panics if it fails or returns nothing.
+    pub async fn simulate_return_data(&mut self, instruction: Instruction) -> BridgeReturnData {
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&self.program_context.payer.pubkey()),
+            &[&self.program_context.payer],
+            self.program_context.last_blockhash,
+        );
+        let simulation = self
+            .banks_client()
+            .simulate_transaction(transaction)
+            .await
+            .unwrap();
+        assert!(
+            matches!(simulation.result, Some(Ok(()))),
+            "simulation failed: {:?}",
+            simulation.result
+        );
+        let return_data = simulation
+            .simulation_details
+            .and_then(|details| details.return_data)
+            .expect("no return data");
+        assert_eq!(return_data.program_id, self.program_id);
+        BridgeReturnData::unpack(&return_data.data).unwrap()
+    }
+
+    /// Queries the bridge. `Token` queries read the token config of `mint`.
+    pub async fn query(&mut self, query: BridgeQuery, mint: Option<&Pubkey>) -> BridgeReturnData {
+        let mut accounts = vec![AccountMeta::new_readonly(self.bridge_pda, false)];
+        if let Some(mint) = mint {
+            accounts.push(AccountMeta::new_readonly(
+                self.token_config_address(mint),
+                false,
+            ));
+        }
+        self.simulate_return_data(Instruction {
+            program_id: self.program_id,
+            accounts,
+            data: BridgeInstruction::Query(query).pack(),
+        })
+        .await
+    }
+
+    /// Moves to a new blockhash, so that a transaction identical to an earlier one is not
+    /// rejected as already processed.
+    pub async fn refresh_blockhash(&mut self) {
+        self.program_context.last_blockhash = self
+            .program_context
+            .get_new_latest_blockhash()
+            .await
+            .unwrap();
+    }
+
+    /// Moves the clock `seconds` forward, e.g. past the eta of a queued operation.
+    pub async fn advance_clock(&mut self, seconds: i64) {
+        let mut clock: Clock = self.banks_client().get_sysvar().await.unwrap();
+        clock.unix_timestamp += seconds;
+        self.program_context.set_sysvar(&clock);
+    }
+
+    pub fn receipt_address(&self, sequence: u64) -> Pubkey {
+        Pubkey::find_program_address(&[RECEIPT_SEED, &sequence.to_le_bytes()], &self.program_id).0
+    }
+
+    pub fn token_config_address(&self, mint: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[TOKEN_SEED, mint.as_ref()], &self.program_id).0
+    }
+
+    pub fn operation_address(&self, id: u64) -> Pubkey {
+        Pubkey::find_program_address(&[OPERATION_SEED, &id.to_le_bytes()], &self.program_id).0
+    }
+
+    pub fn native_vault(&self) -> Pubkey {
+        Pubkey::find_program_address(&[NATIVE_VAULT_SEED], &self.program_id).0
+    }
+
+    /// The bridge vault of `mint`.
+    pub fn bridge_ata(&self, mint: &Pubkey) -> Pubkey {
+        get_associated_token_address(&self.bridge_pda, mint)
+    }
+
+    /// A mint with 8 decimals and the payer as mint authority.
+    pub async fn create_mint(&mut self) -> Pubkey {
+        let mint = Keypair::new();
+        let payer = self.payer().pubkey();
+        let rent = self.banks_client().get_rent().await.unwrap();
+        self.process(
+            &[
+                system_instruction::create_account(
+                    &payer,
+                    &mint.pubkey(),
+                    rent.minimum_balance(Mint::LEN),
+                    Mint::LEN as u64,
+                    &spl_token::id(),
+                ),
+                spl_token::instruction::initialize_mint(
+                    &spl_token::id(),
+                    &mint.pubkey(),
+                    &payer,
+                    None,
+                    8,
+                )
+                .unwrap(),
+            ],
+            &[&mint],
+        )
+        .await
+        .unwrap();
+        mint.pubkey()
+    }
+
+    /// Creates the associated token account of `owner`.
+    pub async fn create_token_account(&mut self, owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+        let payer = self.payer().pubkey();
+        self.execute(create_associated_token_account(
+            &payer,
+            owner,
+            mint,
+            &spl_token::id(),
+        ))
+        .await
+        .unwrap();
+        get_associated_token_address(owner, mint)
+    }
+
+    pub async fn mint_to(&mut self, mint: &Pubkey, account: &Pubkey, amount: u64) {
+        let payer = self.payer().pubkey();
+        self.execute(
+            spl_token::instruction::mint_to(&spl_token::id(), mint, account, &payer, &[], amount)
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    }
+
+    /// Registers `mint`, or native SOL for `NATIVE_SOL_MINT`, which funds the rent reserve of
+    /// the native vault.
+    pub async fn register_token(&mut self, mint: &Pubkey, enabled: bool) {
+        let payer = self.payer().pubkey();
+        let mut accounts = vec![
+            AccountMeta::new(payer, true),
+            AccountMeta::new(self.bridge_pda, false),
+            AccountMeta::new(self.token_config_address(mint), false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ];
+        if *mint == NATIVE_SOL_MINT {
+            accounts.push(AccountMeta::new(self.native_vault(), false));
+        }
+        accounts.push(AccountMeta::new_readonly(sysvar::instructions::id(), false));
+        self.execute(Instruction {
+            program_id: self.program_id,
+            accounts,
+            data: BridgeInstruction::RegisterToken(RegisterTokenData { enabled }).pack(),
+        })
+        .await
+        .unwrap();
+    }
+
+    /// A new mint, enabled on the bridge, whose vault holds `vault_amount`. The amount counts
+    /// as locked, so it can be released. Returns the mint and the vault.
+    pub async fn add_token(&mut self, vault_amount: u64) -> (Pubkey, Pubkey) {
+        let mint = self.create_mint().await;
+        let bridge_pda = self.bridge_pda;
+        let vault = self.create_token_account(&bridge_pda, &mint).await;
+        self.register_token(&mint, true).await;
+        if vault_amount > 0 {
+            self.mint_to(&mint, &vault, vault_amount).await;
+            self.credit_locked(&mint, vault_amount).await;
+        }
+        (mint, vault)
+    }
+
+    /// Adds `amount` to the tokens locked for `mint`, as if it had been bridged out, without
+    /// moving any tokens.
+    pub async fn credit_locked(&mut self, mint: &Pubkey, amount: u64) {
+        let address = self.token_config_address(mint);
+        let mut account = self
+            .banks_client()
+            .get_account(address)
+            .await
+            .unwrap()
+            .expect("token is not registered");
+        let mut token_config = TokenConfig::try_from_slice(&account.data).unwrap();
+        token_config.locked += amount;
+        account.data = token_config.try_to_vec().unwrap();
+        self.program_context.set_account(&address, &account.into());
+    }
+
+    /// A new wallet holding `lamports`.
+    pub async fn create_user(&mut self, lamports: u64) -> Keypair {
+        let user = Keypair::new();
+        let payer = self.payer().pubkey();
+        self.execute(system_instruction::transfer(
+            &payer,
+            &user.pubkey(),
+            lamports,
+        ))
+        .await
+        .unwrap();
+        user
+    }
+
+    /// Gives `user` a token account with `amount` of `mint` that the bridge pda may spend, as
+    /// `TransferOut` requires.
+    pub async fn fund_user(&mut self, user: &Keypair, mint: &Pubkey, amount: u64) -> Pubkey {
+        self.fund_owner(&user.pubkey(), &[user], mint, amount).await
+    }
+
+    /// Same as `fund_user` for the payer's own token account.
+    pub async fn fund_payer(&mut self, mint: &Pubkey, amount: u64) -> Pubkey {
+        let payer = self.payer().pubkey();
+        self.fund_owner(&payer, &[], mint, amount).await
+    }
+
+    async fn fund_owner(
+        &mut self,
+        owner: &Pubkey,
+        signers: &[&Keypair],
+        mint: &Pubkey,
+        amount: u64,
+    ) -> Pubkey {
+        let owner_ata = self.create_token_account(owner, mint).await;
+        self.mint_to(mint, &owner_ata, amount).await;
+        let approve = spl_token::instruction::approve(
+            &spl_token::id(),
+            &owner_ata,
+            &self.bridge_pda,
+            owner,
+            &[],
+            amount,
+        )
+        .unwrap();
+        self.process(&[approve], signers).await.unwrap();
+        owner_ata
+    }
+
+    pub async fn token_balance(&mut self, account: &Pubkey) -> u64 {
+        let account = self.banks_client().get_account(*account).await.unwrap();
+        SplTokenAccount::unpack(&account.expect("token account not found").data)
+            .unwrap()
+            .amount
+    }
+
+    pub async fn lamports(&mut self, address: &Pubkey) -> u64 {
+        self.banks_client().get_balance(*address).await.unwrap()
+    }
+
+    /// Decodes a borsh account, `None` if it does not exist.
+    pub async fn get_decoded<T: BorshDeserialize>(&mut self, address: &Pubkey) -> Option<T> {
+        let account = self.banks_client().get_account(*address).await.unwrap()?;
+        Some(T::try_from_slice(&account.data).unwrap())
+    }
+
+    pub async fn state(&mut self) -> BridgeStateV0 {
+        let bridge_pda = self.bridge_pda;
+        self.get_decoded(&bridge_pda).await.unwrap()
+    }
+
+    pub async fn receipt(&mut self, sequence: u64) -> TransferReceipt {
+        let receipt = self.receipt_address(sequence);
+        self.get_decoded(&receipt).await.unwrap()
+    }
+
+    pub async fn token_config(&mut self, mint: &Pubkey) -> TokenConfig {
+        let token_config = self.token_config_address(mint);
+        self.get_decoded(&token_config).await.unwrap()
+    }
+
+    pub async fn assert_token_balance(&mut self, account: &Pubkey, expected: u64) {
+        assert_eq!(
+            self.token_balance(account).await,
+            expected,
+            "token balance of {}",
+            account
+        );
+    }
+
+    pub async fn assert_lamports(&mut self, address: &Pubkey, expected: u64) {
+        assert_eq!(
+            self.lamports(address).await,
+            expected,
+            "lamports of {}",
+            address
+        );
+    }
+}
@@ -1,168 +1,1716 @@
 #![cfg(feature = "test-bpf")]
 
-mod token_action;
 use sisu_bridge::state;
 use {
     assert_matches::*,
-    borsh::{BorshDeserialize, BorshSerialize},
-    sisu_bridge::processor::Processor,
+    borsh::BorshSerialize,
+    sisu_bridge::error::BridgeError,
     sisu_bridge::state::BridgeInstruction,
-    sisu_bridge::state::BridgeStateV0,
-    sisu_bridge::state::TransferInIx,
-    solana_program::instruction::{AccountMeta, Instruction},
-    solana_program::{hash::Hash, pubkey::Pubkey, system_program},
-    solana_program_test::*,
+    sisu_bridge::test_utils::BridgeTestContext,
+    solana_program::instruction::{AccountMeta, Instruction, InstructionError},
+    solana_program::{
+        program_pack::Pack, pubkey::Pubkey, system_instruction, system_program, sysvar,
+    },
     solana_sdk::{
-        program_pack::Pack, signature::Keypair, signature::Signer, transaction::Transaction,
+        compute_budget::ComputeBudgetInstruction,
+        packet::PACKET_DATA_SIZE,
+        signature::Keypair,
+        signature::Signer,
+        transaction::{Transaction, TransactionError},
     },
     spl_token::state::Account as SplTokenAccount,
 };
 
 const INIT_AMOUNT: u64 = 1_000_000_000_000_000;
 
-async fn initialize() -> (BanksClient, Keypair, Pubkey, Pubkey, Hash) {
-    let bridge_program_id = Pubkey::new_unique();
-    let seed_string = b"SisuBridge";
-    let (bridge_pda, _) = Pubkey::find_program_address(&[seed_string], &bridge_program_id);
-
-    let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
-        "sisu_bridge",
-        bridge_program_id,
-        processor!(Processor::process_instruction),
-    )
-    .start()
-    .await;
-
-    let data = BridgeInstruction::try_to_vec(&BridgeInstruction::Initialize).unwrap();
-    let mut transaction = Transaction::new_with_payer(
-        &[Instruction {
-            program_id: bridge_program_id,
+// Last account of every instruction that only the top level or an allowed program may call.
+fn instructions_sysvar() -> AccountMeta {
+    AccountMeta::new_readonly(sysvar::instructions::id(), false)
+}
+
+#[tokio::test]
+async fn test_initialize() {
+    let mut context = BridgeTestContext::new().await;
+
+    // The admin holds no hot role, the spender was added apart from it.
+    let state = context.state().await;
+    assert_eq!(state.admin, context.payer().pubkey());
+    assert_eq!(state.guardian, Pubkey::default());
+    assert!(!state.spenders.contains(&context.payer().pubkey()));
+    assert!(state.spenders.contains(&context.spender.pubkey()));
+}
+
+#[tokio::test]
+async fn test_reject_trailing_data() {
+    let mut context = BridgeTestContext::new().await;
+
+    let mut data = BridgeInstruction::SetPaused(state::SetPausedData { paused: true }).pack();
+    data.push(0);
+    let ix = Instruction {
+        program_id: context.program_id,
+        accounts: vec![],
+        data,
+    };
+    let err = context.execute(ix).await.unwrap_err().unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(BridgeError::TrailingInstructionData as u32)
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_transfer_in() {
+    let mut context = BridgeTestContext::new().await;
+    let (mint, bridge_ata) = context.add_token(INIT_AMOUNT).await;
+
+    // Do a transfer in
+    let amount = 1000;
+    let ix = BridgeInstruction::TransferIn(state::TransferInData {
+        nonce: 1,
+        amounts: vec![amount],
+    });
+
+    let user = Keypair::new();
+    let user_ata = context.create_token_account(&user.pubkey(), &mint).await;
+
+    // Make the transfer request
+    context
+        .relay(Instruction {
+            program_id: context.program_id,
             accounts: vec![
-                AccountMeta::new(payer.pubkey(), false),
-                AccountMeta::new(bridge_pda, false),
-                AccountMeta::new(system_program::id(), false),
+                AccountMeta::new(context.spender.pubkey(), true),
+                AccountMeta::new_readonly(spl_token::id(), false),
+                AccountMeta::new_readonly(context.bridge_pda, false),
+                AccountMeta::new(bridge_ata, false),
+                AccountMeta::new(user_ata, false),
+                AccountMeta::new(context.token_config_address(&mint), false),
+                instructions_sysvar(),
             ],
-            data,
-        }],
-        Some(&payer.pubkey()),
+            data: ix.pack(),
+        })
+        .await
+        .unwrap();
+
+    // Check balance
+    context.assert_token_balance(&user_ata, amount).await;
+}
+
+#[tokio::test]
+async fn test_transfer_native() {
+    let mut context = BridgeTestContext::new().await;
+    let payer = context.payer().pubkey();
+    let native_vault = context.native_vault();
+    let native_config = context.token_config_address(&state::NATIVE_SOL_MINT);
+    let reserve = context
+        .banks_client()
+        .get_rent()
+        .await
+        .unwrap()
+        .minimum_balance(0);
+
+    let transfer_out = |context: &BridgeTestContext, bridge_pda, sequence, amount| Instruction {
+        program_id: context.program_id,
+        accounts: vec![
+            AccountMeta::new(payer, true),
+            AccountMeta::new(bridge_pda, false),
+            AccountMeta::new(native_vault, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(context.receipt_address(sequence), false),
+            AccountMeta::new(native_config, false),
+        ],
+        data: BridgeInstruction::TransferOutNative(state::TransferOutNativeData {
+            amount,
+            chain_id: 189985,
+            recipient: "0x8095f5b69F2970f38DC6eBD2682ed71E4939f988".to_string(),
+        })
+        .pack(),
+    };
+    let transfer_in = |context: &BridgeTestContext, nonce, receiver, amount| Instruction {
+        program_id: context.program_id,
+        accounts: vec![
+            AccountMeta::new(context.spender.pubkey(), true),
+            AccountMeta::new_readonly(context.bridge_pda, false),
+            AccountMeta::new(native_vault, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(native_config, false),
+            AccountMeta::new(receiver, false),
+            instructions_sysvar(),
+        ],
+        data: BridgeInstruction::TransferInNative(state::TransferInData {
+            nonce,
+            amounts: vec![amount],
+        })
+        .pack(),
+    };
+
+    // Native SOL only leaves once it is registered, and counts as locked from then on. The
+    // registration funds the rent reserve of the vault, which is not counted.
+    let out_amount = 2_000_000_000;
+    let ix = transfer_out(&context, context.bridge_pda, 0, out_amount);
+    assert!(context.execute(ix).await.is_err());
+    context.register_token(&state::NATIVE_SOL_MINT, true).await;
+    context.assert_lamports(&native_vault, reserve).await;
+    context.refresh_blockhash().await;
+    let ix = transfer_out(&context, context.bridge_pda, 0, out_amount);
+    context.execute(ix).await.unwrap();
+    context
+        .assert_lamports(&native_vault, reserve + out_amount)
+        .await;
+    assert_eq!(
+        context.token_config(&state::NATIVE_SOL_MINT).await.locked,
+        out_amount
     );
-    transaction.sign(&[&payer], recent_blockhash);
-    assert_matches!(banks_client.process_transaction(transaction).await, Ok(()));
 
-    // Make sure the new
-    assert_matches!(banks_client.get_account(bridge_pda).await, Ok(_));
+    // A copy of the bridge state at another address is not the bridge.
+    let forged_pda = Pubkey::new_unique();
+    let bridge_account = context
+        .banks_client()
+        .get_account(context.bridge_pda)
+        .await
+        .unwrap()
+        .unwrap();
+    context
+        .program_context
+        .set_account(&forged_pda, &bridge_account.into());
+    let ix = transfer_out(&context, forged_pda, 1, out_amount);
+    assert!(context.execute(ix).await.is_err());
+    context
+        .assert_lamports(&native_vault, reserve + out_amount)
+        .await;
 
-    let account = banks_client.get_account(bridge_pda).await.unwrap().unwrap();
+    let ix = transfer_out(&context, context.bridge_pda, 1, out_amount);
+    context.execute(ix).await.unwrap();
 
-    let state = BridgeStateV0::try_from_slice(account.data.as_slice()).unwrap();
-    assert_eq!(payer.pubkey(), state.admin);
+    // Release part of it to a fresh account.
+    let receiver = Pubkey::new_unique();
+    let in_amount = 1_000_000_000;
+    context
+        .relay(transfer_in(&context, 1, receiver, in_amount))
+        .await
+        .unwrap();
+    context.assert_lamports(&receiver, in_amount).await;
+    context
+        .assert_lamports(&native_vault, reserve + 2 * out_amount - in_amount)
+        .await;
 
-    return (
-        banks_client,
-        payer,
-        bridge_program_id,
-        bridge_pda,
-        recent_blockhash,
+    // A refund is released like a transfer in.
+    let refund = receipt_ix(
+        BridgeInstruction::Refund,
+        &context,
+        1,
+        vec![
+            AccountMeta::new(native_vault, false),
+            AccountMeta::new(payer, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(native_config, false),
+        ],
+    );
+    context.relay(refund).await.unwrap();
+    let config = context.token_config(&state::NATIVE_SOL_MINT).await;
+    assert_eq!(
+        (config.locked, config.released),
+        (2 * out_amount, in_amount + out_amount)
     );
+
+    // Lamports sent straight to the vault were never bridged in, they cannot be released.
+    let outstanding = out_amount - in_amount;
+    context
+        .execute(system_instruction::transfer(&payer, &native_vault, 5 * outstanding))
+        .await
+        .unwrap();
+    let err = context
+        .relay(transfer_in(&context, 2, receiver, outstanding + 1))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_bridge_error(err, BridgeError::ExceedsLocked);
+
+    // The donation shows up as surplus, the reserve does not.
+    let logs = reconcile_logs(&mut context, native_config, native_vault).await;
+    assert!(logs.iter().any(|log| log.ends_with(
+        "outstanding 1000000000, balance 6000000000, surplus 5000000000, shortfall 0"
+    )));
 }
 
 #[tokio::test]
-async fn test_initialize() {
-    initialize().await;
+async fn test_native_vault_reserve() {
+    let mut context = BridgeTestContext::new().await;
+    let payer = context.payer().pubkey();
+    let native_vault = context.native_vault();
+    let native_config = context.token_config_address(&state::NATIVE_SOL_MINT);
+    let reserve = context
+        .banks_client()
+        .get_rent()
+        .await
+        .unwrap()
+        .minimum_balance(0);
+    context.register_token(&state::NATIVE_SOL_MINT, true).await;
+    context.assert_lamports(&native_vault, reserve).await;
+
+    // A deposit far below the rent exempt minimum, into a vault that holds nothing else.
+    let amount = 1000;
+    let transfer_out = Instruction {
+        program_id: context.program_id,
+        accounts: vec![
+            AccountMeta::new(payer, true),
+            AccountMeta::new(context.bridge_pda, false),
+            AccountMeta::new(native_vault, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(context.receipt_address(0), false),
+            AccountMeta::new(native_config, false),
+        ],
+        data: BridgeInstruction::TransferOutNative(state::TransferOutNativeData {
+            amount,
+            chain_id: 189985,
+            recipient: "0x8095f5b69F2970f38DC6eBD2682ed71E4939f988".to_string(),
+        })
+        .pack(),
+    };
+    context.execute(transfer_out).await.unwrap();
+    context
+        .assert_lamports(&native_vault, reserve + amount)
+        .await;
+
+    // The whole amount comes back out, the reserve stays behind.
+    let receiver = context.create_user(1_000_000_000).await.pubkey();
+    let transfer_in = Instruction {
+        program_id: context.program_id,
+        accounts: vec![
+            AccountMeta::new(context.spender.pubkey(), true),
+            AccountMeta::new_readonly(context.bridge_pda, false),
+            AccountMeta::new(native_vault, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(native_config, false),
+            AccountMeta::new(receiver, false),
+            instructions_sysvar(),
+        ],
+        data: BridgeInstruction::TransferInNative(state::TransferInData {
+            nonce: 1,
+            amounts: vec![amount],
+        })
+        .pack(),
+    };
+    context.relay(transfer_in).await.unwrap();
+    context
+        .assert_lamports(&receiver, 1_000_000_000 + amount)
+        .await;
+    context.assert_lamports(&native_vault, reserve).await;
+    let config = context.token_config(&state::NATIVE_SOL_MINT).await;
+    assert_eq!((config.locked, config.released), (amount, amount));
+}
+
+#[tokio::test]
+async fn test_transfer_out_batch() {
+    let mut context = BridgeTestContext::new().await;
+
+    // Two different mints, each with a funded user account that delegates to the bridge.
+    let mut accounts = vec![
+        AccountMeta::new(context.payer().pubkey(), true),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new(context.bridge_pda, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    let mut items = vec![];
+    let mut bridge_atas = vec![];
+    for (i, amount) in [100u64, 200u64].into_iter().enumerate() {
+        let (mint, bridge_ata) = context.add_token(INIT_AMOUNT).await;
+        let user_ata = context.fund_payer(&mint, amount).await;
+
+        accounts.push(AccountMeta::new(user_ata, false));
+        accounts.push(AccountMeta::new(bridge_ata, false));
+        accounts.push(AccountMeta::new(context.receipt_address(i as u64), false));
+        accounts.push(AccountMeta::new(context.token_config_address(&mint), false));
+        bridge_atas.push(bridge_ata);
+        items.push(state::TransferOutData {
+            amount,
+            token_address: mint.to_string(),
+            chain_id: 100 + i as u64,
+            recipient: format!("recipient-{}", i),
+        });
+    }
+
+    let data = BridgeInstruction::TransferOutBatch(state::TransferOutBatchData { items }).pack();
+    context
+        .execute(Instruction {
+            program_id: context.program_id,
+            accounts,
+            data,
+        })
+        .await
+        .unwrap();
+
+    for (bridge_ata, amount) in bridge_atas.into_iter().zip([100u64, 200u64]) {
+        context
+            .assert_token_balance(&bridge_ata, INIT_AMOUNT + amount)
+            .await;
+    }
+
+    // Each item consumed its own sequence number.
+    assert_eq!(context.state().await.out_sequence, 2);
 }
 
-async fn create_token_and_bridge_ata(
-    banks_client: &mut BanksClient,
-    payer: &Keypair,
-    bridge_pda: &Pubkey,
-    recent_blockhash: Hash,
-) -> (Keypair, Pubkey) {
-    let mint = Keypair::new();
-    let decimals = 8;
+#[tokio::test]
+async fn test_transfer_in_compact() {
+    let mut context = BridgeTestContext::new().await;
+    let (mint, bridge_ata) = context.add_token(INIT_AMOUNT).await;
+
+    // Three receivers that each get paid twice from the same vault.
+    let mut accounts = vec![
+        AccountMeta::new(context.spender.pubkey(), true),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new_readonly(context.bridge_pda, false),
+        AccountMeta::new(bridge_ata, false),
+        AccountMeta::new(context.token_config_address(&mint), false),
+    ];
+    let mut receivers = vec![];
+    for _ in 0..3 {
+        let receiver_ata = context
+            .create_token_account(&Pubkey::new_unique(), &mint)
+            .await;
+        accounts.push(AccountMeta::new(receiver_ata, false));
+        receivers.push(receiver_ata);
+    }
 
-    token_action::create_mint(banks_client, recent_blockhash, &payer, &mint, decimals)
+    let mut items = vec![];
+    for round in 0..2u64 {
+        for i in 0..receivers.len() {
+            items.push(state::CompactTransferItem {
+                vault_index: 0,
+                receiver_index: (i + 2) as u8,
+                config_index: 1,
+                amount: state::VarU64(100 * (round + 1)),
+            });
+        }
+    }
+
+    accounts.push(instructions_sysvar());
+    let data =
+        BridgeInstruction::TransferInCompact(state::TransferInCompactData { nonce: 1, items })
+            .pack();
+    context
+        .relay(Instruction {
+            program_id: context.program_id,
+            accounts,
+            data,
+        })
         .await
         .unwrap();
 
-    // Create bridge_ata
-    let bridge_ata = token_action::create_associated_account(
-        banks_client,
-        recent_blockhash,
-        &payer,
-        &bridge_pda,
-        &mint.pubkey(),
-    )
-    .await
-    .unwrap();
+    for receiver_ata in receivers {
+        context.assert_token_balance(&receiver_ata, 300).await;
+    }
+}
+
+// Size of a transaction with a single signature, as it goes over the wire.
+fn transaction_size(payer: &Pubkey, ix: Instruction) -> usize {
+    let transaction = Transaction::new_with_payer(&[ix], Some(payer));
+    1 + 64 + transaction.message.serialize().len()
+}
 
-    // Mint to the bridge ata
-    token_action::mint_to(
-        banks_client,
-        recent_blockhash,
-        &payer,
-        &mint.pubkey(),
-        &bridge_ata,
-        &payer,
-        INIT_AMOUNT,
-    )
-    .await
-    .unwrap();
+#[test]
+fn test_compact_batch_size() {
+    let program_id = Pubkey::new_unique();
+    let payer = Pubkey::new_unique();
+    let bridge_pda = Pubkey::new_unique();
+    let bridge_ata = Pubkey::new_unique();
+    let token_config = Pubkey::new_unique();
+    let receivers: Vec<Pubkey> = (0..64).map(|_| Pubkey::new_unique()).collect();
 
-    // Verify the bridge ata has expected amount
-    let account = banks_client.get_account(bridge_ata).await.unwrap().unwrap();
-    let account_token = SplTokenAccount::unpack(&account.data).unwrap();
-    assert_eq!(account_token.amount, INIT_AMOUNT);
+    let legacy_ix = |n: usize| {
+        let mut accounts = vec![
+            AccountMeta::new(payer, true),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(bridge_pda, false),
+        ];
+        for receiver in &receivers[..n] {
+            accounts.push(AccountMeta::new(bridge_ata, false));
+            accounts.push(AccountMeta::new(*receiver, false));
+            accounts.push(AccountMeta::new(token_config, false));
+        }
+        accounts.push(instructions_sysvar());
+        let data = BridgeInstruction::TransferIn(state::TransferInData {
+            nonce: 1,
+            amounts: vec![100; n],
+        });
+        Instruction {
+            program_id,
+            accounts,
+            data: data.pack(),
+        }
+    };
 
-    return (mint, bridge_ata);
+    let compact_ix = |n: usize| {
+        let mut accounts = vec![
+            AccountMeta::new(payer, true),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(bridge_pda, false),
+            AccountMeta::new(bridge_ata, false),
+            AccountMeta::new(token_config, false),
+        ];
+        let mut items = vec![];
+        for (i, receiver) in receivers[..n].iter().enumerate() {
+            accounts.push(AccountMeta::new(*receiver, false));
+            items.push(state::CompactTransferItem {
+                vault_index: 0,
+                receiver_index: (i + 2) as u8,
+                config_index: 1,
+                amount: state::VarU64(100),
+            });
+        }
+        accounts.push(instructions_sysvar());
+        let data =
+            BridgeInstruction::TransferInCompact(state::TransferInCompactData { nonce: 1, items })
+                .pack();
+        Instruction {
+            program_id,
+            accounts,
+            data,
+        }
+    };
+
+    let max_items = |build: &dyn Fn(usize) -> Instruction| {
+        (1..receivers.len())
+            .take_while(|n| transaction_size(&payer, build(*n)) <= PACKET_DATA_SIZE)
+            .last()
+            .unwrap()
+    };
+
+    // Every receiver still costs its 32 byte key, the compact encoding saves the rest: two
+    // account indexes and seven bytes of amount per transfer.
+    assert_eq!(max_items(&legacy_ix), 20);
+    assert_eq!(max_items(&compact_ix), 23);
+}
+
+// Locks `amount` from the payer's token account `user_ata`.
+fn transfer_out_ix(
+    context: &BridgeTestContext,
+    mint: &Pubkey,
+    user_ata: &Pubkey,
+    sequence: u64,
+    amount: u64,
+) -> Instruction {
+    let data = BridgeInstruction::TransferOut(state::TransferOutData {
+        amount,
+        token_address: "0x1234".to_string(),
+        chain_id: 123,
+        recipient: "someone".to_string(),
+    })
+    .pack();
+    Instruction {
+        program_id: context.program_id,
+        accounts: vec![
+            AccountMeta::new(context.payer().pubkey(), true),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new(*user_ata, false),
+            AccountMeta::new(context.bridge_ata(mint), false),
+            AccountMeta::new(context.bridge_pda, false),
+            AccountMeta::new(context.receipt_address(sequence), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(context.token_config_address(mint), false),
+        ],
+        data,
+    }
+}
+
+fn receipt_ix(
+    instruction: fn(state::ReceiptData) -> BridgeInstruction,
+    context: &BridgeTestContext,
+    sequence: u64,
+    mut extra_accounts: Vec<AccountMeta>,
+) -> Instruction {
+    let data = instruction(state::ReceiptData { sequence }).pack();
+    let mut accounts = vec![
+        AccountMeta::new_readonly(context.spender.pubkey(), true),
+        AccountMeta::new_readonly(context.bridge_pda, false),
+        AccountMeta::new(context.receipt_address(sequence), false),
+    ];
+    accounts.append(&mut extra_accounts);
+    accounts.push(instructions_sysvar());
+    Instruction {
+        program_id: context.program_id,
+        accounts,
+        data,
+    }
 }
 
 #[tokio::test]
-async fn test_transfer_in() {
-    let (mut banks_client, payer, bridge_program_id, bridge_pda, recent_blockhash) =
-        initialize().await;
-    let (mint, bridge_ata) =
-        create_token_and_bridge_ata(&mut banks_client, &payer, &bridge_pda, recent_blockhash).await;
+async fn test_refund() {
+    let mut context = BridgeTestContext::new().await;
+    let (mint, bridge_ata) = context.add_token(INIT_AMOUNT).await;
+    let user_ata = context.fund_payer(&mint, 1000).await;
 
-    // Do a transfer in
-    let amount = 1000;
-    let ix = TransferInIx::from_data(state::TransferInData {
-        nonce: 1,
-        amounts: vec![amount],
-    });
+    // Two outbound transfers: sequence 0 gets refunded, sequence 1 gets delivered.
+    for (sequence, amount) in [(0, 400), (1, 600)] {
+        let ix = transfer_out_ix(&context, &mint, &user_ata, sequence, amount);
+        context.execute(ix).await.unwrap();
+    }
+    context.assert_token_balance(&user_ata, 0).await;
 
-    let user = Keypair::new();
-    let user_ata = token_action::create_associated_account(
-        &mut banks_client,
-        recent_blockhash,
-        &payer,
-        &user.pubkey(),
-        &mint.pubkey(),
-    )
-    .await
-    .unwrap();
+    let refund_accounts = vec![
+        AccountMeta::new(bridge_ata, false),
+        AccountMeta::new(user_ata, false),
+        AccountMeta::new_readonly(spl_token::id(), false),
+        AccountMeta::new(context.token_config_address(&mint), false),
+    ];
+    let refund = |context: &BridgeTestContext, sequence| {
+        receipt_ix(
+            BridgeInstruction::Refund,
+            context,
+            sequence,
+            refund_accounts.clone(),
+        )
+    };
 
-    // Make the transfer request
+    context.relay(refund(&context, 0)).await.unwrap();
+    context.assert_token_balance(&user_ata, 400).await;
+    assert_eq!(
+        context.receipt(0).await.status,
+        state::ReceiptStatus::Refunded
+    );
+
+    // A refunded receipt cannot be refunded again.
+    context.refresh_blockhash().await;
+    assert!(context.relay(refund(&context, 0)).await.is_err());
+
+    // Nor can a delivered one.
+    let mark_delivered = receipt_ix(BridgeInstruction::MarkDelivered, &context, 1, vec![]);
+    context.relay(mark_delivered).await.unwrap();
+    assert!(context.relay(refund(&context, 1)).await.is_err());
+    context.assert_token_balance(&user_ata, 400).await;
+}
+
+#[tokio::test]
+async fn test_refund_native() {
+    let mut context = BridgeTestContext::new().await;
+    let payer = context.payer().pubkey();
+    let native_vault = context.native_vault();
+    let native_config = context.token_config_address(&state::NATIVE_SOL_MINT);
+    let reserve = context
+        .banks_client()
+        .get_rent()
+        .await
+        .unwrap()
+        .minimum_balance(0);
+    context.register_token(&state::NATIVE_SOL_MINT, true).await;
+
+    // The receipt holds the only SOL in the vault, beside the reserve.
+    let amount = 1_000_000_000;
+    let transfer_out = Instruction {
+        program_id: context.program_id,
+        accounts: vec![
+            AccountMeta::new(payer, true),
+            AccountMeta::new(context.bridge_pda, false),
+            AccountMeta::new(native_vault, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(context.receipt_address(0), false),
+            AccountMeta::new(native_config, false),
+        ],
+        data: BridgeInstruction::TransferOutNative(state::TransferOutNativeData {
+            amount,
+            chain_id: 189985,
+            recipient: "0x8095f5b69F2970f38DC6eBD2682ed71E4939f988".to_string(),
+        })
+        .pack(),
+    };
+    context.execute(transfer_out).await.unwrap();
+    context
+        .assert_lamports(&native_vault, reserve + amount)
+        .await;
+
+    let refund = receipt_ix(
+        BridgeInstruction::Refund,
+        &context,
+        0,
+        vec![
+            AccountMeta::new(native_vault, false),
+            AccountMeta::new(payer, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(native_config, false),
+        ],
+    );
+    let before = context.lamports(&payer).await;
+    context.relay(refund).await.unwrap();
+    context.assert_lamports(&native_vault, reserve).await;
+    assert_eq!(
+        context.receipt(0).await.status,
+        state::ReceiptStatus::Refunded
+    );
+    let config = context.token_config(&state::NATIVE_SOL_MINT).await;
+    assert_eq!((config.locked, config.released), (amount, amount));
+    assert!(context.lamports(&payer).await > before);
+}
+
+#[tokio::test]
+async fn test_locked_accounting() {
+    let mut context = BridgeTestContext::new().await;
+    let (mint, vault) = context.add_token(0).await;
+    let user_ata = context.fund_payer(&mint, 300).await;
+    let token_config = context.token_config_address(&mint);
+
+    let transfer_in = |context: &BridgeTestContext, nonce, amount| Instruction {
+        program_id: context.program_id,
+        accounts: vec![
+            AccountMeta::new(context.spender.pubkey(), true),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(context.bridge_pda, false),
+            AccountMeta::new(vault, false),
+            AccountMeta::new(user_ata, false),
+            AccountMeta::new(token_config, false),
+            instructions_sysvar(),
+        ],
+        data: BridgeInstruction::TransferIn(state::TransferInData {
+            nonce,
+            amounts: vec![amount],
+        })
+        .pack(),
+    };
+
+    // Tokens sent straight to the vault were never bridged in, they cannot be released.
+    context.mint_to(&mint, &vault, 500).await;
+    let err = context
+        .relay(transfer_in(&context, 1, 100))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_bridge_error(err, BridgeError::ExceedsLocked);
+
+    let ix = transfer_out_ix(&context, &mint, &user_ata, 0, 300);
+    context.execute(ix).await.unwrap();
+    context.relay(transfer_in(&context, 2, 200)).await.unwrap();
+    let config = context.token_config(&mint).await;
+    assert_eq!((config.locked, config.released), (300, 200));
+
+    let err = context
+        .relay(transfer_in(&context, 3, 200))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_bridge_error(err, BridgeError::ExceedsLocked);
+
+    // The donation shows up as surplus.
+    let logs = reconcile_logs(&mut context, token_config, vault).await;
+    assert!(logs
+        .iter()
+        .any(|log| log.ends_with("outstanding 100, balance 600, surplus 500, shortfall 0")));
+}
+
+// Logs of a simulated `Reconcile` of `vault` against `token_config`.
+async fn reconcile_logs(
+    context: &mut BridgeTestContext,
+    token_config: Pubkey,
+    vault: Pubkey,
+) -> Vec<String> {
+    let reconcile = Instruction {
+        program_id: context.program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(context.payer().pubkey(), true),
+            AccountMeta::new_readonly(context.bridge_pda, false),
+            AccountMeta::new_readonly(token_config, false),
+            AccountMeta::new_readonly(vault, false),
+            instructions_sysvar(),
+        ],
+        data: BridgeInstruction::Reconcile.pack(),
+    };
     let transaction = Transaction::new_signed_with_payer(
-        &[Instruction {
-            program_id: bridge_program_id,
-            accounts: vec![
-                AccountMeta::new(payer.pubkey(), true),
-                AccountMeta::new_readonly(spl_token::id(), false),
-                AccountMeta::new_readonly(bridge_pda, false),
-                AccountMeta::new(bridge_ata, false),
-                AccountMeta::new(user_ata, false),
+        &[reconcile],
+        Some(&context.payer().pubkey()),
+        &[context.payer()],
+        context.program_context.last_blockhash,
+    );
+    let simulation = context
+        .banks_client()
+        .simulate_transaction(transaction)
+        .await
+        .unwrap();
+    assert_matches!(simulation.result, Some(Ok(())));
+    simulation.simulation_details.unwrap().logs
+}
+
+#[tokio::test]
+async fn test_query() {
+    let mut context = BridgeTestContext::new().await;
+    let payer = context.payer().pubkey();
+    let (mint, _) = context.add_token(0).await;
+    let user_ata = context.fund_payer(&mint, 500).await;
+
+    // An outbound transfer returns the sequence of its receipt.
+    let ix = transfer_out_ix(&context, &mint, &user_ata, 0, 300);
+    assert_eq!(
+        context.simulate_return_data(ix.clone()).await,
+        state::BridgeReturnData::Sequence(0)
+    );
+    context.execute(ix).await.unwrap();
+
+    assert_eq!(
+        context.query(state::BridgeQuery::Config, None).await,
+        state::BridgeReturnData::Config(state::ConfigInfo {
+            admin: payer,
+            guardian: Pubkey::default(),
+            paused: false,
+            treasury: Pubkey::default(),
+            timelock_delay: 0,
+            token_count: 1,
+            out_sequence: 1,
+            message_sequence: 0,
+        })
+    );
+    assert_matches!(
+        context.query(state::BridgeQuery::Spenders, None).await,
+        state::BridgeReturnData::Spenders(state::SpendersInfo { epoch: 1, current, .. })
+            if current == context.spender.pubkey()
+    );
+    assert_eq!(
+        context.query(state::BridgeQuery::Token, Some(&mint)).await,
+        state::BridgeReturnData::Token(state::TokenInfo {
+            mint,
+            enabled: true,
+            locked: 300,
+            released: 0,
+        })
+    );
+}
+
+fn admin_ix<T>(
+    instruction: fn(T) -> BridgeInstruction,
+    context: &BridgeTestContext,
+    payload: T,
+) -> Instruction {
+    let data = instruction(payload).pack();
+    Instruction {
+        program_id: context.program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(context.payer().pubkey(), true),
+            AccountMeta::new(context.bridge_pda, false),
+            instructions_sysvar(),
+        ],
+        data,
+    }
+}
+
+#[tokio::test]
+async fn test_pause_and_disabled_token() {
+    let mut context = BridgeTestContext::new().await;
+    let mint = context.create_mint().await;
+    let bridge_pda = context.bridge_pda;
+    context.create_token_account(&bridge_pda, &mint).await;
+    let user_ata = context.fund_payer(&mint, 1000).await;
+    let transfer_out = |context: &BridgeTestContext, sequence, amount| {
+        transfer_out_ix(context, &mint, &user_ata, sequence, amount)
+    };
+
+    // Unregistered, then disabled mints cannot leave. A new config counts from zero.
+    assert!(context.execute(transfer_out(&context, 0, 1)).await.is_err());
+    context.register_token(&mint, false).await;
+    assert_eq!(context.token_config(&mint).await.locked, 0);
+    assert!(context.execute(transfer_out(&context, 0, 2)).await.is_err());
+    context.register_token(&mint, true).await;
+
+    // Nothing moves while the bridge is paused.
+    let pause = |context: &BridgeTestContext, paused| {
+        admin_ix(
+            BridgeInstruction::SetPaused,
+            context,
+            state::SetPausedData { paused },
+        )
+    };
+    context.execute(pause(&context, true)).await.unwrap();
+    assert!(context.execute(transfer_out(&context, 0, 3)).await.is_err());
+
+    context.execute(pause(&context, false)).await.unwrap();
+    context.execute(transfer_out(&context, 0, 4)).await.unwrap();
+    assert_eq!(context.token_config(&mint).await.locked, 4);
+}
+
+#[tokio::test]
+async fn test_remove_spender_and_change_admin() {
+    let mut context = BridgeTestContext::new().await;
+    let spender = context.spender.pubkey();
+    let new_admin = Keypair::new();
+
+    let remove_stranger = admin_ix(
+        BridgeInstruction::RemoveSpender,
+        &context,
+        state::AddSpenderData {
+            spender: Pubkey::new_unique(),
+        },
+    );
+    let err = context.execute(remove_stranger).await.unwrap_err().unwrap();
+    assert_bridge_error(err, BridgeError::NotASpender);
+
+    let remove_spender = admin_ix(
+        BridgeInstruction::RemoveSpender,
+        &context,
+        state::AddSpenderData { spender },
+    );
+    context.execute(remove_spender).await.unwrap();
+    let change_admin = admin_ix(
+        BridgeInstruction::ChangeAdmin,
+        &context,
+        state::ChangeAdminData {
+            new_admin: new_admin.pubkey(),
+        },
+    );
+    context.execute(change_admin).await.unwrap();
+
+    let state = context.state().await;
+    assert_eq!(state.admin, new_admin.pubkey());
+    assert_eq!(state.spenders, [Pubkey::default(); 2]);
+
+    // The old admin keeps no role, it no longer unpauses.
+    let pause = admin_ix(
+        BridgeInstruction::SetPaused,
+        &context,
+        state::SetPausedData { paused: false },
+    );
+    assert!(context.execute(pause).await.is_err());
+}
+
+#[tokio::test]
+async fn test_roles() {
+    let mut context = BridgeTestContext::new().await;
+    let (mint, bridge_ata) = context.add_token(INIT_AMOUNT).await;
+    let user_ata = context
+        .create_token_account(&Pubkey::new_unique(), &mint)
+        .await;
+    let registrar = context.create_user(1_000_000_000).await;
+    let guardian = Keypair::new();
+    let relayer = Keypair::new();
+
+    let set_role = |context: &BridgeTestContext, holder: &Keypair, role, granted| {
+        admin_ix(
+            BridgeInstruction::SetRole,
+            context,
+            state::SetRoleData {
+                holder: holder.pubkey(),
+                role,
+                granted,
+            },
+        )
+    };
+    let signed_by = |mut ix: Instruction, signer: &Keypair| {
+        ix.accounts[0].pubkey = signer.pubkey();
+        ix
+    };
+    let register = |context: &BridgeTestContext, mint: &Pubkey| Instruction {
+        program_id: context.program_id,
+        accounts: vec![
+            AccountMeta::new(registrar.pubkey(), true),
+            AccountMeta::new(context.bridge_pda, false),
+            AccountMeta::new(context.token_config_address(mint), false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            instructions_sysvar(),
+        ],
+        data: BridgeInstruction::RegisterToken(state::RegisterTokenData { enabled: true }).pack(),
+    };
+
+    // A token registrar registers tokens and nothing else.
+    let new_mint = context.create_mint().await;
+    let err = context
+        .process(&[register(&context, &new_mint)], &[&registrar])
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_bridge_error(err, BridgeError::MissingRole);
+    let ix = set_role(&context, &registrar, state::Role::TokenRegistrar, true);
+    context.execute(ix).await.unwrap();
+    context
+        .process(&[register(&context, &new_mint)], &[&registrar])
+        .await
+        .unwrap();
+    let ix = signed_by(
+        admin_ix(
+            BridgeInstruction::SetPaused,
+            &context,
+            state::SetPausedData { paused: true },
+        ),
+        &registrar,
+    );
+    let err = context
+        .process(&[ix], &[&registrar])
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_bridge_error(err, BridgeError::MissingRole);
+
+    // A guardian pauses, only the admin unpauses.
+    let ix = set_role(&context, &guardian, state::Role::Guardian, true);
+    context.execute(ix).await.unwrap();
+    let pause = |context: &BridgeTestContext, paused| {
+        signed_by(
+            admin_ix(
+                BridgeInstruction::SetPaused,
+                context,
+                state::SetPausedData { paused },
+            ),
+            &guardian,
+        )
+    };
+    context
+        .process(&[pause(&context, true)], &[&guardian])
+        .await
+        .unwrap();
+    let err = context
+        .process(&[pause(&context, false)], &[&guardian])
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_bridge_error(err, BridgeError::NotAnAdmin);
+    let ix = admin_ix(
+        BridgeInstruction::SetPaused,
+        &context,
+        state::SetPausedData { paused: false },
+    );
+    context.execute(ix).await.unwrap();
+
+    // Hot keys cannot take config roles, be it a spender or a guardian.
+    let ix = admin_ix(
+        BridgeInstruction::SetRole,
+        &context,
+        state::SetRoleData {
+            holder: context.spender.pubkey(),
+            role: state::Role::TokenRegistrar,
+            granted: true,
+        },
+    );
+    let err = context.execute(ix).await.unwrap_err().unwrap();
+    assert_bridge_error(err, BridgeError::RoleConflict);
+    let ix = set_role(&context, &guardian, state::Role::TokenRegistrar, true);
+    let err = context.execute(ix).await.unwrap_err().unwrap();
+    assert_bridge_error(err, BridgeError::RoleConflict);
+
+    // Relayers sign transfers in like spenders, until the role is revoked.
+    let transfer_in = |context: &BridgeTestContext, nonce| Instruction {
+        program_id: context.program_id,
+        accounts: vec![
+            AccountMeta::new(relayer.pubkey(), true),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(context.bridge_pda, false),
+            AccountMeta::new(bridge_ata, false),
+            AccountMeta::new(user_ata, false),
+            AccountMeta::new(context.token_config_address(&mint), false),
+            instructions_sysvar(),
+        ],
+        data: BridgeInstruction::TransferIn(state::TransferInData {
+            nonce,
+            amounts: vec![10],
+        })
+        .pack(),
+    };
+    let ix = set_role(&context, &relayer, state::Role::Relayer, true);
+    context.execute(ix).await.unwrap();
+    context
+        .process(&[transfer_in(&context, 1)], &[&relayer])
+        .await
+        .unwrap();
+    context.assert_token_balance(&user_ata, 10).await;
+
+    let ix = set_role(&context, &relayer, state::Role::Relayer, false);
+    context.execute(ix).await.unwrap();
+    let err = context
+        .process(&[transfer_in(&context, 2)], &[&relayer])
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_bridge_error(err, BridgeError::NotASpender);
+
+    let state = context.state().await;
+    assert_eq!(state.roles_of(&relayer.pubkey()), 0);
+    assert_eq!(
+        state.roles_of(&registrar.pubkey()),
+        state::Role::TokenRegistrar.bit()
+    );
+}
+
+#[tokio::test]
+async fn test_spender_epochs() {
+    let mut context = BridgeTestContext::new().await;
+    let (mint, bridge_ata) = context.add_token(INIT_AMOUNT).await;
+    let user_ata = context
+        .create_token_account(&Pubkey::new_unique(), &mint)
+        .await;
+    let spender = context.spender.pubkey();
+    let new_spender = Keypair::new();
+
+    let transfer_in = |context: &BridgeTestContext, spender: &Pubkey, nonce| Instruction {
+        program_id: context.program_id,
+        accounts: vec![
+            AccountMeta::new(*spender, true),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(context.bridge_pda, false),
+            AccountMeta::new(bridge_ata, false),
+            AccountMeta::new(user_ata, false),
+            AccountMeta::new(context.token_config_address(&mint), false),
+            instructions_sysvar(),
+        ],
+        data: BridgeInstruction::TransferIn(state::TransferInData {
+            nonce,
+            amounts: vec![10],
+        })
+        .pack(),
+    };
+
+    let activate = admin_ix(
+        BridgeInstruction::ActivateSpender,
+        &context,
+        state::ActivateSpenderData {
+            spender: new_spender.pubkey(),
+            overlap: state::SpenderOverlap::Slots(100),
+        },
+    );
+    context.execute(activate).await.unwrap();
+
+    let state = context.state().await;
+    assert_eq!(state.spender_epoch, 2);
+    assert_eq!(state.spender_keys().current(), &new_spender.pubkey());
+    assert_eq!(state.spender_keys().previous(), &spender);
+
+    // Both key sets sign during the overlap.
+    context
+        .relay(transfer_in(&context, &spender, 1))
+        .await
+        .unwrap();
+    let ix = transfer_in(&context, &new_spender.pubkey(), 2);
+    context.process(&[ix], &[&new_spender]).await.unwrap();
+
+    // After the deadline only the new one does.
+    context
+        .program_context
+        .warp_to_slot(state.previous_valid_until_slot + 1)
+        .unwrap();
+    context.refresh_blockhash().await;
+    let err = context
+        .relay(transfer_in(&context, &spender, 3))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(BridgeError::SpenderExpired as u32)
+        )
+    );
+    let ix = transfer_in(&context, &new_spender.pubkey(), 4);
+    context.process(&[ix], &[&new_spender]).await.unwrap();
+    context.assert_token_balance(&user_ata, 30).await;
+
+    // Keys of older epochs are not spenders at all.
+    let third_spender = Keypair::new();
+    let activate = admin_ix(
+        BridgeInstruction::AddSpender,
+        &context,
+        state::AddSpenderData {
+            spender: third_spender.pubkey(),
+        },
+    );
+    context.execute(activate).await.unwrap();
+    assert_eq!(context.state().await.spender_epoch, 3);
+    let err = context
+        .relay(transfer_in(&context, &spender, 5))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(BridgeError::NotASpender as u32)
+        )
+    );
+}
+
+fn operation_ix(
+    instruction: BridgeInstruction,
+    context: &BridgeTestContext,
+    signer: &Pubkey,
+    id: u64,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*signer, true),
+        AccountMeta::new(context.bridge_pda, false),
+        AccountMeta::new(context.operation_address(id), false),
+    ];
+    if let BridgeInstruction::QueueOperation(_) = instruction {
+        accounts.push(AccountMeta::new_readonly(system_program::id(), false));
+    }
+    accounts.push(instructions_sysvar());
+    Instruction {
+        program_id: context.program_id,
+        accounts,
+        data: instruction.pack(),
+    }
+}
+
+fn assert_bridge_error(err: TransactionError, expected: BridgeError) {
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(0, InstructionError::Custom(expected as u32))
+    );
+}
+
+#[tokio::test]
+async fn test_timelock() {
+    let mut context = BridgeTestContext::new().await;
+    let payer = context.payer().pubkey();
+    let spender = context.spender.pubkey();
+    let guardian = context.create_user(1_000_000_000).await;
+    let new_admin = Pubkey::new_unique();
+
+    let set_timelock = admin_ix(
+        BridgeInstruction::SetTimelock,
+        &context,
+        state::SetTimelockData {
+            guardian: guardian.pubkey(),
+            delay: 3600,
+        },
+    );
+    context.execute(set_timelock).await.unwrap();
+
+    // Sensitive changes no longer apply directly.
+    let change_admin = state::ChangeAdminData { new_admin };
+    let ix = admin_ix(
+        BridgeInstruction::ChangeAdmin,
+        &context,
+        change_admin.clone(),
+    );
+    let err = context.execute(ix).await.unwrap_err().unwrap();
+    assert_bridge_error(err, BridgeError::TimelockRequired);
+
+    let queue = |context: &BridgeTestContext, id, operation| {
+        operation_ix(
+            BridgeInstruction::QueueOperation(state::QueueOperationData { id, operation }),
+            context,
+            &payer,
+            id,
+        )
+    };
+    let execute = |context: &BridgeTestContext, id| {
+        operation_ix(
+            BridgeInstruction::ExecuteOperation(state::OperationData { id }),
+            context,
+            &payer,
+            id,
+        )
+    };
+    let cancel = |context: &BridgeTestContext, signer: &Pubkey, id| {
+        operation_ix(
+            BridgeInstruction::CancelOperation(state::OperationData { id }),
+            context,
+            signer,
+            id,
+        )
+    };
+
+    let ix = queue(
+        &context,
+        1,
+        state::AdminOperation::ChangeAdmin(change_admin),
+    );
+    context.execute(ix).await.unwrap();
+    let ix = queue(
+        &context,
+        2,
+        state::AdminOperation::RemoveSpender(state::AddSpenderData { spender }),
+    );
+    context.execute(ix).await.unwrap();
+
+    let pending: state::PendingOperation = context
+        .get_decoded(&context.operation_address(1))
+        .await
+        .unwrap();
+    let err = context
+        .execute(execute(&context, 1))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_bridge_error(err, BridgeError::OperationNotReady);
+
+    // Only the guardian cancels, and a cancelled operation is gone for good.
+    let err = context
+        .execute(cancel(&context, &payer, 2))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_bridge_error(err, BridgeError::NotAGuardian);
+    let ix = cancel(&context, &guardian.pubkey(), 2);
+    context.process(&[ix], &[&guardian]).await.unwrap();
+    assert!(context
+        .get_decoded::<state::PendingOperation>(&context.operation_address(2))
+        .await
+        .is_none());
+
+    context.advance_clock(3600).await;
+    context.refresh_blockhash().await;
+    let clock: solana_program::clock::Clock = context.banks_client().get_sysvar().await.unwrap();
+    assert!(clock.unix_timestamp >= pending.eta);
+    assert!(context.execute(execute(&context, 2)).await.is_err());
+    context.execute(execute(&context, 1)).await.unwrap();
+
+    let state = context.state().await;
+    assert_eq!(state.admin, new_admin);
+    assert!(state.spenders.contains(&spender));
+    assert!(context
+        .get_decoded::<state::PendingOperation>(&context.operation_address(1))
+        .await
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_decommission() {
+    let mut context = BridgeTestContext::new().await;
+    let payer = context.payer().pubkey();
+    let (mint, vault) = context.add_token(1000).await;
+    let native_vault = context.native_vault();
+    context
+        .execute(system_instruction::transfer(
+            &payer,
+            &native_vault,
+            5_000_000,
+        ))
+        .await
+        .unwrap();
+    context.register_token(&state::NATIVE_SOL_MINT, true).await;
+    let native_config = context.token_config_address(&state::NATIVE_SOL_MINT);
+    let treasury = Pubkey::new_unique();
+    let treasury_ata = context.create_token_account(&treasury, &mint).await;
+
+    // A second bridge account that is not the associated one, it can only receive donations.
+    let other_vault = Keypair::new();
+    let bank_rent = context.banks_client().get_rent().await.unwrap();
+    let bridge_pda = context.bridge_pda;
+    context
+        .process(
+            &[
+                system_instruction::create_account(
+                    &payer,
+                    &other_vault.pubkey(),
+                    bank_rent.minimum_balance(SplTokenAccount::LEN),
+                    SplTokenAccount::LEN as u64,
+                    &spl_token::id(),
+                ),
+                spl_token::instruction::initialize_account(
+                    &spl_token::id(),
+                    &other_vault.pubkey(),
+                    &mint,
+                    &bridge_pda,
+                )
+                .unwrap(),
             ],
-            data: ix.try_to_vec().unwrap(),
-        }],
-        Some(&payer.pubkey()),
-        &[&payer],
-        recent_blockhash,
+            &[&other_vault],
+        )
+        .await
+        .unwrap();
+    let other_vault = other_vault.pubkey();
+    context.mint_to(&mint, &other_vault, 500).await;
+    let user_ata = context.fund_payer(&mint, 100).await;
+    let mut ix = transfer_out_ix(&context, &mint, &user_ata, 0, 100);
+    ix.accounts[3].pubkey = other_vault;
+    assert!(context.execute(ix).await.is_err());
+
+    let sweep_vault = |context: &BridgeTestContext, vault: Pubkey| Instruction {
+        program_id: context.program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(payer, true),
+            AccountMeta::new(context.bridge_pda, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new(context.token_config_address(&mint), false),
+            AccountMeta::new(vault, false),
+            AccountMeta::new(treasury_ata, false),
+            AccountMeta::new(treasury, false),
+            instructions_sysvar(),
+        ],
+        data: BridgeInstruction::SweepVault.pack(),
+    };
+    let close_bridge = |context: &BridgeTestContext| Instruction {
+        program_id: context.program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(payer, true),
+            AccountMeta::new(context.bridge_pda, false),
+            AccountMeta::new(native_vault, false),
+            AccountMeta::new(treasury, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(native_config, false),
+            instructions_sysvar(),
+        ],
+        data: BridgeInstruction::CloseBridge.pack(),
+    };
+
+    // Nothing can be swept from a live bridge.
+    let err = context
+        .execute(sweep_vault(&context, vault))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_bridge_error(err, BridgeError::NotDecommissioned);
+
+    let decommission = admin_ix(
+        BridgeInstruction::Decommission,
+        &context,
+        state::DecommissionData { treasury },
     );
+    context.execute(decommission).await.unwrap();
+    let state = context.state().await;
+    assert!(state.paused);
+    assert_eq!(state.token_count, 2);
 
-    banks_client.process_transaction(transaction).await.unwrap();
+    let unpause = admin_ix(
+        BridgeInstruction::SetPaused,
+        &context,
+        state::SetPausedData { paused: false },
+    );
+    let err = context.execute(unpause).await.unwrap_err().unwrap();
+    assert_bridge_error(err, BridgeError::BridgeDecommissioned);
 
-    // Check balance
-    let solana_account = banks_client.get_account(user_ata).await.unwrap().unwrap();
-    let token_account = spl_token::state::Account::unpack(solana_account.data.as_slice()).unwrap();
-    assert_eq!(amount, token_account.amount);
+    // The bridge stays open while a token vault is left.
+    let err = context
+        .execute(close_bridge(&context))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_bridge_error(err, BridgeError::TokensRemaining);
+
+    // Sweeping the donations leaves the token config, and the bridge stays open while the vault
+    // of the config is unswept.
+    let rent = context.lamports(&vault).await
+        + context.lamports(&other_vault).await
+        + context.lamports(&context.token_config_address(&mint)).await
+        + context.lamports(&native_config).await;
+    context
+        .execute(sweep_vault(&context, other_vault))
+        .await
+        .unwrap();
+    context.assert_token_balance(&treasury_ata, 500).await;
+    assert_eq!(context.state().await.token_count, 2);
+    context.refresh_blockhash().await;
+    let err = context
+        .execute(close_bridge(&context))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_bridge_error(err, BridgeError::TokensRemaining);
+    context.execute(sweep_vault(&context, vault)).await.unwrap();
+    context.assert_token_balance(&treasury_ata, 1500).await;
+    assert_eq!(context.state().await.token_count, 1);
+    for account in [vault, other_vault] {
+        assert!(context
+            .banks_client()
+            .get_account(account)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    // Some rent stays with the tombstone of the bridge pda.
+    let bridge_rent = context.lamports(&context.bridge_pda).await;
+    let tombstone_rent = bank_rent.minimum_balance(0);
+    context.execute(close_bridge(&context)).await.unwrap();
+    context
+        .assert_lamports(&treasury, rent + bridge_rent - tombstone_rent + 5_000_000)
+        .await;
+    for account in [native_vault, native_config] {
+        assert!(context
+            .banks_client()
+            .get_account(account)
+            .await
+            .unwrap()
+            .is_none());
+    }
+    let tombstone = context
+        .banks_client()
+        .get_account(context.bridge_pda)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(tombstone.owner, context.program_id);
+    assert!(tombstone.data.is_empty());
+
+    // The sequences of a new bridge would collide with the receipts of the old one.
+    let initialize = Instruction {
+        program_id: context.program_id,
+        accounts: vec![
+            AccountMeta::new(payer, true),
+            AccountMeta::new(context.bridge_pda, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: BridgeInstruction::Initialize.pack(),
+    };
+    assert!(context.execute(initialize).await.is_err());
+}
+
+#[tokio::test]
+async fn test_migrate() {
+    let mut context = BridgeTestContext::new().await;
+    let payer = context.payer().pubkey();
+    let old_spender = Pubkey::new_unique();
+
+    // A bridge created before the account grew, with the admin as one of the spenders.
+    let legacy = state::LegacyBridgeState {
+        version: 0,
+        bump: context.state().await.bump,
+        admin: payer,
+        spenders: [payer, old_spender],
+        spender_index: 1,
+    };
+    let rent = context.banks_client().get_rent().await.unwrap();
+    let mut account = context
+        .banks_client()
+        .get_account(context.bridge_pda)
+        .await
+        .unwrap()
+        .unwrap();
+    account.data = legacy.try_to_vec().unwrap();
+    account.lamports = rent.minimum_balance(account.data.len());
+    assert_eq!(account.data.len(), 99);
+    context
+        .program_context
+        .set_account(&context.bridge_pda, &account.into());
+
+    let migrate = |context: &BridgeTestContext, signer: &Pubkey| Instruction {
+        program_id: context.program_id,
+        accounts: vec![
+            AccountMeta::new(*signer, true),
+            AccountMeta::new(context.bridge_pda, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            instructions_sysvar(),
+        ],
+        data: BridgeInstruction::Migrate.pack(),
+    };
+    let pause = |context: &BridgeTestContext| {
+        admin_ix(
+            BridgeInstruction::SetPaused,
+            context,
+            state::SetPausedData { paused: true },
+        )
+    };
+
+    // Nothing reads the old layout.
+    let err = context.execute(pause(&context)).await.unwrap_err().unwrap();
+    assert_bridge_error(err, BridgeError::MigrationRequired);
+
+    let spender = context.spender.pubkey();
+    let err = context
+        .relay(migrate(&context, &spender))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_bridge_error(err, BridgeError::NotAnAdmin);
+
+    context.execute(migrate(&context, &payer)).await.unwrap();
+    let account = context
+        .banks_client()
+        .get_account(context.bridge_pda)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.data.len(), state::BridgeStateV0::LEN);
+    assert!(account.lamports >= rent.minimum_balance(state::BridgeStateV0::LEN));
+
+    // The admin leaves the spender slots, the other spender stays current.
+    let state = context.state().await;
+    assert_eq!(state.version, state::BridgeStateV0::VERSION);
+    assert_eq!(state.admin, payer);
+    assert_eq!(state.spenders, [Pubkey::default(), old_spender]);
+    assert_eq!(state.spender_keys().current(), &old_spender);
+    assert_eq!(state.guardian, Pubkey::default());
+    assert_eq!(state.out_sequence, 0);
+
+    context.execute(pause(&context)).await.unwrap();
+    assert!(context.state().await.paused);
+
+    context.refresh_blockhash().await;
+    let err = context
+        .execute(migrate(&context, &payer))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_bridge_error(err, BridgeError::AlreadyMigrated);
+}
+
+fn send_message_ix(context: &BridgeTestContext, payload: Vec<u8>) -> Instruction {
+    let data = BridgeInstruction::SendMessage(state::SendMessageData {
+        chain_id: 123,
+        target: "0x8095f5b69F2970f38DC6eBD2682ed71E4939f988".to_string(),
+        payload,
+    })
+    .pack();
+    Instruction {
+        program_id: context.program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(context.payer().pubkey(), true),
+            AccountMeta::new(context.bridge_pda, false),
+        ],
+        data,
+    }
+}
+
+#[tokio::test]
+async fn test_send_message() {
+    let mut context = BridgeTestContext::new().await;
+
+    for payload in [vec![1, 2, 3], vec![0; state::MAX_PAYLOAD_LEN]] {
+        let ix = send_message_ix(&context, payload);
+        context.execute(ix).await.unwrap();
+    }
+
+    let ix = send_message_ix(&context, vec![0; state::MAX_PAYLOAD_LEN + 1]);
+    let err = context.execute(ix).await.unwrap_err().unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(BridgeError::PayloadTooLarge as u32)
+        )
+    );
+
+    // Messages do not use up transfer sequences.
+    let state = context.state().await;
+    assert_eq!(state.message_sequence, 2);
+    assert_eq!(state.out_sequence, 0);
+}
+
+#[tokio::test]
+async fn test_transfer_out_with_payload() {
+    let mut context = BridgeTestContext::new().await;
+    let (mint, bridge_ata) = context.add_token(INIT_AMOUNT).await;
+    let user_ata = context.fund_payer(&mint, 1000).await;
+
+    // Same accounts as a plain transfer out.
+    let with_payload = |context: &BridgeTestContext, sequence, payload| {
+        let mut ix = transfer_out_ix(context, &mint, &user_ata, sequence, 100);
+        let transfer = match BridgeInstruction::unpack(&ix.data).unwrap() {
+            BridgeInstruction::TransferOut(transfer) => transfer,
+            _ => unreachable!(),
+        };
+        ix.data = BridgeInstruction::TransferOutWithPayload(state::TransferOutWithPayloadData {
+            transfer,
+            payload,
+        })
+        .pack();
+        ix
+    };
+
+    let ix = with_payload(&context, 0, b"deposit".to_vec());
+    context.execute(ix).await.unwrap();
+    let ix = with_payload(&context, 1, vec![0; state::MAX_PAYLOAD_LEN + 1]);
+    assert!(context.execute(ix).await.is_err());
+
+    context.assert_token_balance(&user_ata, 900).await;
+    context
+        .assert_token_balance(&bridge_ata, INIT_AMOUNT + 100)
+        .await;
+    assert_eq!(context.receipt(0).await.amount, 100);
+}
+
+// Compute budgets of the hot paths, as the clients estimate them.
+const TRANSFER_OUT_MAX_UNITS: u64 = state::TRANSFER_OUT_UNITS as u64;
+const TRANSFER_IN_BASE_MAX_UNITS: u64 = state::TRANSFER_IN_BASE_UNITS as u64;
+const TRANSFER_IN_ITEM_MAX_UNITS: u64 = state::TRANSFER_IN_ITEM_UNITS as u64;
+
+// Units consumed by `ix`, and the 150 of the compute budget instruction that lifts the limit.
+// A `relayed` instruction is signed by the spender as well.
+async fn units_consumed(context: &mut BridgeTestContext, ix: Instruction, relayed: bool) -> u64 {
+    let signers = if relayed {
+        vec![context.payer(), &context.spender]
+    } else {
+        vec![context.payer()]
+    };
+    let transaction = Transaction::new_signed_with_payer(
+        &[ComputeBudgetInstruction::set_compute_unit_limit(1_400_000), ix],
+        Some(&context.payer().pubkey()),
+        &signers,
+        context.program_context.last_blockhash,
+    );
+    let simulation = context
+        .banks_client()
+        .simulate_transaction(transaction)
+        .await
+        .unwrap();
+    assert_matches!(simulation.result, Some(Ok(())));
+
+    simulation.simulation_details.unwrap().units_consumed
+}
+
+struct TransferUnits {
+    transfer_out: u64,
+    transfer_in: u64,
+    transfer_in_batch: u64,
+}
+
+// Measures a transfer out, and transfers in of one and of 16 items, with the program loaded from
+// `<program_name>.so`. Only BPF is metered, the native processor consumes nothing.
+async fn transfer_units(program_name: &'static str) -> TransferUnits {
+    let mut context = BridgeTestContext::builder()
+        .program_name(program_name)
+        .configure(|program_test| program_test.prefer_bpf(true))
+        .start()
+        .await;
+    let (mint, bridge_ata) = context.add_token(INIT_AMOUNT).await;
+    let user_ata = context.fund_payer(&mint, 1000).await;
+
+    let ix = transfer_out_ix(&context, &mint, &user_ata, 0, 100);
+    let transfer_out = units_consumed(&mut context, ix, false).await;
+
+    let mut receivers = vec![];
+    for _ in 0..16 {
+        let receiver_ata = context
+            .create_token_account(&Keypair::new().pubkey(), &mint)
+            .await;
+        receivers.push(receiver_ata);
+    }
+    let spender = context.spender.pubkey();
+    let (program_id, bridge_pda) = (context.program_id, context.bridge_pda);
+    let token_config = context.token_config_address(&mint);
+    let transfer_in_ix = |n: usize| {
+        let mut accounts = vec![
+            AccountMeta::new_readonly(spender, true),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(bridge_pda, false),
+        ];
+        for receiver_ata in &receivers[..n] {
+            accounts.push(AccountMeta::new(bridge_ata, false));
+            accounts.push(AccountMeta::new(*receiver_ata, false));
+            accounts.push(AccountMeta::new(token_config, false));
+        }
+        accounts.push(instructions_sysvar());
+        Instruction {
+            program_id,
+            accounts,
+            data: BridgeInstruction::TransferIn(state::TransferInData {
+                nonce: 1,
+                amounts: vec![100; n],
+            })
+            .pack(),
+        }
+    };
+
+    TransferUnits {
+        transfer_out,
+        transfer_in: units_consumed(&mut context, transfer_in_ix(1), true).await,
+        transfer_in_batch: units_consumed(&mut context, transfer_in_ix(16), true).await,
+    }
+}
+
+// Compares the program against its `borsh-state` build, which decodes the whole bridge state
+// like the transfer paths did before reading it in place.
+#[tokio::test]
+#[ignore = "needs sisu_bridge.so and sisu_bridge_borsh.so, run `make test`"]
+async fn test_compute_units() {
+    let zero_copy = transfer_units("sisu_bridge").await;
+    let borsh = transfer_units("sisu_bridge_borsh").await;
+
+    assert!(zero_copy.transfer_out <= TRANSFER_OUT_MAX_UNITS);
+    assert!(zero_copy.transfer_in <= TRANSFER_IN_BASE_MAX_UNITS + TRANSFER_IN_ITEM_MAX_UNITS);
+    // The fixed cost is paid once, each further item only adds its token transfer.
+    assert!(
+        zero_copy.transfer_in_batch.saturating_sub(zero_copy.transfer_in)
+            <= 15 * TRANSFER_IN_ITEM_MAX_UNITS
+    );
+
+    assert!(zero_copy.transfer_out < borsh.transfer_out);
+    assert!(zero_copy.transfer_in < borsh.transfer_in);
+    assert!(zero_copy.transfer_in_batch < borsh.transfer_in_batch);
 }
@@ -0,0 +1,231 @@
+#![cfg(feature = "test-bpf")]
+
+use {
+    sisu_bridge::{
+        cpi,
+        error::BridgeError,
+        state::{
+            BridgeInstruction, ReceiptStatus, SetCpiCallerData, SetPausedData, TransferOutData,
+        },
+        test_utils::BridgeTestContext,
+    },
+    solana_program::{
+        account_info::{next_account_info, AccountInfo},
+        entrypoint::ProgramResult,
+        instruction::{AccountMeta, Instruction, InstructionError},
+        program::invoke,
+        pubkey::Pubkey,
+        system_program, sysvar,
+    },
+    solana_program_test::*,
+    solana_sdk::{signature::Signer, transaction::TransactionError},
+};
+
+const DEPOSITOR_SEED: &[u8] = b"depositor";
+
+// A program that keeps tokens under its own pda and bridges them out. The data is the amount.
+fn caller_process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let payer = next_account_info(accounts_iter)?;
+    let depositor = next_account_info(accounts_iter)?;
+    let bridge_program = next_account_info(accounts_iter)?;
+    let token_program = next_account_info(accounts_iter)?;
+    let depositor_token = next_account_info(accounts_iter)?;
+    let bridge_token = next_account_info(accounts_iter)?;
+    let bridge_pda = next_account_info(accounts_iter)?;
+    let receipt = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let token_config = next_account_info(accounts_iter)?;
+
+    let (_, bump) = Pubkey::find_program_address(&[DEPOSITOR_SEED], program_id);
+    let amount = u64::from_le_bytes(instruction_data.try_into().unwrap());
+
+    cpi::transfer_out(
+        &cpi::TransferOut {
+            bridge_program: bridge_program.clone(),
+            user: depositor.clone(),
+            token_program: token_program.clone(),
+            user_token: depositor_token.clone(),
+            bridge_token: bridge_token.clone(),
+            bridge_pda: bridge_pda.clone(),
+            receipt: receipt.clone(),
+            system_program: system_program.clone(),
+            token_config: token_config.clone(),
+            payer: Some(payer.clone()),
+        },
+        TransferOutData {
+            amount,
+            token_address: "0x1234".to_string(),
+            chain_id: 123,
+            recipient: "someone".to_string(),
+        },
+        &[&[DEPOSITOR_SEED, &[bump]]],
+    )?;
+    assert_eq!(cpi::transfer_out_sequence(bridge_program.key), Some(0));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_transfer_out_from_program() {
+    let caller_program_id = Pubkey::new_unique();
+    let mut context = BridgeTestContext::builder()
+        .configure(|program_test| {
+            program_test.add_program(
+                "cpi_caller",
+                caller_program_id,
+                processor!(caller_process_instruction),
+            )
+        })
+        .start()
+        .await;
+    let (mint, bridge_ata) = context.add_token(0).await;
+
+    // The caller's pda holds the tokens but no lamports.
+    let (depositor, _) = Pubkey::find_program_address(&[DEPOSITOR_SEED], &caller_program_id);
+    let depositor_ata = context.create_token_account(&depositor, &mint).await;
+    context.mint_to(&mint, &depositor_ata, 1000).await;
+
+    context
+        .execute(Instruction {
+            program_id: caller_program_id,
+            accounts: vec![
+                AccountMeta::new(context.payer().pubkey(), true),
+                AccountMeta::new(depositor, false),
+                AccountMeta::new_readonly(context.program_id, false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+                AccountMeta::new(depositor_ata, false),
+                AccountMeta::new(bridge_ata, false),
+                AccountMeta::new(context.bridge_pda, false),
+                AccountMeta::new(context.receipt_address(0), false),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new(context.token_config_address(&mint), false),
+            ],
+            data: 600u64.to_le_bytes().to_vec(),
+        })
+        .await
+        .unwrap();
+
+    let receipt = context.receipt(0).await;
+    assert_eq!(receipt.sender, depositor);
+    assert_eq!(receipt.source, depositor_ata);
+    assert_eq!(receipt.amount, 600);
+    assert_eq!(receipt.status, ReceiptStatus::Pending);
+
+    context.assert_token_balance(&depositor_ata, 400).await;
+    context.assert_token_balance(&bridge_ata, 600).await;
+    assert_eq!(context.state().await.out_sequence, 1);
+}
+
+// A program that passes its instruction on to the bridge, the first account, with the
+// remaining accounts. Signers of the transaction stay signers in the bridge.
+fn forwarder_process_instruction(
+    _program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let (bridge_program, accounts) = accounts.split_first().unwrap();
+    let instruction = Instruction {
+        program_id: *bridge_program.key,
+        accounts: accounts
+            .iter()
+            .map(|account| AccountMeta {
+                pubkey: *account.key,
+                is_signer: account.is_signer,
+                is_writable: account.is_writable,
+            })
+            .collect(),
+        data: instruction_data.to_vec(),
+    };
+    invoke(&instruction, accounts)
+}
+
+#[tokio::test]
+async fn test_untrusted_caller() {
+    let forwarder_program_id = Pubkey::new_unique();
+    let mut context = BridgeTestContext::builder()
+        .configure(|program_test| {
+            program_test.add_program(
+                "forwarder",
+                forwarder_program_id,
+                processor!(forwarder_process_instruction),
+            )
+        })
+        .start()
+        .await;
+    let payer = context.payer().pubkey();
+
+    let admin_ix = |context: &BridgeTestContext, program_id, instruction: BridgeInstruction| {
+        let mut accounts = vec![
+            AccountMeta::new_readonly(payer, true),
+            AccountMeta::new(context.bridge_pda, false),
+            AccountMeta::new_readonly(sysvar::instructions::id(), false),
+        ];
+        if program_id == forwarder_program_id {
+            accounts.insert(0, AccountMeta::new_readonly(context.program_id, false));
+        }
+        Instruction {
+            program_id,
+            accounts,
+            data: instruction.pack(),
+        }
+    };
+    let pause = BridgeInstruction::SetPaused(SetPausedData { paused: true });
+
+    // The admin's signature does not carry over to a program the admin did not allow.
+    let err = context
+        .execute(admin_ix(&context, forwarder_program_id, pause.clone()))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(BridgeError::UntrustedCaller as u32)
+        )
+    );
+    assert!(!context.state().await.paused);
+
+    // Guarded instructions cannot leave out the instructions sysvar either.
+    let mut ix = admin_ix(&context, context.program_id, pause.clone());
+    ix.accounts.pop();
+    let err = context.execute(ix).await.unwrap_err().unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(0, InstructionError::NotEnoughAccountKeys)
+    );
+
+    let allow = BridgeInstruction::SetCpiCaller(SetCpiCallerData {
+        program: forwarder_program_id,
+        allowed: true,
+    });
+    let ix = admin_ix(&context, context.program_id, allow);
+    context.execute(ix).await.unwrap();
+    assert_eq!(context.state().await.cpi_callers[0], forwarder_program_id);
+
+    // An allowed program only vouches for its own calls, not for a call it forwards through
+    // another program.
+    let mut ix = admin_ix(&context, forwarder_program_id, pause.clone());
+    ix.accounts.insert(0, AccountMeta::new_readonly(forwarder_program_id, false));
+    let err = context.execute(ix).await.unwrap_err().unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(BridgeError::UntrustedCaller as u32)
+        )
+    );
+    assert!(!context.state().await.paused);
+
+    context.refresh_blockhash().await;
+    context
+        .execute(admin_ix(&context, forwarder_program_id, pause))
+        .await
+        .unwrap();
+    assert!(context.state().await.paused);
+}
@@ -0,0 +1,259 @@
+#![cfg(feature = "idl")]
+
+// Decodes the golden vectors with nothing but `idl.json`, the way a generated client would,
+// and checks the result against their JSON values.
+
+use std::{fs, path::PathBuf};
+
+use serde_json::{json, Value};
+
+fn load(file: &str) -> Value {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(file);
+    serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+}
+
+fn from_hex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+struct Decoder<'a> {
+    idl: &'a Value,
+    data: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, len: usize) -> &'a [u8] {
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        bytes
+    }
+
+    fn int(&mut self, len: usize) -> u64 {
+        let mut bytes = [0; 8];
+        bytes[..len].copy_from_slice(self.take(len));
+        u64::from_le_bytes(bytes)
+    }
+
+    fn len(&mut self) -> usize {
+        self.int(4) as usize
+    }
+
+    // Numbers are decoded to strings, see `normalize`.
+    fn value(&mut self, ty: &Value) -> Value {
+        if let Some(primitive) = ty.as_str() {
+            return match primitive {
+                "bool" => json!(self.int(1) != 0),
+                "u8" => json!(self.int(1).to_string()),
+                "u32" => json!(self.int(4).to_string()),
+                "u64" => json!(self.int(8).to_string()),
+                "i64" => json!((self.int(8) as i64).to_string()),
+                "string" => {
+                    let len = self.len();
+                    json!(std::str::from_utf8(self.take(len)).unwrap())
+                }
+                "bytes" => {
+                    let len = self.len();
+                    json!(format!("0x{}", to_hex(self.take(len))))
+                }
+                "pubkey" => json!(bs58::encode(self.take(32)).into_string()),
+                _ => panic!("unknown type {}", primitive),
+            };
+        }
+        if let Some(item) = ty.get("vec") {
+            let len = self.len();
+            return (0..len).map(|_| self.value(item)).collect();
+        }
+        if let Some(array) = ty.get("array") {
+            let len = array[1].as_u64().unwrap();
+            return (0..len).map(|_| self.value(&array[0])).collect();
+        }
+        let name = ty["defined"]["name"].as_str().unwrap();
+        self.defined(name)
+    }
+
+    fn defined(&mut self, name: &str) -> Value {
+        let idl = self.idl;
+        let def = idl["types"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|def| def["name"] == name)
+            .unwrap_or_else(|| panic!("undefined type {}", name));
+
+        if def["serialization"]["custom"] == "leb128" {
+            let mut value = 0u64;
+            for i in 0.. {
+                let byte = self.take(1)[0];
+                value |= ((byte & 0x7f) as u64) << (7 * i);
+                if byte & 0x80 == 0 {
+                    break;
+                }
+            }
+            return json!(value.to_string());
+        }
+
+        let ty = &def["type"];
+        match ty["kind"].as_str().unwrap() {
+            "struct" => self.fields(&ty["fields"]),
+            "enum" => {
+                let variants = ty["variants"].as_array().unwrap();
+                let variant = &variants[self.int(1) as usize];
+                let name = variant["name"].clone();
+                // Enums without data are plain strings, the others carry a type and data.
+                if variants
+                    .iter()
+                    .all(|variant| variant.get("fields").is_none())
+                {
+                    return name;
+                }
+                match variant.get("fields") {
+                    Some(fields) => json!({"type": name, "data": self.value(&fields[0])}),
+                    None => json!({ "type": name }),
+                }
+            }
+            kind => panic!("unexpected kind {}", kind),
+        }
+    }
+
+    fn fields(&mut self, fields: &Value) -> Value {
+        let mut object = serde_json::Map::new();
+        for field in fields.as_array().unwrap() {
+            let value = self.value(&field["type"]);
+            object.insert(field["name"].as_str().unwrap().to_string(), value);
+        }
+        Value::Object(object)
+    }
+
+    fn finish(self, value: Value) -> Value {
+        assert!(self.data.is_empty(), "trailing bytes");
+        value
+    }
+}
+
+// The JSON schema writes amounts as strings and the other numbers as numbers, the IDL does not
+// tell them apart.
+fn normalize(value: &Value) -> Value {
+    match value {
+        Value::Number(number) => json!(number.to_string()),
+        Value::Array(items) => items.iter().map(normalize).collect(),
+        Value::Object(object) => Value::Object(
+            object
+                .iter()
+                .map(|(key, value)| (key.clone(), normalize(value)))
+                .collect(),
+        ),
+        value => value.clone(),
+    }
+}
+
+fn vectors(file: &str) -> Vec<(String, Vec<u8>, Value)> {
+    load(&format!("tests/vectors/{}", file))
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|vector| {
+            (
+                vector["name"].as_str().unwrap().to_string(),
+                from_hex(vector["hex"].as_str().unwrap()),
+                normalize(&vector["value"]),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_idl_decodes_instructions() {
+    let idl = load("idl.json");
+    for (name, bytes, value) in vectors("instructions.json") {
+        let instruction = idl["instructions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|ix| ix["discriminator"] == json!([bytes[0]]))
+            .unwrap();
+        let mut decoder = Decoder {
+            idl: &idl,
+            data: &bytes[1..],
+        };
+        let args = decoder.fields(&instruction["args"]);
+        let args = decoder.finish(args);
+
+        // Payload structs are spread over the args, `query` is the only enum payload.
+        let data = value.get("data").cloned().unwrap_or_else(|| json!({}));
+        let expected = match args.get("query") {
+            Some(_) => json!({ "query": data }),
+            None => data,
+        };
+        assert_eq!(args, expected, "{}", name);
+    }
+}
+
+#[test]
+fn test_idl_decodes_events() {
+    let idl = load("idl.json");
+    for (name, bytes, value) in vectors("events.json") {
+        let event = idl["events"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|event| event["discriminator"] == json!([bytes[0]]))
+            .unwrap();
+        assert_eq!(event["name"], value["type"]);
+
+        let mut decoder = Decoder {
+            idl: &idl,
+            data: &bytes[1..],
+        };
+        let fields = decoder.defined(event["name"].as_str().unwrap());
+        assert_eq!(decoder.finish(fields), value["data"], "{}", name);
+    }
+}
+
+#[test]
+fn test_idl_decodes_accounts() {
+    let idl = load("idl.json");
+    for (name, bytes, value) in vectors("accounts.json") {
+        assert!(idl["accounts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|account| account["name"] == name));
+
+        let mut decoder = Decoder {
+            idl: &idl,
+            data: &bytes,
+        };
+        let account = decoder.defined(&name);
+        assert_eq!(decoder.finish(account), value, "{}", name);
+    }
+}
+
+#[test]
+fn test_idl_decodes_return_data() {
+    let idl = load("idl.json");
+    for (name, bytes, value) in vectors("return_data.json") {
+        // The version byte comes first.
+        let mut decoder = Decoder {
+            idl: &idl,
+            data: &bytes[1..],
+        };
+        let data = decoder.defined("BridgeReturnData");
+        assert_eq!(decoder.finish(data), value, "{}", name);
+    }
+
+    for (name, bytes, value) in vectors("admin_operations.json") {
+        let mut decoder = Decoder {
+            idl: &idl,
+            data: &bytes,
+        };
+        let operation = decoder.defined("AdminOperation");
+        assert_eq!(decoder.finish(operation), value, "{}", name);
+    }
+}
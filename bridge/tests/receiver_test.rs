@@ -0,0 +1,206 @@
+#![cfg(feature = "test-bpf")]
+
+use {
+    sisu_bridge::{
+        error::BridgeError,
+        receiver::{self, ReceiveMessage},
+        state::{self, BridgeInstruction, DeliverMessageData},
+        test_utils::BridgeTestContext,
+    },
+    solana_program::{
+        account_info::{next_account_info, AccountInfo},
+        entrypoint::ProgramResult,
+        instruction::{AccountMeta, Instruction, InstructionError},
+        program_error::ProgramError,
+        pubkey::Pubkey,
+        system_instruction, system_program, sysvar,
+    },
+    solana_program_test::*,
+    solana_sdk::{
+        program_pack::Pack,
+        signature::{Keypair, Signer},
+        transaction::TransactionError,
+    },
+    spl_token::state::Account as SplTokenAccount,
+};
+
+const BRIDGE_PROGRAM_ID: Pubkey = Pubkey::new_from_array([7; 32]);
+const INBOX_LEN: usize = 1 + 8 + 32;
+
+// A receiver that counts deliveries in its inbox account and keeps the last nonce and payload.
+// A "fail" payload makes it fail.
+fn receiver_process_instruction(
+    _program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let message =
+        ReceiveMessage::unpack(instruction_data).ok_or(ProgramError::InvalidInstructionData)?;
+    let accounts_iter = &mut accounts.iter();
+    let authority = next_account_info(accounts_iter)?;
+    receiver::check_message_authority(&BRIDGE_PROGRAM_ID, authority)?;
+
+    if message.amount > 0 {
+        let token_account = next_account_info(accounts_iter)?;
+        let token = SplTokenAccount::unpack(&token_account.data.borrow())?;
+        assert!(token.amount >= message.amount);
+    }
+    if message.payload == b"fail" {
+        return Err(ProgramError::Custom(42));
+    }
+
+    let inbox = next_account_info(accounts_iter)?;
+    let mut data = inbox.data.borrow_mut();
+    data[0] += 1;
+    data[1..9].copy_from_slice(&message.nonce.to_le_bytes());
+    data[9..9 + message.payload.len()].copy_from_slice(&message.payload);
+    Ok(())
+}
+
+fn delivery_record(source_chain_id: u64, nonce: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            state::DELIVERY_SEED,
+            &source_chain_id.to_le_bytes(),
+            &nonce.to_le_bytes(),
+        ],
+        &BRIDGE_PROGRAM_ID,
+    )
+    .0
+}
+
+#[tokio::test]
+async fn test_deliver_message() {
+    let receiver_program_id = Pubkey::new_unique();
+    let mut context = BridgeTestContext::builder()
+        .program_id(BRIDGE_PROGRAM_ID)
+        .configure(|program_test| {
+            program_test.add_program(
+                "message_receiver",
+                receiver_program_id,
+                processor!(receiver_process_instruction),
+            )
+        })
+        .start()
+        .await;
+
+    // Locked tokens, and a token account of the receiver.
+    let (mint, bridge_ata) = context.add_token(1000).await;
+    let receiver_vault = Pubkey::find_program_address(&[b"vault"], &receiver_program_id).0;
+    let receiver_ata = context.create_token_account(&receiver_vault, &mint).await;
+
+    let inbox = Keypair::new();
+    let payer = context.payer().pubkey();
+    let rent = context.banks_client().get_rent().await.unwrap();
+    context
+        .process(
+            &[system_instruction::create_account(
+                &payer,
+                &inbox.pubkey(),
+                rent.minimum_balance(INBOX_LEN),
+                INBOX_LEN as u64,
+                &receiver_program_id,
+            )],
+            &[&inbox],
+        )
+        .await
+        .unwrap();
+
+    let bridge_pda = context.bridge_pda;
+    let token_config = context.token_config_address(&mint);
+    let spender = context.spender.pubkey();
+    let deliver = |nonce: u64, payload: &[u8], amount: u64| {
+        let mut accounts = vec![
+            AccountMeta::new(spender, true),
+            AccountMeta::new_readonly(bridge_pda, false),
+            AccountMeta::new(delivery_record(189985, nonce), false),
+            AccountMeta::new_readonly(receiver::message_authority(&BRIDGE_PROGRAM_ID).0, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(receiver_program_id, false),
+        ];
+        if amount > 0 {
+            accounts.push(AccountMeta::new_readonly(spl_token::id(), false));
+            accounts.push(AccountMeta::new(bridge_ata, false));
+            accounts.push(AccountMeta::new(receiver_ata, false));
+            accounts.push(AccountMeta::new(token_config, false));
+        }
+        accounts.push(AccountMeta::new(inbox.pubkey(), false));
+        accounts.push(AccountMeta::new_readonly(sysvar::instructions::id(), false));
+        Instruction {
+            program_id: BRIDGE_PROGRAM_ID,
+            accounts,
+            data: BridgeInstruction::DeliverMessage(DeliverMessageData {
+                source_chain_id: 189985,
+                nonce,
+                sender: "0x8095f5b69F2970f38DC6eBD2682ed71E4939f988".to_string(),
+                payload: payload.to_vec(),
+                amount,
+            })
+            .pack(),
+        }
+    };
+
+    context.relay(deliver(1, b"mint", 300)).await.unwrap();
+
+    // The same nonce again, even with another payload, is not executed.
+    let err = context
+        .relay(deliver(1, b"again", 0))
+        .await
+        .unwrap_err()
+        .unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(BridgeError::MessageAlreadyDelivered as u32)
+        )
+    );
+
+    // A failing receiver leaves no record behind, so the message can be delivered later.
+    assert!(context.relay(deliver(2, b"fail", 100)).await.is_err());
+    assert!(context
+        .banks_client()
+        .get_account(delivery_record(189985, 2))
+        .await
+        .unwrap()
+        .is_none());
+
+    let account = context
+        .banks_client()
+        .get_account(inbox.pubkey())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.data[0], 1);
+    assert_eq!(account.data[1..9], 1u64.to_le_bytes());
+    assert_eq!(&account.data[9..13], b"mint");
+    context.assert_token_balance(&receiver_ata, 300).await;
+
+    // The instructions sysvar goes last, never to the receiver.
+    let mut ix = deliver(3, b"mint", 0);
+    let last = ix.accounts.len() - 1;
+    ix.accounts.swap(last - 1, last);
+    let err = context.relay(ix).await.unwrap_err().unwrap();
+    assert_eq!(
+        err,
+        TransactionError::InstructionError(0, InstructionError::InvalidArgument)
+    );
+
+    // Only the bridge can call the receiver.
+    let forged = Instruction {
+        program_id: receiver_program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(payer, true),
+            AccountMeta::new(inbox.pubkey(), false),
+        ],
+        data: ReceiveMessage {
+            source_chain_id: 189985,
+            nonce: 3,
+            sender: "someone".to_string(),
+            payload: vec![],
+            amount: 0,
+        }
+        .pack(),
+    };
+    assert!(context.execute(forged).await.is_err());
+}
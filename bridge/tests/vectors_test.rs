@@ -0,0 +1,562 @@
+// Golden wire format vectors, checked in under `tests/vectors` so that the Go scanner and the
+// TypeScript types can be checked against the same bytes. Each file lists one sample per
+// variant with its encoding in hex and, in the JSON schema of the `serde` feature, its value.
+//
+// Any change to the encoding fails this test. If the change is intended, regenerate the files
+// with `UPDATE_VECTORS=1 cargo test --features serde --test vectors_test` and update the
+// other implementations.
+
+use std::{env, fs, path::PathBuf};
+
+use borsh::BorshSerialize;
+use serde_json::{json, Value};
+use sisu_bridge::state::*;
+use solana_program::pubkey::Pubkey;
+
+const K1: Pubkey = Pubkey::new_from_array([1; 32]);
+const K2: Pubkey = Pubkey::new_from_array([2; 32]);
+const K3: Pubkey = Pubkey::new_from_array([3; 32]);
+const RECIPIENT: &str = "0x8095f5b69F2970f38DC6eBD2682ed71E4939f988";
+
+struct Vector {
+    name: &'static str,
+    bytes: Vec<u8>,
+    value: Option<Value>,
+}
+
+#[cfg(feature = "serde")]
+fn to_json<T: serde::Serialize>(value: &T) -> Option<Value> {
+    Some(serde_json::to_value(value).unwrap())
+}
+
+#[cfg(not(feature = "serde"))]
+fn to_json<T>(_: &T) -> Option<Value> {
+    None
+}
+
+fn vector<T: BorshSerialize>(name: &'static str, value: T) -> Vector {
+    Vector {
+        name,
+        bytes: value.try_to_vec().unwrap(),
+        value: to_json(&value),
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn transfer_out() -> TransferOutData {
+    TransferOutData {
+        amount: 900,
+        token_address: "0x1234".to_string(),
+        chain_id: 189985,
+        recipient: RECIPIENT.to_string(),
+    }
+}
+
+fn transfer_out_event() -> TransferOutEvent {
+    TransferOutEvent {
+        sequence: 5,
+        sender: K1,
+        mint: K2,
+        token_address: "0x1234".to_string(),
+        amount: 900,
+        chain_id: 189985,
+        recipient: RECIPIENT.to_string(),
+    }
+}
+
+fn instructions() -> Vec<Vector> {
+    use BridgeInstruction::*;
+
+    vec![
+        vector("Initialize", Initialize),
+        vector("TransferOut", TransferOut(transfer_out())),
+        vector(
+            "TransferIn",
+            TransferIn(TransferInData {
+                nonce: 7,
+                amounts: vec![100, u64::MAX],
+            }),
+        ),
+        vector("AddSpender", AddSpender(AddSpenderData { spender: K1 })),
+        vector(
+            "RemoveSpender",
+            RemoveSpender(AddSpenderData { spender: K1 }),
+        ),
+        vector(
+            "ChangeAdmin",
+            ChangeAdmin(ChangeAdminData { new_admin: K2 }),
+        ),
+        vector(
+            "TransferOutNative",
+            TransferOutNative(TransferOutNativeData {
+                amount: 2_000_000_000,
+                chain_id: 97,
+                recipient: RECIPIENT.to_string(),
+            }),
+        ),
+        vector(
+            "TransferInNative",
+            TransferInNative(TransferInData {
+                nonce: 8,
+                amounts: vec![5000],
+            }),
+        ),
+        vector(
+            "TransferOutBatch",
+            TransferOutBatch(TransferOutBatchData {
+                items: vec![transfer_out()],
+            }),
+        ),
+        vector(
+            "TransferInCompact",
+            TransferInCompact(TransferInCompactData {
+                nonce: 9,
+                items: vec![CompactTransferItem {
+                    vault_index: 0,
+                    receiver_index: 1,
+                    config_index: 2,
+                    amount: VarU64(300),
+                }],
+            }),
+        ),
+        vector("Refund", Refund(ReceiptData { sequence: 5 })),
+        vector("MarkDelivered", MarkDelivered(ReceiptData { sequence: 5 })),
+        vector("SetPaused", SetPaused(SetPausedData { paused: true })),
+        vector(
+            "RegisterToken",
+            RegisterToken(RegisterTokenData { enabled: true }),
+        ),
+        vector(
+            "SendMessage",
+            SendMessage(SendMessageData {
+                chain_id: 1,
+                target: "0xabcd".to_string(),
+                payload: vec![0xde, 0xad, 0xbe, 0xef],
+            }),
+        ),
+        vector(
+            "TransferOutWithPayload",
+            TransferOutWithPayload(TransferOutWithPayloadData {
+                transfer: transfer_out(),
+                payload: vec![1, 2, 3],
+            }),
+        ),
+        vector(
+            "DeliverMessage",
+            DeliverMessage(DeliverMessageData {
+                source_chain_id: 1,
+                nonce: 3,
+                sender: "0xabcd".to_string(),
+                payload: vec![0xca, 0xfe],
+                amount: 250,
+            }),
+        ),
+        vector(
+            "ActivateSpender",
+            ActivateSpender(ActivateSpenderData {
+                spender: K1,
+                overlap: SpenderOverlap::Seconds(3600),
+            }),
+        ),
+        vector(
+            "SetTimelock",
+            SetTimelock(SetTimelockData {
+                guardian: K2,
+                delay: 600,
+            }),
+        ),
+        vector(
+            "QueueOperation",
+            QueueOperation(QueueOperationData {
+                id: 4,
+                operation: AdminOperation::ChangeAdmin(ChangeAdminData { new_admin: K3 }),
+            }),
+        ),
+        vector(
+            "ExecuteOperation",
+            ExecuteOperation(OperationData { id: 4 }),
+        ),
+        vector("CancelOperation", CancelOperation(OperationData { id: 4 })),
+        vector(
+            "Decommission",
+            Decommission(DecommissionData { treasury: K3 }),
+        ),
+        vector("SweepVault", SweepVault),
+        vector("CloseBridge", CloseBridge),
+        vector("Reconcile", Reconcile),
+        vector("Query.Config", Query(BridgeQuery::Config)),
+        vector("Query.Spenders", Query(BridgeQuery::Spenders)),
+        vector("Query.Token", Query(BridgeQuery::Token)),
+        vector(
+            "SetCpiCaller",
+            SetCpiCaller(SetCpiCallerData {
+                program: K3,
+                allowed: true,
+            }),
+        ),
+        vector(
+            "SetRole",
+            SetRole(SetRoleData {
+                holder: K3,
+                role: Role::TokenRegistrar,
+                granted: true,
+            }),
+        ),
+        vector("Migrate", Migrate),
+    ]
+}
+
+fn admin_operations() -> Vec<Vector> {
+    use AdminOperation::*;
+
+    vec![
+        vector(
+            "ActivateSpender",
+            ActivateSpender(ActivateSpenderData {
+                spender: K1,
+                overlap: SpenderOverlap::Slots(150),
+            }),
+        ),
+        vector(
+            "RemoveSpender",
+            RemoveSpender(AddSpenderData { spender: K1 }),
+        ),
+        vector(
+            "ChangeAdmin",
+            ChangeAdmin(ChangeAdminData { new_admin: K3 }),
+        ),
+        vector(
+            "SetTimelock",
+            SetTimelock(SetTimelockData {
+                guardian: K2,
+                delay: 600,
+            }),
+        ),
+        vector(
+            "Decommission",
+            Decommission(DecommissionData { treasury: K3 }),
+        ),
+        vector(
+            "SetCpiCaller",
+            SetCpiCaller(SetCpiCallerData {
+                program: K3,
+                allowed: false,
+            }),
+        ),
+        vector(
+            "SetRole",
+            SetRole(SetRoleData {
+                holder: K3,
+                role: Role::TokenRegistrar,
+                granted: false,
+            }),
+        ),
+    ]
+}
+
+fn events() -> Vec<Vector> {
+    use BridgeEvent::*;
+
+    vec![
+        vector("TransferOut", TransferOut(transfer_out_event())),
+        vector(
+            "Refunded",
+            Refunded(RefundedEvent {
+                sequence: 5,
+                destination: K1,
+                mint: K2,
+                amount: 900,
+            }),
+        ),
+        vector("Delivered", Delivered(DeliveredEvent { sequence: 5 })),
+        vector(
+            "MessageSent",
+            MessageSent(MessageEvent {
+                sequence: 2,
+                sender: K1,
+                chain_id: 1,
+                target: "0xabcd".to_string(),
+                payload: vec![0xde, 0xad, 0xbe, 0xef],
+            }),
+        ),
+        vector(
+            "TransferOutWithPayload",
+            TransferOutWithPayload(TransferOutWithPayloadEvent {
+                transfer: transfer_out_event(),
+                payload: vec![1, 2, 3],
+            }),
+        ),
+        vector(
+            "MessageDelivered",
+            MessageDelivered(MessageDeliveredEvent {
+                source_chain_id: 1,
+                nonce: 3,
+                receiver_program: K3,
+                amount: 250,
+            }),
+        ),
+        vector(
+            "SpenderActivated",
+            SpenderActivated(SpenderActivatedEvent {
+                epoch: 2,
+                spender: K1,
+                previous: K2,
+                previous_valid_until_slot: 1150,
+                previous_valid_until: 1_700_000_000,
+            }),
+        ),
+        vector(
+            "OperationQueued",
+            OperationQueued(OperationQueuedEvent {
+                id: 4,
+                eta: 1_700_000_600,
+                operation: AdminOperation::ChangeAdmin(ChangeAdminData { new_admin: K3 }),
+            }),
+        ),
+        vector(
+            "OperationExecuted",
+            OperationExecuted(OperationEvent { id: 4 }),
+        ),
+        vector(
+            "OperationCancelled",
+            OperationCancelled(OperationEvent { id: 4 }),
+        ),
+        vector(
+            "VaultSwept",
+            VaultSwept(VaultSweptEvent {
+                mint: NATIVE_SOL_MINT,
+                amount: 5000,
+                treasury: K3,
+            }),
+        ),
+        vector(
+            "Reconciled",
+            Reconciled(ReconciledEvent {
+                mint: K2,
+                locked: 1000,
+                released: 400,
+                balance: 650,
+                surplus: 50,
+                shortfall: 0,
+            }),
+        ),
+    ]
+}
+
+fn accounts() -> Vec<Vector> {
+    vec![
+        vector(
+            "BridgeStateV0",
+            BridgeStateV0 {
+                version: BridgeStateV0::VERSION,
+                bump: 254,
+                admin: K1,
+                spenders: [K1, K2],
+                spender_index: 1,
+                out_sequence: 6,
+                paused: false,
+                message_sequence: 2,
+                spender_epoch: 2,
+                previous_valid_until_slot: 1150,
+                previous_valid_until: 1_700_000_000,
+                guardian: K2,
+                timelock_delay: 600,
+                treasury: Pubkey::default(),
+                token_count: 1,
+                cpi_callers: [K3, Pubkey::default(), Pubkey::default(), Pubkey::default()],
+                role_holders: [
+                    K3,
+                    Pubkey::default(),
+                    Pubkey::default(),
+                    Pubkey::default(),
+                    Pubkey::default(),
+                    Pubkey::default(),
+                    Pubkey::default(),
+                    Pubkey::default(),
+                ],
+                role_masks: [Role::TokenRegistrar.bit(), 0, 0, 0, 0, 0, 0, 0],
+            },
+        ),
+        vector(
+            "TokenConfig",
+            TokenConfig {
+                mint: K2,
+                enabled: true,
+                bump: 253,
+                locked: 1000,
+                released: 400,
+            },
+        ),
+        vector(
+            "TransferReceipt",
+            TransferReceipt {
+                sequence: 5,
+                sender: K1,
+                source: K3,
+                mint: K2,
+                amount: 900,
+                status: ReceiptStatus::Pending,
+                bump: 252,
+            },
+        ),
+        vector(
+            "DeliveryRecord",
+            DeliveryRecord {
+                source_chain_id: 1,
+                nonce: 3,
+                receiver_program: K3,
+                bump: 251,
+            },
+        ),
+        vector(
+            "PendingOperation",
+            PendingOperation {
+                id: 4,
+                eta: 1_700_000_600,
+                bump: 250,
+                operation: AdminOperation::ActivateSpender(ActivateSpenderData {
+                    spender: K1,
+                    overlap: SpenderOverlap::Slots(150),
+                }),
+            },
+        ),
+    ]
+}
+
+// Return data is written with its version byte in front.
+fn packed(name: &'static str, data: BridgeReturnData) -> Vector {
+    Vector {
+        bytes: data.pack(),
+        ..vector(name, data)
+    }
+}
+
+fn return_data() -> Vec<Vector> {
+    vec![
+        packed("Sequence", BridgeReturnData::Sequence(5)),
+        packed(
+            "Config",
+            BridgeReturnData::Config(ConfigInfo {
+                admin: K1,
+                guardian: K2,
+                paused: false,
+                treasury: Pubkey::default(),
+                timelock_delay: 600,
+                token_count: 1,
+                out_sequence: 6,
+                message_sequence: 2,
+            }),
+        ),
+        packed(
+            "Spenders",
+            BridgeReturnData::Spenders(SpendersInfo {
+                epoch: 2,
+                current: K1,
+                previous: K2,
+                previous_valid_until_slot: 1150,
+                previous_valid_until: 1_700_000_000,
+                previous_active: true,
+            }),
+        ),
+        packed(
+            "Token",
+            BridgeReturnData::Token(TokenInfo {
+                mint: K2,
+                enabled: true,
+                locked: 1000,
+                released: 400,
+            }),
+        ),
+    ]
+}
+
+fn check_vectors(file: &str, vectors: Vec<Vector>) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/vectors")
+        .join(file);
+
+    if env::var_os("UPDATE_VECTORS").is_some() {
+        let entries: Vec<Value> = vectors
+            .iter()
+            .map(|vector| {
+                json!({
+                    "name": vector.name,
+                    "hex": to_hex(&vector.bytes),
+                    "value": vector
+                        .value
+                        .clone()
+                        .expect("run with --features serde to update the vectors"),
+                })
+            })
+            .collect();
+        let text = serde_json::to_string_pretty(&entries).unwrap();
+        fs::write(&path, text + "\n").unwrap();
+        return;
+    }
+
+    let text = fs::read_to_string(&path).unwrap();
+    let entries: Vec<Value> = serde_json::from_str(&text).unwrap();
+    let names: Vec<&str> = entries
+        .iter()
+        .map(|entry| entry["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        names,
+        vectors.iter().map(|vector| vector.name).collect::<Vec<_>>(),
+        "{}: the samples changed",
+        file
+    );
+
+    for (entry, vector) in entries.iter().zip(&vectors) {
+        assert_eq!(
+            entry["hex"].as_str().unwrap(),
+            to_hex(&vector.bytes),
+            "{}: encoding of {} changed",
+            file,
+            vector.name
+        );
+        if let Some(value) = &vector.value {
+            assert_eq!(
+                &entry["value"], value,
+                "{}: JSON of {} changed",
+                file, vector.name
+            );
+        }
+    }
+}
+
+#[test]
+fn test_instruction_vectors() {
+    check_vectors("instructions.json", instructions());
+    check_vectors("admin_operations.json", admin_operations());
+}
+
+#[test]
+fn test_event_vectors() {
+    check_vectors("events.json", events());
+}
+
+#[test]
+fn test_account_vectors() {
+    check_vectors("accounts.json", accounts());
+}
+
+#[test]
+fn test_return_data_vectors() {
+    check_vectors("return_data.json", return_data());
+}
+
+// Every instruction variant has a vector, so a new one cannot be added without one.
+#[test]
+fn test_vectors_cover_every_instruction() {
+    let mut tags: Vec<u8> = instructions()
+        .iter()
+        .map(|vector| vector.bytes[0])
+        .collect();
+    tags.dedup();
+    assert_eq!(tags, (0..tags.len() as u8).collect::<Vec<_>>());
+    assert!(BridgeInstruction::unpack(&[tags.len() as u8]).is_err());
+}
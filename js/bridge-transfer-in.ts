@@ -1,5 +1,6 @@
 import {
   PublicKey, Transaction, TransactionInstruction, sendAndConfirmTransaction, SystemProgram,
+  SYSVAR_INSTRUCTIONS_PUBKEY,
 } from "@solana/web3.js";
 import {
   TOKEN_PROGRAM_ID,
@@ -36,6 +37,12 @@ const tranferIn = async(bridgeProgramId: PublicKey, bridgeAssociatedAccount: Pub
   );
   const bridgePda = result[0];
 
+  // Releases are counted against the tokens locked for the mint.
+  const [tokenConfigPda] = await PublicKey.findProgramAddress(
+    [Buffer.from('SisuBridgeToken', 'utf8'), mintPubkey.toBuffer()],
+    bridgeProgramId
+  );
+
   const data = new TransferInData({
     nonce: 1,
     amount : [5],
@@ -76,6 +83,16 @@ const tranferIn = async(bridgeProgramId: PublicKey, bridgeAssociatedAccount: Pub
         isSigner: false,
         isWritable: true,
       },
+      {
+        pubkey: tokenConfigPda,
+        isSigner: false,
+        isWritable: true,
+      },
+      {
+        pubkey: SYSVAR_INSTRUCTIONS_PUBKEY,
+        isSigner: false,
+        isWritable: false,
+      },
     ],
     data: Buffer.from(new Uint8Array([2, ...payload])), // 2 is thcd ../se transferOut command
     programId: bridgeProgramId,
@@ -27,6 +27,23 @@ const transferOut = async(bridgeProgramId: PublicKey, tokenPubkey: PublicKey, ow
 
   console.log("Bridge ATA = ", bridgeAta.toString());
 
+  // Each transfer creates a receipt keyed by the bridge's next outbound sequence, which is the
+  // u64 right after the 99 byte header of the bridge state.
+  const bridgeAccount = await connection.getAccountInfo(bridgePda, "confirmed");
+  const sequence = bridgeAccount!.data.readBigUInt64LE(99);
+  const sequenceBytes = Buffer.alloc(8);
+  sequenceBytes.writeBigUInt64LE(sequence);
+  const [receiptPda] = await PublicKey.findProgramAddress(
+    [Buffer.from('SisuBridgeReceipt', 'utf8'), sequenceBytes],
+    bridgeProgramId
+  );
+
+  // Only mints registered and enabled through RegisterToken may be bridged out.
+  const [tokenConfigPda] = await PublicKey.findProgramAddress(
+    [Buffer.from('SisuBridgeToken', 'utf8'), tokenPubkey.toBuffer()],
+    bridgeProgramId
+  );
+
   const data = new TransferOutData({
     amount: new BN(1000e8),
     tokenAddress: tokenPubkey.toString(),
@@ -40,7 +57,7 @@ const transferOut = async(bridgeProgramId: PublicKey, tokenPubkey: PublicKey, ow
       {
         pubkey: feePayer.publicKey,
         isSigner: true,
-        isWritable: false,
+        isWritable: true,
       },
       {
         pubkey: TOKEN_PROGRAM_ID,
@@ -62,6 +79,21 @@ const transferOut = async(bridgeProgramId: PublicKey, tokenPubkey: PublicKey, ow
         isSigner: false,
         isWritable: true,
       },
+      {
+        pubkey: receiptPda,
+        isSigner: false,
+        isWritable: true,
+      },
+      {
+        pubkey: SystemProgram.programId,
+        isSigner: false,
+        isWritable: false,
+      },
+      {
+        pubkey: tokenConfigPda,
+        isSigner: false,
+        isWritable: true,
+      },
     ],
     data: Buffer.from(new Uint8Array([1, ...payload])), // 1 is thcd ../se transferOut command
     programId: bridgeProgramId,